@@ -0,0 +1,573 @@
+use sha2::{Digest, Sha256};
+
+use crate::{
+    opcodes::{OP_CHECKSIG, OP_DUP, OP_EQUAL, OP_EQUALVERIFY, OP_HASH160},
+    AddressError, Network, Result, Script, ScriptBuilder, ScriptType,
+};
+
+const BASE58_ALPHABET: &[u8; 58] = b"123456789ABCDEFGHJKLMNPQRSTUVWXYZabcdefghijkmnopqrstuvwxyz";
+const BECH32_CHARSET: &[u8; 32] = b"qpzry9x8gf2tvdw0s3jn54khce6mua7l";
+const BECH32_CONST: u32 = 1;
+const BECH32M_CONST: u32 = 0x2bc8_30a3;
+/// BIP173 limit on the length of the whole string.
+const BECH32_MAX_LEN: usize = 90;
+
+/// Destination of an output, without the network it is encoded for. The
+/// network only matters for the string form, see [`Address::from_str_in`] and
+/// [`Address::to_string_in`].
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Address {
+    P2pkh([u8; 20]),
+    P2sh([u8; 20]),
+    /// Any BIP141 witness output, the program is 2 to 40 bytes.
+    Segwit {
+        version: u8,
+        program: Vec<u8>,
+    },
+}
+
+impl Address {
+    /// Address of an output script. P2PK, bare multisig, null data and
+    /// nonstandard scripts have none.
+    pub fn from_script(script_pub_key: &[u8]) -> Result<Self> {
+        let script = Script::new(script_pub_key);
+        let address = match script.script_type() {
+            ScriptType::PubKeyHash(hash) => Address::P2pkh(*hash),
+            ScriptType::ScriptHash(hash) => Address::P2sh(*hash),
+            ScriptType::WitnessV0KeyHash(_)
+            | ScriptType::WitnessV0ScriptHash(_)
+            | ScriptType::WitnessV1Taproot(_)
+            | ScriptType::WitnessUnknown { .. } => {
+                let (version, program) = script.witness_program().unwrap();
+                Address::Segwit {
+                    version,
+                    program: program.to_vec(),
+                }
+            }
+            script_type => return Err(AddressError::UnsupportedScript(script_type.name()).into()),
+        };
+        Ok(address)
+    }
+
+    /// The output script paying to this address.
+    pub fn script_pub_key(&self) -> Vec<u8> {
+        match self {
+            Address::P2pkh(hash) => ScriptBuilder::new()
+                .push_opcode(OP_DUP)
+                .push_opcode(OP_HASH160)
+                .push_slice(hash)
+                .push_opcode(OP_EQUALVERIFY)
+                .push_opcode(OP_CHECKSIG)
+                .into_bytes(),
+            Address::P2sh(hash) => ScriptBuilder::new()
+                .push_opcode(OP_HASH160)
+                .push_slice(hash)
+                .push_opcode(OP_EQUAL)
+                .into_bytes(),
+            Address::Segwit { version, program } => ScriptBuilder::new()
+                .push_int(*version as i64)
+                .push_slice(program)
+                .into_bytes(),
+        }
+    }
+
+    /// Parses an address, which has to use the prefixes of `network`. Testnet,
+    /// testnet4 and signet share theirs, so any of them accepts the others'
+    /// addresses.
+    pub fn from_str_in(s: &str, network: Network) -> Result<Self> {
+        let is_bech32 = match s.rfind('1') {
+            Some(separator) => {
+                let hrp = s[..separator].to_ascii_lowercase();
+                Network::ALL
+                    .into_iter()
+                    .any(|network| network.bech32_hrp() == hrp)
+                    || bech32_decode(s).is_ok()
+            }
+            None => false,
+        };
+        if is_bech32 {
+            decode_segwit(s, network)
+        } else {
+            decode_base58(s, network)
+        }
+    }
+
+    /// Base58Check for P2PKH and P2SH, Bech32 for witness v0 and Bech32m for
+    /// later versions, always lowercase.
+    pub fn to_string_in(&self, network: Network) -> String {
+        match self {
+            Address::P2pkh(hash) => base58check_encode(network.p2pkh_prefix(), hash),
+            Address::P2sh(hash) => base58check_encode(network.p2sh_prefix(), hash),
+            Address::Segwit { version, program } => {
+                let mut data = vec![*version];
+                data.extend(convert_bits(program, 8, 5, true).unwrap());
+                let variant = if *version == 0 {
+                    BECH32_CONST
+                } else {
+                    BECH32M_CONST
+                };
+                bech32_encode(network.bech32_hrp(), &data, variant)
+            }
+        }
+    }
+}
+
+fn decode_base58(s: &str, network: Network) -> Result<Address> {
+    let payload = base58check_decode(s)?;
+    let (prefix, hash) = match payload.as_slice() {
+        [prefix, hash @ ..] if hash.len() == 20 => (*prefix, hash.try_into().unwrap()),
+        _ => return Err(AddressError::InvalidLength(payload.len()).into()),
+    };
+    if prefix == network.p2pkh_prefix() {
+        return Ok(Address::P2pkh(hash));
+    }
+    if prefix == network.p2sh_prefix() {
+        return Ok(Address::P2sh(hash));
+    }
+    let is_known = Network::ALL
+        .into_iter()
+        .any(|other| prefix == other.p2pkh_prefix() || prefix == other.p2sh_prefix());
+    if is_known {
+        Err(AddressError::WrongNetwork(network).into())
+    } else {
+        Err(AddressError::UnknownPrefix(prefix).into())
+    }
+}
+
+fn decode_segwit(s: &str, network: Network) -> Result<Address> {
+    let (hrp, data, variant) = bech32_decode(s)?;
+    if hrp != network.bech32_hrp() {
+        let is_known = Network::ALL
+            .into_iter()
+            .any(|other| other.bech32_hrp() == hrp);
+        return Err(if is_known {
+            AddressError::WrongNetwork(network)
+        } else {
+            AddressError::UnknownHrp(hrp)
+        }
+        .into());
+    }
+    let (&version, program) = data.split_first().ok_or(AddressError::InvalidLength(0))?;
+    if version > 16 {
+        return Err(AddressError::InvalidWitnessVersion(version).into());
+    }
+    let program = convert_bits(program, 5, 8, false)?;
+    let length = program.len();
+    if !(2..=40).contains(&length) || (version == 0 && length != 20 && length != 32) {
+        return Err(AddressError::InvalidWitnessProgramLength { version, length }.into());
+    }
+    let expected = if version == 0 {
+        BECH32_CONST
+    } else {
+        BECH32M_CONST
+    };
+    if variant != expected {
+        return Err(AddressError::WrongChecksumVariant(version).into());
+    }
+    Ok(Address::Segwit { version, program })
+}
+
+fn base58check_encode(prefix: u8, hash: &[u8]) -> String {
+    let mut payload = vec![prefix];
+    payload.extend_from_slice(hash);
+    let checksum = Sha256::digest(Sha256::digest(&payload));
+    payload.extend_from_slice(&checksum[..4]);
+
+    // Repeated division of the big-endian number by 58
+    let mut digits: Vec<u8> = Vec::new();
+    for &byte in &payload {
+        let mut carry = byte as u32;
+        for digit in &mut digits {
+            carry += (*digit as u32) << 8;
+            *digit = (carry % 58) as u8;
+            carry /= 58;
+        }
+        while carry > 0 {
+            digits.push((carry % 58) as u8);
+            carry /= 58;
+        }
+    }
+    let zeros = payload.iter().take_while(|&&byte| byte == 0).count();
+    std::iter::repeat_n(b'1', zeros)
+        .chain(
+            digits
+                .iter()
+                .rev()
+                .map(|&digit| BASE58_ALPHABET[digit as usize]),
+        )
+        .map(char::from)
+        .collect()
+}
+
+fn base58check_decode(s: &str) -> std::result::Result<Vec<u8>, AddressError> {
+    let mut bytes: Vec<u8> = Vec::new();
+    for c in s.chars() {
+        let mut carry = BASE58_ALPHABET
+            .iter()
+            .position(|&symbol| symbol as char == c)
+            .ok_or(AddressError::InvalidCharacter(c))? as u32;
+        for byte in &mut bytes {
+            carry += *byte as u32 * 58;
+            *byte = carry as u8;
+            carry >>= 8;
+        }
+        while carry > 0 {
+            bytes.push(carry as u8);
+            carry >>= 8;
+        }
+    }
+    let zeros = s.chars().take_while(|&c| c == '1').count();
+    let mut payload = vec![0; zeros];
+    payload.extend(bytes.iter().rev());
+
+    if payload.len() < 4 {
+        return Err(AddressError::InvalidLength(payload.len()));
+    }
+    let (data, checksum) = payload.split_at(payload.len() - 4);
+    if Sha256::digest(Sha256::digest(data))[..4] != *checksum {
+        return Err(AddressError::InvalidChecksum);
+    }
+    payload.truncate(payload.len() - 4);
+    Ok(payload)
+}
+
+fn bech32_polymod(values: impl IntoIterator<Item = u8>) -> u32 {
+    const GENERATOR: [u32; 5] = [0x3b6a57b2, 0x26508e6d, 0x1ea119fa, 0x3d4233dd, 0x2a1462b3];
+    values.into_iter().fold(1, |checksum, value| {
+        let top = checksum >> 25;
+        let checksum = ((checksum & 0x1ffffff) << 5) ^ value as u32;
+        (0..5)
+            .filter(|i| (top >> i) & 1 == 1)
+            .fold(checksum, |checksum, i| checksum ^ GENERATOR[i])
+    })
+}
+
+fn hrp_expand(hrp: &str) -> Vec<u8> {
+    let mut expanded: Vec<u8> = hrp.bytes().map(|b| b >> 5).collect();
+    expanded.push(0);
+    expanded.extend(hrp.bytes().map(|b| b & 0x1f));
+    expanded
+}
+
+fn bech32_encode(hrp: &str, data: &[u8], variant: u32) -> String {
+    let values = hrp_expand(hrp).into_iter().chain(data.iter().copied());
+    let polymod = bech32_polymod(values.chain([0; 6])) ^ variant;
+    let checksum = (0..6).map(|i| ((polymod >> (5 * (5 - i))) & 0x1f) as u8);
+    let mut s = format!("{}1", hrp);
+    s.extend(
+        data.iter()
+            .copied()
+            .chain(checksum)
+            .map(|value| BECH32_CHARSET[value as usize] as char),
+    );
+    s
+}
+
+/// Splits a Bech32 or Bech32m string into its lowercase human readable part,
+/// the 5-bit values without the checksum, and the checksum constant it matched.
+fn bech32_decode(s: &str) -> std::result::Result<(String, Vec<u8>, u32), AddressError> {
+    if let Some(c) = s.chars().find(|c| !(33..=126).contains(&(*c as u32))) {
+        return Err(AddressError::InvalidCharacter(c));
+    }
+    if s.len() > BECH32_MAX_LEN {
+        return Err(AddressError::InvalidLength(s.len()));
+    }
+    let has_lower = s.bytes().any(|b| b.is_ascii_lowercase());
+    let has_upper = s.bytes().any(|b| b.is_ascii_uppercase());
+    if has_lower && has_upper {
+        return Err(AddressError::MixedCase);
+    }
+    let s = s.to_ascii_lowercase();
+    let separator = s.rfind('1').ok_or(AddressError::InvalidCharacter('1'))?;
+    let (hrp, data) = (&s[..separator], &s[separator + 1..]);
+    if hrp.is_empty() || data.len() < 6 {
+        return Err(AddressError::InvalidLength(s.len()));
+    }
+    let data = data
+        .chars()
+        .map(|c| {
+            BECH32_CHARSET
+                .iter()
+                .position(|&symbol| symbol as char == c)
+                .map(|value| value as u8)
+                .ok_or(AddressError::InvalidCharacter(c))
+        })
+        .collect::<std::result::Result<Vec<u8>, _>>()?;
+    let variant = match bech32_polymod(hrp_expand(hrp).into_iter().chain(data.iter().copied())) {
+        variant @ (BECH32_CONST | BECH32M_CONST) => variant,
+        _ => return Err(AddressError::InvalidChecksum),
+    };
+    Ok((hrp.to_string(), data[..data.len() - 6].to_vec(), variant))
+}
+
+/// Regroups `data` from `from`-bit into `to`-bit values. Without `pad` the
+/// leftover bits have to be fewer than `from` and all zero.
+fn convert_bits(
+    data: &[u8],
+    from: u32,
+    to: u32,
+    pad: bool,
+) -> std::result::Result<Vec<u8>, AddressError> {
+    let mut accumulator = 0u32;
+    let mut bits = 0;
+    let mut converted = Vec::new();
+    let max = (1 << to) - 1;
+    for &value in data {
+        accumulator = (accumulator << from) | value as u32;
+        bits += from;
+        while bits >= to {
+            bits -= to;
+            converted.push(((accumulator >> bits) & max) as u8);
+        }
+    }
+    if pad {
+        if bits > 0 {
+            converted.push(((accumulator << (to - bits)) & max) as u8);
+        }
+    } else if bits >= from || (accumulator << (to - bits)) & max != 0 {
+        return Err(AddressError::InvalidPadding);
+    }
+    Ok(converted)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::BitcoinError;
+
+    fn address_error(s: &str, network: Network) -> AddressError {
+        match Address::from_str_in(s, network) {
+            Err(BitcoinError::InvalidAddress(error)) => error,
+            other => panic!("{}: expected an address error, got {:?}", s, other),
+        }
+    }
+
+    #[test]
+    fn test_base58() {
+        for (address, script, network) in [
+            (
+                "132F25rTsvBdp9JzLLBHP5mvGY66i1xdiM",
+                "76a914162c5ea71c0b23f5b9022ef047c4a86470a5b07088ac",
+                Network::Bitcoin,
+            ),
+            (
+                "33iFwdLuRpW1uK1RTRqsoi8rR4NpDzk66k",
+                "a914162c5ea71c0b23f5b9022ef047c4a86470a5b07087",
+                Network::Bitcoin,
+            ),
+            (
+                "1111111111111111111114oLvT2",
+                "76a914000000000000000000000000000000000000000088ac",
+                Network::Bitcoin,
+            ),
+        ] {
+            let parsed = Address::from_str_in(address, network).unwrap();
+            assert_eq!(hex::encode(parsed.script_pub_key()), script);
+            let from_script = Address::from_script(&hex::decode(script).unwrap()).unwrap();
+            assert_eq!(from_script, parsed);
+            assert_eq!(parsed.to_string_in(network), address);
+        }
+
+        let testnet = "2N3zXjbwdTcPsJiy8sUK9FhWJhqQCxA8Jjr";
+        let parsed = Address::from_str_in(testnet, Network::Testnet).unwrap();
+        assert!(matches!(parsed, Address::P2sh(_)));
+        assert_eq!(parsed.to_string_in(Network::Signet), testnet);
+    }
+
+    #[test]
+    fn test_bip173_350_valid() {
+        for (address, script, network) in [
+            (
+                "BC1QW508D6QEJXTDG4Y5R3ZARVARY0C5XW7KV8F3T4",
+                "0014751e76e8199196d454941c45d1b3a323f1433bd6",
+                Network::Bitcoin,
+            ),
+            (
+                "tb1qrp33g0q5c5txsp9arysrx4k6zdkfs4nce4xj0gdcccefvpysxf3q0sl5k7",
+                "00201863143c14c5166804bd19203356da136c985678cd4d27a1b8c6329604903262",
+                Network::Testnet,
+            ),
+            (
+                "bc1pw508d6qejxtdg4y5r3zarvary0c5xw7kw508d6qejxtdg4y5r3zarvary0c5xw7kt5nd6y",
+                "5128751e76e8199196d454941c45d1b3a323f1433bd6751e76e8199196d454941c45d1b3a323f1433bd6",
+                Network::Bitcoin,
+            ),
+            ("BC1SW50QGDZ25J", "6002751e", Network::Bitcoin),
+            (
+                "bc1zw508d6qejxtdg4y5r3zarvaryvaxxpcs",
+                "5210751e76e8199196d454941c45d1b3a323",
+                Network::Bitcoin,
+            ),
+            (
+                "tb1qqqqqp399et2xygdj5xreqhjjvcmzhxw4aywxecjdzew6hylgvsesrxh6hy",
+                "0020000000c4a5cad46221b2a187905e5266362b99d5e91c6ce24d165dab93e86433",
+                Network::Signet,
+            ),
+            (
+                "tb1pqqqqp399et2xygdj5xreqhjjvcmzhxw4aywxecjdzew6hylgvsesf3hn0c",
+                "5120000000c4a5cad46221b2a187905e5266362b99d5e91c6ce24d165dab93e86433",
+                Network::Testnet4,
+            ),
+            (
+                "bc1p0xlxvlhemja6c4dqv22uapctqupfhlxm9h8z3k2e72q4k9hcz7vqzk5jj0",
+                "512079be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798",
+                Network::Bitcoin,
+            ),
+        ] {
+            let parsed = Address::from_str_in(address, network).unwrap();
+            assert_eq!(hex::encode(parsed.script_pub_key()), script, "{}", address);
+            let from_script = Address::from_script(&hex::decode(script).unwrap()).unwrap();
+            assert_eq!(from_script, parsed);
+            assert_eq!(parsed.to_string_in(network), address.to_ascii_lowercase());
+        }
+    }
+
+    #[test]
+    fn test_bip173_350_invalid() {
+        use AddressError::*;
+        for (address, expected) in [
+            (
+                "tc1p0xlxvlhemja6c4dqv22uapctqupfhlxm9h8z3k2e72q4k9hcz7vq5zuyut",
+                UnknownHrp("tc".to_string()),
+            ),
+            (
+                "bc1p0xlxvlhemja6c4dqv22uapctqupfhlxm9h8z3k2e72q4k9hcz7vqh2y7hd",
+                WrongChecksumVariant(1),
+            ),
+            (
+                "BC1S0XLXVLHEMJA6C4DQV22UAPCTQUPFHLXM9H8Z3K2E72Q4K9HCZ7VQ54WELL",
+                WrongChecksumVariant(16),
+            ),
+            (
+                "bc1qw508d6qejxtdg4y5r3zarvary0c5xw7kemeawh",
+                WrongChecksumVariant(0),
+            ),
+            (
+                "bc1p38j9r5y49hruaue7wxjce0updqjuyyx0kh56v8s25huc6995vvpql3jow4",
+                InvalidCharacter('o'),
+            ),
+            (
+                "BC130XLXVLHEMJA6C4DQV22UAPCTQUPFHLXM9H8Z3K2E72Q4K9HCZ7VQ7ZWS8R",
+                InvalidWitnessVersion(17),
+            ),
+            (
+                "bc1pw5dgrnzv",
+                InvalidWitnessProgramLength {
+                    version: 1,
+                    length: 1,
+                },
+            ),
+            (
+                "bc1p0xlxvlhemja6c4dqv22uapctqupfhlxm9h8z3k2e72q4k9hcz7v8n0nx0muaewav253zgeav",
+                InvalidWitnessProgramLength {
+                    version: 1,
+                    length: 41,
+                },
+            ),
+            (
+                "BC1QR508D6QEJXTDG4Y5R3ZARVARYV98GJ9P",
+                InvalidWitnessProgramLength {
+                    version: 0,
+                    length: 16,
+                },
+            ),
+            (
+                "bc1p0xlxvlhemja6c4dqv22uapctqupfhlxm9h8z3k2e72q4k9hcz7v07qwwzcrf",
+                InvalidPadding,
+            ),
+            ("bc1gmk9yu", InvalidLength(0)),
+            (
+                "bc1qw508d6qejxtdg4y5r3zarvary0c5xw7kv8f3t5",
+                InvalidChecksum,
+            ),
+            (
+                "bc10w508d6qejxtdg4y5r3zarvary0c5xw7kw508d6qejxtdg4y5r3zarvary0c5xw7kw5rljs90",
+                InvalidWitnessProgramLength {
+                    version: 15,
+                    length: 41,
+                },
+            ),
+        ] {
+            assert_eq!(
+                address_error(address, Network::Bitcoin),
+                expected,
+                "{}",
+                address
+            );
+        }
+
+        for (address, network) in [
+            (
+                "tb1p0xlxvlhemja6c4dqv22uapctqupfhlxm9h8z3k2e72q4k9hcz7vq47Zagq",
+                Network::Testnet,
+            ),
+            (
+                "tb1p0xlxvlhemja6c4dqv22uapctqupfhlxm9h8z3k2e72q4k9hcz7vpggkg4j",
+                Network::Testnet,
+            ),
+            (
+                "tb1z0xlxvlhemja6c4dqv22uapctqupfhlxm9h8z3k2e72q4k9hcz7vqglt7rf",
+                Network::Testnet,
+            ),
+            ("bc1zw508d6qejxtdg4y5r3zarvaryvg6kdaj", Network::Bitcoin),
+        ] {
+            assert!(
+                Address::from_str_in(address, network).is_err(),
+                "{}",
+                address
+            );
+        }
+    }
+
+    #[test]
+    fn test_wrong_network_and_checksum() {
+        assert_eq!(
+            address_error("132F25rTsvBdp9JzLLBHP5mvGY66i1xdiM", Network::Testnet),
+            AddressError::WrongNetwork(Network::Testnet)
+        );
+        assert_eq!(
+            address_error(
+                "tb1qrp33g0q5c5txsp9arysrx4k6zdkfs4nce4xj0gdcccefvpysxf3q0sl5k7",
+                Network::Regtest
+            ),
+            AddressError::WrongNetwork(Network::Regtest)
+        );
+        assert_eq!(
+            address_error("132F25rTsvBdp9JzLLBHP5mvGY66i1xdiN", Network::Bitcoin),
+            AddressError::InvalidChecksum
+        );
+        assert_eq!(
+            address_error("132F25rTsvBdp9JzLLBHP5mvGY66i1xdi0", Network::Bitcoin),
+            AddressError::InvalidCharacter('0')
+        );
+
+        let address = Address::Segwit {
+            version: 0,
+            program: vec![0xab; 20],
+        };
+        let regtest = address.to_string_in(Network::Regtest);
+        assert!(regtest.starts_with("bcrt1q"));
+        assert_eq!(
+            Address::from_str_in(&regtest, Network::Regtest).unwrap(),
+            address
+        );
+        assert_eq!(
+            address_error(&regtest, Network::Bitcoin),
+            AddressError::WrongNetwork(Network::Bitcoin)
+        );
+    }
+
+    #[test]
+    fn test_unsupported_scripts() {
+        let transaction =
+            crate::Transaction::deserialize(&hex::decode(crate::TRANSACTION_SERIALIZED).unwrap())
+                .unwrap();
+        assert!(matches!(
+            Address::from_script(&transaction.outputs[0].script_pub_key),
+            Err(BitcoinError::InvalidAddress(
+                AddressError::UnsupportedScript("pubkey")
+            ))
+        ));
+        assert!(Address::from_script(&[crate::opcodes::OP_TRUE]).is_err());
+    }
+}
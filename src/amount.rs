@@ -0,0 +1,245 @@
+use std::{fmt, str::FromStr};
+
+use crate::{BitcoinError, Result};
+
+/// Unit an amount is written in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Denomination {
+    Bitcoin,
+    Satoshi,
+}
+
+impl fmt::Display for Denomination {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Denomination::Bitcoin => "BTC",
+            Denomination::Satoshi => "sat",
+        })
+    }
+}
+
+impl FromStr for Denomination {
+    type Err = BitcoinError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "BTC" | "btc" => Ok(Denomination::Bitcoin),
+            "sat" | "sats" => Ok(Denomination::Satoshi),
+            _ => Err(BitcoinError::InvalidAmount(format!(
+                "unknown denomination {:?}",
+                s
+            ))),
+        }
+    }
+}
+
+/// Amount of satoshis. Any `u64` can be represented since that is what the
+/// wire format allows, but parsing rejects anything above [`Amount::MAX_MONEY`]
+/// and there are no unchecked arithmetic operators.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(transparent)
+)]
+pub struct Amount(u64);
+
+impl Amount {
+    pub const ZERO: Self = Self(0);
+    pub const ONE_SAT: Self = Self(1);
+    pub const ONE_BTC: Self = Self(100_000_000);
+    /// 21 million BTC, Core's `MAX_MONEY`. No valid output or output sum exceeds it.
+    pub const MAX_MONEY: Self = Self(21_000_000 * 100_000_000);
+
+    pub const fn from_sat(sat: u64) -> Self {
+        Self(sat)
+    }
+
+    /// Panics if the amount does not fit in a `u64` of satoshis.
+    pub const fn from_int_btc(btc: u64) -> Self {
+        Self(btc * Self::ONE_BTC.0)
+    }
+
+    pub const fn to_sat(self) -> u64 {
+        self.0
+    }
+
+    /// Lossy, for display and JSON only.
+    pub fn to_btc(self) -> f64 {
+        self.0 as f64 / Self::ONE_BTC.0 as f64
+    }
+
+    /// Core's `MoneyRange`.
+    pub const fn is_money_range(self) -> bool {
+        self.0 <= Self::MAX_MONEY.0
+    }
+
+    pub fn checked_add(self, rhs: Self) -> Option<Self> {
+        self.0.checked_add(rhs.0).map(Self)
+    }
+
+    pub fn checked_sub(self, rhs: Self) -> Option<Self> {
+        self.0.checked_sub(rhs.0).map(Self)
+    }
+
+    pub fn checked_mul(self, rhs: u64) -> Option<Self> {
+        self.0.checked_mul(rhs).map(Self)
+    }
+
+    /// Sum of `amounts`, `None` if it overflows.
+    pub fn checked_sum<I: IntoIterator<Item = Amount>>(amounts: I) -> Option<Self> {
+        amounts
+            .into_iter()
+            .try_fold(Self::ZERO, |sum, amount| sum.checked_add(amount))
+    }
+
+    /// Parses a plain number such as `0.001` in BTC or `100000` in satoshis.
+    pub fn from_str_in(s: &str, denomination: Denomination) -> Result<Self> {
+        let invalid = |reason: &str| BitcoinError::InvalidAmount(format!("{:?}: {}", s, reason));
+        let (integer, fraction) = match denomination {
+            Denomination::Satoshi => (s, ""),
+            Denomination::Bitcoin => s.split_once('.').unwrap_or((s, "")),
+        };
+        let is_digits = |part: &str| part.bytes().all(|b| b.is_ascii_digit());
+        if integer.is_empty() || s.ends_with('.') || !is_digits(integer) || !is_digits(fraction) {
+            return Err(invalid("not a non-negative decimal number"));
+        }
+        if fraction.len() > 8 {
+            return Err(invalid("more than 8 decimal places"));
+        }
+
+        let out_of_range = || invalid("exceeds 21000000 BTC");
+        let integer: u64 = integer.parse().map_err(|_| out_of_range())?;
+        let sat = match denomination {
+            Denomination::Satoshi => integer,
+            Denomination::Bitcoin => {
+                let fraction: u64 = format!("{:0<8}", fraction).parse().unwrap();
+                integer
+                    .checked_mul(Self::ONE_BTC.0)
+                    .and_then(|sat| sat.checked_add(fraction))
+                    .ok_or_else(out_of_range)?
+            }
+        };
+        let amount = Self(sat);
+        if !amount.is_money_range() {
+            return Err(out_of_range());
+        }
+        Ok(amount)
+    }
+
+    /// Plain number without unit, BTC always with 8 decimal places.
+    pub fn to_string_in(self, denomination: Denomination) -> String {
+        match denomination {
+            Denomination::Bitcoin => format!(
+                "{}.{:08}",
+                self.0 / Self::ONE_BTC.0,
+                self.0 % Self::ONE_BTC.0
+            ),
+            Denomination::Satoshi => self.0.to_string(),
+        }
+    }
+}
+
+/// Formats as BTC with the unit, e.g. `50.00000000 BTC`.
+impl fmt::Display for Amount {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} BTC", self.to_string_in(Denomination::Bitcoin))
+    }
+}
+
+/// Parses a number followed by its unit, e.g. `0.5 BTC` or `1000 sat`.
+impl FromStr for Amount {
+    type Err = BitcoinError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let (number, denomination) = s
+            .trim()
+            .split_once(' ')
+            .ok_or_else(|| BitcoinError::InvalidAmount(format!("{:?}: missing denomination", s)))?;
+        Self::from_str_in(number, denomination.trim_start().parse()?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let parse = |s: &str| Amount::from_str_in(s, Denomination::Bitcoin);
+        assert_eq!(parse("50").unwrap(), Amount::from_sat(5_000_000_000));
+        assert_eq!(parse("0.00000001").unwrap(), Amount::ONE_SAT);
+        assert_eq!(parse("1.5").unwrap(), Amount::from_sat(150_000_000));
+        assert_eq!(parse("21000000").unwrap(), Amount::MAX_MONEY);
+        assert_eq!(
+            Amount::from_str_in("2100000000000000", Denomination::Satoshi).unwrap(),
+            Amount::MAX_MONEY
+        );
+        for invalid in [
+            "",
+            ".5",
+            "1.",
+            "-1",
+            "+1",
+            "1e8",
+            "0.000000001",
+            "21000000.00000001",
+            "18446744073709551616",
+        ] {
+            assert!(
+                matches!(parse(invalid), Err(BitcoinError::InvalidAmount(_))),
+                "{:?}",
+                invalid
+            );
+        }
+        assert!(Amount::from_str_in("1.5", Denomination::Satoshi).is_err());
+        assert!(Amount::from_str_in("2100000000000001", Denomination::Satoshi).is_err());
+    }
+
+    #[test]
+    fn test_display_and_from_str() {
+        assert_eq!(Amount::from_int_btc(50).to_string(), "50.00000000 BTC");
+        assert_eq!(
+            Amount::from_sat(12_345).to_string_in(Denomination::Bitcoin),
+            "0.00012345"
+        );
+        assert_eq!(
+            Amount::from_sat(12_345).to_string_in(Denomination::Satoshi),
+            "12345"
+        );
+        assert_eq!(
+            "0.5 BTC".parse::<Amount>().unwrap(),
+            Amount::from_sat(50_000_000)
+        );
+        assert_eq!(
+            "1000 sat".parse::<Amount>().unwrap(),
+            Amount::from_sat(1000)
+        );
+        let amount = Amount::from_sat(2_099_999_997_690_000);
+        assert_eq!(amount.to_string().parse::<Amount>().unwrap(), amount);
+        assert!("1000".parse::<Amount>().is_err());
+        assert!("1000 mBTC".parse::<Amount>().is_err());
+    }
+
+    #[test]
+    fn test_checked_arithmetic() {
+        let max = Amount::from_sat(u64::MAX);
+        assert_eq!(max.checked_add(Amount::ONE_SAT), None);
+        assert_eq!(Amount::ZERO.checked_sub(Amount::ONE_SAT), None);
+        assert_eq!(max.checked_mul(2), None);
+        assert_eq!(
+            Amount::ONE_BTC.checked_sub(Amount::ONE_SAT),
+            Some(Amount::from_sat(99_999_999))
+        );
+        assert_eq!(
+            Amount::checked_sum([Amount::ONE_BTC, Amount::ONE_BTC]),
+            Some(Amount::from_int_btc(2))
+        );
+        assert_eq!(Amount::checked_sum([max, Amount::ONE_SAT]), None);
+        assert!(Amount::MAX_MONEY.is_money_range());
+        assert!(!Amount::MAX_MONEY
+            .checked_add(Amount::ONE_SAT)
+            .unwrap()
+            .is_money_range());
+    }
+}
@@ -1,11 +1,23 @@
+use std::io::{self, Read, Write};
+
+use sha2::{Digest, Sha256};
+
 use crate::{
     block_header::BlockHeader,
-    transaction::Transaction,
-    utils::{self, encode_varint},
-    BitcoinError, Result,
+    decoder::Decoder,
+    encode::{CompactSize, Decodable, Encodable},
+    merkle_root::MerkleRoot,
+    transaction::{Transaction, TransactionOutput, MIN_TRANSACTION_SIZE},
+    utils::varint_size,
+    Amount, BitcoinError, Result, TxMerkleNode, Txid, WitnessMerkleNode, Wtxid,
+    MAX_BLOCK_SIGOPS_COST, MAX_BLOCK_WEIGHT, WITNESS_SCALE_FACTOR,
 };
 
+/// OP_RETURN, push of 36 bytes and the BIP141 commitment tag `aa21a9ed`.
+const WITNESS_COMMITMENT_HEADER: [u8; 6] = [0x6a, 0x24, 0xaa, 0x21, 0xa9, 0xed];
+
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Block {
     pub block_header: BlockHeader,
     pub transactions: Vec<Transaction>,
@@ -13,45 +25,222 @@ pub struct Block {
 
 impl Block {
     pub fn deserialize(payload: &[u8]) -> Result<Self> {
-        if payload.len() < 80 {
-            return Err(BitcoinError::InvalidPayload(
-                "Invalid block length".to_string(),
+        Self::consensus_decode(&mut Decoder::from_slice(payload))
+    }
+
+    pub fn serialize(&self) -> Vec<u8> {
+        crate::encode::serialize(self)
+    }
+
+    pub fn stripped_size(&self) -> usize {
+        80 + varint_size(self.transactions.len() as u64)
+            + self
+                .transactions
+                .iter()
+                .map(|tx| tx.stripped_size())
+                .sum::<usize>()
+    }
+
+    pub fn total_size(&self) -> usize {
+        80 + varint_size(self.transactions.len() as u64)
+            + self
+                .transactions
+                .iter()
+                .map(|tx| tx.total_size())
+                .sum::<usize>()
+    }
+
+    pub fn weight(&self) -> usize {
+        self.stripped_size() * (WITNESS_SCALE_FACTOR - 1) + self.total_size()
+    }
+
+    pub fn vsize(&self) -> usize {
+        self.weight().div_ceil(WITNESS_SCALE_FACTOR)
+    }
+
+    /// Sum of every transaction's outputs, each transaction checked with
+    /// [`Transaction::total_output_value`]. The block total itself may exceed
+    /// `MAX_MONEY` when transactions spend outputs created earlier in the block.
+    pub fn total_output_value(&self) -> Result<Amount> {
+        let mut total = Amount::ZERO;
+        for (i, transaction) in self.transactions.iter().enumerate() {
+            let value = transaction
+                .total_output_value()
+                .map_err(|e| BitcoinError::InvalidBlock(format!("transaction {}: {}", i, e)))?;
+            total = total.checked_add(value).ok_or_else(|| {
+                BitcoinError::InvalidBlock("total output value overflows".to_string())
+            })?;
+        }
+        Ok(total)
+    }
+
+    pub fn merkle_root(&self) -> TxMerkleNode {
+        let txids: Vec<Txid> = self.transactions.iter().map(|tx| tx.txid()).collect();
+        MerkleRoot::calculate(&txids)
+    }
+
+    /// Merkle root of the wtxids, with the coinbase wtxid replaced by zeros.
+    pub fn witness_root(&self) -> WitnessMerkleNode {
+        let wtxids: Vec<Wtxid> = self
+            .transactions
+            .iter()
+            .enumerate()
+            .map(|(i, tx)| {
+                if i == 0 {
+                    Wtxid::all_zeros()
+                } else {
+                    tx.wtxid()
+                }
+            })
+            .collect();
+        MerkleRoot::calculate_witness(&wtxids)
+    }
+
+    pub fn witness_commitment(
+        witness_root: &WitnessMerkleNode,
+        witness_reserved_value: &[u8],
+    ) -> [u8; 32] {
+        let mut hasher = Sha256::new();
+        hasher.update(witness_root.as_byte_array());
+        hasher.update(witness_reserved_value);
+        Sha256::digest(hasher.finalize()).into()
+    }
+
+    /// Index of the coinbase output carrying the witness commitment. When several
+    /// outputs match, the one with the highest index is used.
+    pub fn witness_commitment_index(&self) -> Option<usize> {
+        let coinbase = self.transactions.first()?;
+        coinbase.outputs.iter().rposition(|output| {
+            output.script_pub_key.len() >= 38
+                && output.script_pub_key[..6] == WITNESS_COMMITMENT_HEADER
+        })
+    }
+
+    /// Adds the witness reserved value to the coinbase input and commits to the
+    /// current witness root, replacing an existing commitment output or
+    /// appending a new one. This changes the coinbase txid, so callers must
+    /// recompute `merkle_root_hash` afterwards.
+    pub fn add_witness_commitment(&mut self, witness_reserved_value: [u8; 32]) -> Result<()> {
+        let witness_root = self.witness_root();
+        let commitment_index = self.witness_commitment_index();
+        let coinbase = self.transactions.first_mut().ok_or_else(|| {
+            BitcoinError::InvalidBlock("Missing coinbase transaction".to_string())
+        })?;
+        let coinbase_input = coinbase.inputs.first_mut().ok_or_else(|| {
+            BitcoinError::InvalidBlock("Coinbase transaction has no inputs".to_string())
+        })?;
+        coinbase_input.witness = vec![witness_reserved_value.to_vec()];
+
+        let commitment = Self::witness_commitment(&witness_root, &witness_reserved_value);
+        let mut script_pub_key = WITNESS_COMMITMENT_HEADER.to_vec();
+        script_pub_key.extend_from_slice(&commitment);
+        let output = TransactionOutput {
+            value: Amount::ZERO,
+            script_pub_key,
+        };
+        match commitment_index {
+            Some(index) => coinbase.outputs[index] = output,
+            None => coinbase.outputs.push(output),
+        }
+        Ok(())
+    }
+
+    pub fn check_merkle_root(&self) -> Result<()> {
+        if self.merkle_root() != self.block_header.merkle_root_hash {
+            return Err(BitcoinError::InvalidBlock(
+                "Merkle root mismatch".to_string(),
             ));
         }
-        let block_header = BlockHeader::deserialize(&payload[0..80])?;
-        let (transaction_count, offset_count) =
-            utils::decode_varint(payload[80..].try_into().unwrap()).map_err(|_| {
-                BitcoinError::InvalidPayload("Invalid transaction count".to_string())
-            })?;
+        Ok(())
+    }
 
-        let mut transactions = Vec::new();
-        let mut offset = 80 + offset_count;
+    pub fn check_witness_commitment(&self) -> Result<()> {
+        let Some(index) = self.witness_commitment_index() else {
+            if self.transactions.iter().any(|tx| tx.has_witness()) {
+                return Err(BitcoinError::InvalidBlock(
+                    "Unexpected witness data without commitment".to_string(),
+                ));
+            }
+            return Ok(());
+        };
+        let coinbase = &self.transactions[0];
+        let witness_reserved_value = match coinbase.inputs.first().map(|input| &input.witness) {
+            Some(witness) if witness.len() == 1 && witness[0].len() == 32 => &witness[0],
+            _ => {
+                return Err(BitcoinError::InvalidBlock(
+                    "Invalid coinbase witness reserved value".to_string(),
+                ))
+            }
+        };
+        let commitment = Self::witness_commitment(&self.witness_root(), witness_reserved_value);
+        if coinbase.outputs[index].script_pub_key[6..38] != commitment {
+            return Err(BitcoinError::InvalidBlock(
+                "Witness commitment mismatch".to_string(),
+            ));
+        }
+        Ok(())
+    }
 
-        while transaction_count > transactions.len() as u64 {
-            let transaction = Transaction::deserialize(&payload[offset..])?;
-            offset += transaction.size();
-            transactions.push(transaction);
+    pub fn validate(&self) -> Result<()> {
+        if self.transactions.is_empty() {
+            return Err(BitcoinError::InvalidBlock(
+                "Missing coinbase transaction".to_string(),
+            ));
         }
-        Ok(Self {
-            block_header,
-            transactions,
-        })
+        if self.weight() > MAX_BLOCK_WEIGHT {
+            return Err(BitcoinError::InvalidBlock(format!(
+                "Block weight {} exceeds {}",
+                self.weight(),
+                MAX_BLOCK_WEIGHT
+            )));
+        }
+        // The part of the sigop cost known without the spent outputs
+        let legacy_sigop_cost = self.legacy_sigop_count() * WITNESS_SCALE_FACTOR;
+        if legacy_sigop_cost > MAX_BLOCK_SIGOPS_COST {
+            return Err(BitcoinError::InvalidBlock(format!(
+                "Legacy sigop cost {} exceeds {}",
+                legacy_sigop_cost, MAX_BLOCK_SIGOPS_COST
+            )));
+        }
+        self.check_merkle_root()?;
+        self.check_witness_commitment()
     }
+}
 
-    pub fn serialize(&self) -> Vec<u8> {
-        let mut payload = Vec::new();
-        payload.extend_from_slice(&self.block_header.serialize());
-        payload.extend_from_slice(&encode_varint(self.transactions.len() as u64));
+impl Encodable for Block {
+    fn consensus_encode<W: Write + ?Sized>(&self, writer: &mut W) -> io::Result<usize> {
+        let mut len = self.block_header.consensus_encode(writer)?;
+        len += CompactSize(self.transactions.len() as u64).consensus_encode(writer)?;
         for transaction in &self.transactions {
-            payload.extend_from_slice(&transaction.serialize());
+            len += transaction.consensus_encode(writer)?;
         }
-        payload
+        Ok(len)
+    }
+
+    fn encoded_size(&self) -> usize {
+        self.total_size()
+    }
+}
+
+impl Decodable for Block {
+    fn consensus_decode<R: Read>(decoder: &mut Decoder<R>) -> Result<Self> {
+        let block_header = BlockHeader::consensus_decode(decoder)?;
+        let transaction_count = decoder.read_count("transaction count", MIN_TRANSACTION_SIZE)?;
+        let mut transactions = Vec::new();
+        for _ in 0..transaction_count {
+            transactions.push(Transaction::consensus_decode(decoder)?);
+        }
+        Ok(Self {
+            block_header,
+            transactions,
+        })
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::BlockHash;
 
     #[test]
     fn test_block_deserialize() {
@@ -65,21 +254,15 @@ mod tests {
         assert_eq!(block.block_header.bits, 486604799);
         assert_eq!(block.block_header.nonce, 2573394689);
         assert_eq!(
-            hex::encode({
-                let mut hash = block.block_header.previous_block_hash.clone();
-                hash.reverse();
-                hash
-            }),
+            block.block_header.previous_block_hash.to_string(),
             "000000000019d6689c085ae165831e934ff763ae46a2a6c172b3f1b60a8ce26f"
         );
         assert_eq!(
-            hex::encode({
-                let mut merkle = block.block_header.merkle_root_hash.clone();
-                merkle.reverse();
-                merkle
-            }),
+            block.block_header.merkle_root_hash.to_string(),
             "0e3e2357e806b6cdb1f70b54c3a3a17b6714ee1f0e68bebb44a74b1efd512098"
         );
+        assert_eq!(block.total_size(), payload_block.len());
+        assert_eq!(block.weight(), payload_block.len() * 4);
         assert_eq!(block.transactions.len(), 1);
         assert_eq!(block.transactions[0].serialize(), payload_block[81..]);
         assert_eq!(
@@ -87,4 +270,139 @@ mod tests {
             transaction_hex
         );
     }
+
+    fn segwit_block() -> Block {
+        let coinbase = Transaction::deserialize(&hex::decode("01000000010000000000000000000000000000000000000000000000000000000000000000ffffffff0704ffff001d0102ffffffff0100f2052a01000000434104d46c4968bde02899d2aa0963367c7a6ce34eec332b32e42e5f3407e052d64ac625da6f0718e7b302140434bd725706957c092db53805b821a85b23a7ac61725bac00000000").unwrap()).unwrap();
+        let transaction = Transaction::deserialize(&hex::decode("02000000000101595895ea20179de87052b4046dfe6fd515860505d6511a9004cf12a1f93cac7c0100000000ffffffff01deb807000000000017a9140f3444e271620c736808aa7b33e370bd87cb5a078702483045022100fb60dad8df4af2841adc0346638c16d0b8035f5e3f3753b88db122e70c79f9370220756e6633b17fd2710e626347d28d60b0a2d6cbb41de51740644b9fb3ba7751040121028fa937ca8cba2197a37c007176ed8941055d3bcb8627d085e94553e62f057dcc00000000").unwrap()).unwrap();
+
+        let mut block = Block {
+            block_header: BlockHeader {
+                version: 0x20000000,
+                previous_block_hash: BlockHash::all_zeros(),
+                merkle_root_hash: TxMerkleNode::all_zeros(),
+                timestamp: 0,
+                bits: 0x1d00ffff,
+                nonce: 0,
+            },
+            transactions: vec![coinbase, transaction],
+        };
+        block.add_witness_commitment([0; 32]).unwrap();
+        block.block_header.merkle_root_hash = block.merkle_root();
+        block
+    }
+
+    #[test]
+    fn test_witness_commitment() {
+        let block = segwit_block();
+        let coinbase = &block.transactions[0];
+
+        assert_eq!(block.witness_commitment_index(), Some(1));
+        assert_eq!(coinbase.inputs[0].witness, vec![vec![0u8; 32]]);
+
+        let script_pub_key = &coinbase.outputs[1].script_pub_key;
+        assert_eq!(script_pub_key.len(), 38);
+        assert_eq!(hex::encode(&script_pub_key[..6]), "6a24aa21a9ed");
+        assert_eq!(coinbase.outputs[1].value, Amount::ZERO);
+
+        let mut expected = block.witness_root().as_byte_array().to_vec();
+        expected.extend_from_slice(&[0u8; 32]);
+        let expected = Sha256::digest(Sha256::digest(&expected));
+        assert_eq!(script_pub_key[6..], expected[..]);
+
+        block.validate().unwrap();
+
+        // A template that changes is recommitted in place
+        let mut updated = block.clone();
+        updated.transactions.pop();
+        updated.add_witness_commitment([1; 32]).unwrap();
+        updated.block_header.merkle_root_hash = updated.merkle_root();
+        let coinbase = &updated.transactions[0];
+        assert_eq!(coinbase.outputs.len(), 2);
+        assert_eq!(updated.witness_commitment_index(), Some(1));
+        assert_ne!(
+            coinbase.outputs[1].script_pub_key,
+            block.transactions[0].outputs[1].script_pub_key
+        );
+        updated.check_witness_commitment().unwrap();
+        updated.validate().unwrap();
+
+        let payload_block = block.serialize();
+        Block::deserialize(&payload_block)
+            .unwrap()
+            .validate()
+            .unwrap();
+    }
+
+    #[test]
+    fn test_block_weight() {
+        let block = segwit_block();
+        let payload_block = block.serialize();
+        let stripped_size = 80
+            + 1
+            + block.transactions[0].serialize_without_witness().len()
+            + block.transactions[1].serialize_without_witness().len();
+
+        assert_eq!(block.total_size(), payload_block.len());
+        assert_eq!(block.stripped_size(), stripped_size);
+        assert_eq!(block.weight(), stripped_size * 3 + payload_block.len());
+        assert_eq!(
+            block.weight(),
+            80 * 4
+                + 4
+                + block
+                    .transactions
+                    .iter()
+                    .map(|tx| tx.weight())
+                    .sum::<usize>()
+        );
+        assert_eq!(block.vsize(), block.weight().div_ceil(4));
+    }
+
+    #[test]
+    fn test_total_output_value() {
+        let mut block = segwit_block();
+        assert_eq!(
+            block.total_output_value().unwrap(),
+            Amount::from_sat(5_000_506_078)
+        );
+
+        block.transactions[1].outputs[0].value = Amount::MAX_MONEY;
+        assert_eq!(
+            block.total_output_value().unwrap(),
+            Amount::from_sat(Amount::MAX_MONEY.to_sat() + 5_000_000_000)
+        );
+
+        block.transactions[1].outputs[0].value = Amount::from_sat(u64::MAX);
+        assert!(matches!(
+            block.total_output_value(),
+            Err(BitcoinError::InvalidBlock(_))
+        ));
+    }
+
+    #[test]
+    fn test_witness_root_ignores_coinbase_wtxid() {
+        let block = segwit_block();
+        let wtxids = [Wtxid::all_zeros(), block.transactions[1].wtxid()];
+        let expected = MerkleRoot::calculate_witness(&wtxids);
+        assert_eq!(block.witness_root(), expected);
+    }
+
+    #[test]
+    fn test_invalid_witness_commitment() {
+        let mut block = segwit_block();
+        block.transactions[1].inputs[0].witness[0][4] ^= 0x01;
+        assert!(block.check_witness_commitment().is_err());
+
+        let mut block = segwit_block();
+        block.transactions[0].inputs[0].witness.clear();
+        assert!(block.check_witness_commitment().is_err());
+
+        let mut block = segwit_block();
+        block.transactions[0].outputs.pop();
+        assert!(block.check_witness_commitment().is_err());
+
+        let mut block = segwit_block();
+        block.block_header.merkle_root_hash = TxMerkleNode::all_zeros();
+        assert!(block.validate().is_err());
+    }
 }
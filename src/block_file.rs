@@ -0,0 +1,314 @@
+use std::{
+    fs::{self, File},
+    io::{self, BufReader, Read},
+    path::{Path, PathBuf},
+};
+
+use crate::{BitcoinError, Block, Result, MAX_BLOCK_SERIALIZED_SIZE};
+
+pub const MAINNET_MAGIC: [u8; 4] = [0xf9, 0xbe, 0xb4, 0xd9];
+pub const TESTNET_MAGIC: [u8; 4] = [0x0b, 0x11, 0x09, 0x07];
+pub const TESTNET4_MAGIC: [u8; 4] = [0x1c, 0x16, 0x3f, 0x28];
+pub const SIGNET_MAGIC: [u8; 4] = [0x0a, 0x03, 0xcf, 0x40];
+pub const REGTEST_MAGIC: [u8; 4] = [0xfa, 0xbf, 0xb5, 0xda];
+
+/// Undoes Bitcoin Core's block file obfuscation: every byte is XORed with the
+/// key byte at its file position modulo the key length.
+struct XorReader<R> {
+    inner: R,
+    key: [u8; 8],
+    position: u64,
+}
+
+impl<R: Read> Read for XorReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let len = self.inner.read(buf)?;
+        for byte in &mut buf[..len] {
+            *byte ^= self.key[(self.position % 8) as usize];
+            self.position += 1;
+        }
+        Ok(len)
+    }
+}
+
+/// Iterates over the blocks stored in Bitcoin Core `blkNNNNN.dat` files. Each
+/// record is the network magic, a little-endian length and the serialized block.
+/// Files are preallocated by Core, so a zeroed magic marks the end of the data
+/// in that file.
+pub struct BlockFileReader {
+    files: std::vec::IntoIter<PathBuf>,
+    magic: [u8; 4],
+    xor_key: [u8; 8],
+    current: Option<(PathBuf, XorReader<BufReader<File>>)>,
+}
+
+impl BlockFileReader {
+    /// Opens every `blk*.dat` file in a Core `blocks/` directory, in file order,
+    /// using the obfuscation key from `xor.dat` when present.
+    pub fn open(blocks_dir: impl AsRef<Path>, magic: [u8; 4]) -> Result<Self> {
+        let blocks_dir = blocks_dir.as_ref();
+        let mut files = Vec::new();
+        for entry in fs::read_dir(blocks_dir)? {
+            let path = entry?.path();
+            let is_block_file = path
+                .file_name()
+                .and_then(|name| name.to_str())
+                .is_some_and(|name| name.starts_with("blk") && name.ends_with(".dat"));
+            if is_block_file {
+                files.push(path);
+            }
+        }
+        files.sort();
+
+        let xor_key = match fs::read(blocks_dir.join("xor.dat")) {
+            Ok(key) => key.try_into().map_err(|key: Vec<u8>| {
+                BitcoinError::InvalidPayload(format!(
+                    "xor.dat must hold 8 bytes, found {}",
+                    key.len()
+                ))
+            })?,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => [0u8; 8],
+            Err(e) => return Err(e.into()),
+        };
+        Ok(Self::from_files(files, magic, xor_key))
+    }
+
+    pub fn from_files(files: Vec<PathBuf>, magic: [u8; 4], xor_key: [u8; 8]) -> Self {
+        Self {
+            files: files.into_iter(),
+            magic,
+            xor_key,
+            current: None,
+        }
+    }
+
+    /// Reads the next record of the current file, `None` once the file is exhausted.
+    fn next_record(
+        magic: [u8; 4],
+        path: &Path,
+        reader: &mut XorReader<BufReader<File>>,
+    ) -> Option<Result<(PathBuf, u64, Block)>> {
+        let record_offset = reader.position;
+        let mut header = [0u8; 8];
+        let mut read = 0;
+        while read < header.len() {
+            match reader.read(&mut header[read..]) {
+                Ok(0) => break,
+                Ok(len) => read += len,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => return Some(Err(e.into())),
+            }
+        }
+        // Clean end of file, or the zero padding Core leaves after the last block.
+        // The padding is not obfuscated, so it decodes to the key bytes.
+        let is_padding = header[..read.min(4)]
+            .iter()
+            .zip(record_offset..)
+            .all(|(byte, position)| *byte == reader.key[(position % 8) as usize]);
+        if read == 0 || is_padding {
+            return None;
+        }
+        if read < header.len() {
+            return Some(Err(BitcoinError::UnexpectedEof {
+                field: "block record header",
+                offset: record_offset as usize,
+            }));
+        }
+        if header[..4] != magic {
+            return Some(Err(BitcoinError::InvalidField {
+                field: "network magic",
+                offset: record_offset as usize,
+                reason: format!(
+                    "expected {}, found {}",
+                    hex::encode(magic),
+                    hex::encode(&header[..4])
+                ),
+            }));
+        }
+        let len = u32::from_le_bytes(header[4..].try_into().unwrap());
+        if len as usize > MAX_BLOCK_SERIALIZED_SIZE {
+            return Some(Err(BitcoinError::InvalidField {
+                field: "block length",
+                offset: record_offset as usize + 4,
+                reason: format!("{} bytes exceeds {}", len, MAX_BLOCK_SERIALIZED_SIZE),
+            }));
+        }
+
+        let block_offset = reader.position;
+        let mut payload = vec![0u8; len as usize];
+        if let Err(e) = reader.read_exact(&mut payload) {
+            return Some(Err(if e.kind() == io::ErrorKind::UnexpectedEof {
+                BitcoinError::UnexpectedEof {
+                    field: "block",
+                    offset: block_offset as usize,
+                }
+            } else {
+                e.into()
+            }));
+        }
+        Some(Block::deserialize(&payload).map(|block| (path.to_path_buf(), block_offset, block)))
+    }
+}
+
+impl Iterator for BlockFileReader {
+    /// The file, the offset of the serialized block within it and the block.
+    type Item = Result<(PathBuf, u64, Block)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if self.current.is_none() {
+                let path = self.files.next()?;
+                let file = match File::open(&path) {
+                    Ok(file) => file,
+                    Err(e) => return Some(Err(e.into())),
+                };
+                let reader = XorReader {
+                    inner: BufReader::new(file),
+                    key: self.xor_key,
+                    position: 0,
+                };
+                self.current = Some((path, reader));
+            }
+            let (path, reader) = self.current.as_mut()?;
+            match Self::next_record(self.magic, path, reader) {
+                Some(Ok(record)) => return Some(Ok(record)),
+                // The rest of a file cannot be framed after an error, move on to the next one
+                Some(Err(e)) => {
+                    self.current = None;
+                    return Some(Err(e));
+                }
+                None => self.current = None,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use super::*;
+
+    const BLOCK_1: &str = "010000006fe28c0ab6f1b372c1a6a246ae63f74f931e8365e15a089c68d6190000000000982051fd1e4ba744bbbe680e1fee14677ba1a3c3540bf7b1cdb606e857233e0e61bc6649ffff001d01e362990101000000010000000000000000000000000000000000000000000000000000000000000000ffffffff0704ffff001d0104ffffffff0100f2052a0100000043410496b538e853519c726a2c91e61ec11600ae1390813a627c66fb8be7947be63c52da7589379515d4e0a604f8141781e62294721166bf621e73a82cbf2342c858eeac00000000";
+
+    struct TempDir(PathBuf);
+
+    impl TempDir {
+        fn new(name: &str) -> Self {
+            let path =
+                std::env::temp_dir().join(format!("mine_block-{}-{}", name, std::process::id()));
+            let _ = fs::remove_dir_all(&path);
+            fs::create_dir_all(&path).unwrap();
+            Self(path)
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    fn record(magic: [u8; 4], block: &[u8]) -> Vec<u8> {
+        let mut record = magic.to_vec();
+        record.extend_from_slice(&(block.len() as u32).to_le_bytes());
+        record.extend_from_slice(block);
+        record
+    }
+
+    fn write_obfuscated(path: &Path, data: &[u8], key: [u8; 8]) {
+        let data: Vec<u8> = data
+            .iter()
+            .enumerate()
+            .map(|(i, byte)| byte ^ key[i % 8])
+            .collect();
+        fs::write(path, data).unwrap();
+    }
+
+    #[test]
+    fn test_read_obfuscated_block_files() {
+        let dir = TempDir::new("xor");
+        let key = [0x4c, 0x6d, 0x0a, 0xe1, 0x93, 0x27, 0x38, 0x5f];
+        let block = hex::decode(BLOCK_1).unwrap();
+
+        let mut first = record(MAINNET_MAGIC, &block);
+        first.extend_from_slice(&record(MAINNET_MAGIC, &block));
+        write_obfuscated(&dir.0.join("blk00000.dat"), &first, key);
+        // Preallocated tail of a file Core has not filled yet, written unobfuscated
+        let mut file = fs::OpenOptions::new()
+            .append(true)
+            .open(dir.0.join("blk00000.dat"))
+            .unwrap();
+        file.write_all(&[0u8; 64]).unwrap();
+        write_obfuscated(
+            &dir.0.join("blk00001.dat"),
+            &record(MAINNET_MAGIC, &block),
+            key,
+        );
+        fs::write(dir.0.join("xor.dat"), key).unwrap();
+        fs::write(dir.0.join("rev00000.dat"), [0xffu8; 16]).unwrap();
+
+        let records: Vec<_> = BlockFileReader::open(&dir.0, MAINNET_MAGIC)
+            .unwrap()
+            .collect::<Result<_>>()
+            .unwrap();
+
+        assert_eq!(records.len(), 3);
+        let positions: Vec<_> = records
+            .iter()
+            .map(|(path, offset, _)| (path.file_name().unwrap().to_owned(), *offset))
+            .collect();
+        assert_eq!(
+            positions,
+            [
+                ("blk00000.dat".into(), 8),
+                ("blk00000.dat".into(), 8 + block.len() as u64 + 8),
+                ("blk00001.dat".into(), 8),
+            ]
+        );
+        for (_, _, parsed) in records {
+            assert_eq!(parsed.serialize(), block);
+        }
+    }
+
+    #[test]
+    fn test_read_plain_block_file() {
+        let dir = TempDir::new("plain");
+        let block = hex::decode(BLOCK_1).unwrap();
+        fs::write(dir.0.join("blk00000.dat"), record(REGTEST_MAGIC, &block)).unwrap();
+
+        let mut reader = BlockFileReader::open(&dir.0, REGTEST_MAGIC).unwrap();
+        let (_, offset, parsed) = reader.next().unwrap().unwrap();
+        assert_eq!(offset, 8);
+        assert_eq!(parsed.block_header.nonce, 2573394689);
+        assert!(reader.next().is_none());
+    }
+
+    #[test]
+    fn test_wrong_magic_and_truncated_record() {
+        let dir = TempDir::new("errors");
+        let block = hex::decode(BLOCK_1).unwrap();
+        fs::write(dir.0.join("blk00000.dat"), record(SIGNET_MAGIC, &block)).unwrap();
+        let mut truncated = record(MAINNET_MAGIC, &block);
+        truncated.truncate(100);
+        fs::write(dir.0.join("blk00001.dat"), truncated).unwrap();
+
+        let mut reader = BlockFileReader::open(&dir.0, MAINNET_MAGIC).unwrap();
+        assert!(matches!(
+            reader.next(),
+            Some(Err(BitcoinError::InvalidField {
+                field: "network magic",
+                offset: 0,
+                ..
+            }))
+        ));
+        assert!(matches!(
+            reader.next(),
+            Some(Err(BitcoinError::UnexpectedEof {
+                field: "block",
+                offset: 8
+            }))
+        ));
+        assert!(reader.next().is_none());
+    }
+}
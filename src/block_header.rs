@@ -1,10 +1,20 @@
-use crate::{BitcoinError, Result};
+use std::io::{self, Read, Write};
+
+use sha2::{Digest, Sha256};
+
+use crate::{
+    decoder::Decoder,
+    encode::{Decodable, Encodable},
+    uint::U256,
+    BitcoinError, BlockHash, Result, TxMerkleNode,
+};
 
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct BlockHeader {
     pub version: u32,
-    pub previous_block_hash: [u8; 32],
-    pub merkle_root_hash: [u8; 32],
+    pub previous_block_hash: BlockHash,
+    pub merkle_root_hash: TxMerkleNode,
     pub timestamp: u32,
     pub bits: u32,
     pub nonce: u32,
@@ -17,50 +27,191 @@ impl BlockHeader {
                 "Invalid block header length".to_string(),
             ));
         }
-        let version = u32::from_le_bytes(
-            payload[0..4]
-                .try_into()
-                .map_err(|_| BitcoinError::InvalidPayload("Invalid version length".to_string()))?,
+        Self::consensus_decode(&mut Decoder::from_slice(payload))
+    }
+
+    /// Difficulty relative to the minimum mainnet target, computed like Bitcoin Core.
+    pub fn difficulty(&self) -> f64 {
+        let mut shift = (self.bits >> 24) & 0xff;
+        let mut difficulty = 0x0000ffff as f64 / (self.bits & 0x00ffffff) as f64;
+        while shift < 29 {
+            difficulty *= 256.0;
+            shift += 1;
+        }
+        while shift > 29 {
+            difficulty /= 256.0;
+            shift -= 1;
+        }
+        difficulty
+    }
+
+    pub fn block_hash(&self) -> BlockHash {
+        BlockHash::from_byte_array(Sha256::digest(Sha256::digest(self.serialize())).into())
+    }
+
+    /// Target decoded from the compact `bits` encoding, as Bitcoin Core's
+    /// `SetCompact`. Negative and overflowing encodings give zero, a target no
+    /// hash can meet.
+    pub fn target(&self) -> U256 {
+        let size = self.bits >> 24;
+        let mut word = self.bits & 0x007fffff;
+        let target = if size <= 3 {
+            word >>= 8 * (3 - size);
+            U256::from(word as u64)
+        } else {
+            U256::from(word as u64) << (8 * (size - 3))
+        };
+        let negative = word != 0 && self.bits & 0x00800000 != 0;
+        let overflow =
+            word != 0 && (size > 34 || (word > 0xff && size > 33) || (word > 0xffff && size > 32));
+        if negative || overflow {
+            U256::ZERO
+        } else {
+            target
+        }
+    }
+
+    /// Checks that the header hash meets its own target and returns the hash.
+    /// Whether the target is acceptable for the chain is up to the caller.
+    pub fn validate_pow(&self) -> Result<BlockHash> {
+        let target = self.target();
+        if target.is_zero() {
+            return Err(BitcoinError::InvalidBlock(format!(
+                "Invalid target bits {:08x}",
+                self.bits
+            )));
+        }
+        let hash = self.block_hash();
+        if U256::from_le_bytes(hash.to_byte_array()) > target {
+            return Err(BitcoinError::InvalidBlock(format!(
+                "Block hash {} above target {:064x}",
+                hash, target
+            )));
+        }
+        Ok(hash)
+    }
+
+    /// Expected number of hashes needed to meet the target, `2^256 / (target + 1)`,
+    /// as summed into chainwork.
+    pub fn work(&self) -> U256 {
+        let target = self.target();
+        if target.is_zero() {
+            return U256::ZERO;
+        }
+        // 2^256 does not fit, but 2^256 / (t + 1) == (2^256 - t - 1) / (t + 1) + 1
+        (!target).div_rem(target + U256::ONE).0 + U256::ONE
+    }
+
+    pub fn serialize(&self) -> [u8; 80] {
+        let mut payload = [0u8; 80];
+        self.consensus_encode(&mut payload.as_mut_slice())
+            .expect("a block header is exactly 80 bytes");
+        payload
+    }
+}
+
+impl Encodable for BlockHeader {
+    fn consensus_encode<W: Write + ?Sized>(&self, writer: &mut W) -> io::Result<usize> {
+        writer.write_all(&self.version.to_le_bytes())?;
+        writer.write_all(self.previous_block_hash.as_byte_array())?;
+        writer.write_all(self.merkle_root_hash.as_byte_array())?;
+        writer.write_all(&self.timestamp.to_le_bytes())?;
+        writer.write_all(&self.bits.to_le_bytes())?;
+        writer.write_all(&self.nonce.to_le_bytes())?;
+        Ok(80)
+    }
+}
+
+impl Decodable for BlockHeader {
+    fn consensus_decode<R: Read>(decoder: &mut Decoder<R>) -> Result<Self> {
+        Ok(Self {
+            version: decoder.read_u32("block version")?,
+            previous_block_hash: BlockHash::from_byte_array(
+                decoder.read_array("previous block hash")?,
+            ),
+            merkle_root_hash: TxMerkleNode::from_byte_array(
+                decoder.read_array("merkle root hash")?,
+            ),
+            timestamp: decoder.read_u32("timestamp")?,
+            bits: decoder.read_u32("bits")?,
+            nonce: decoder.read_u32("nonce")?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BLOCK_170_HEADER: &str = "0100000055bd840a78798ad0da853f68974f3d183e2bd1db6a842c1feecf222a00000000ff104ccb05421ab93e63f8c3ce5c2c2e9dbb37de2764b3a3175c8166562cac7d51b96a49ffff001d283e9e70";
+
+    fn header_with_bits(bits: u32) -> BlockHeader {
+        BlockHeader {
+            version: 1,
+            previous_block_hash: BlockHash::all_zeros(),
+            merkle_root_hash: TxMerkleNode::all_zeros(),
+            timestamp: 0,
+            bits,
+            nonce: 0,
+        }
+    }
+
+    #[test]
+    fn test_block_hash_and_pow() {
+        let mut header = BlockHeader::deserialize(&hex::decode(BLOCK_170_HEADER).unwrap()).unwrap();
+        let hash = header.validate_pow().unwrap();
+        assert_eq!(
+            hash.to_string(),
+            "00000000d1145790a8694403d4063f323d499e655c83426834d4ce2f8dd4a2ee"
+        );
+        assert_eq!(header.block_hash(), hash);
+
+        header.nonce += 1;
+        assert!(matches!(
+            header.validate_pow(),
+            Err(BitcoinError::InvalidBlock(_))
+        ));
+    }
+
+    #[test]
+    fn test_target() {
+        assert_eq!(
+            header_with_bits(0x1d00ffff).target().to_be_bytes(),
+            <[u8; 32]>::try_from(
+                hex::decode("00000000ffff0000000000000000000000000000000000000000000000000000")
+                    .unwrap()
+            )
+            .unwrap()
         );
-        let previous_block_hash = payload[4..36].try_into().map_err(|_| {
-            BitcoinError::InvalidPayload("Invalid previous block hash length".to_string())
-        })?;
-        let merkle_root_hash = payload[36..68].try_into().map_err(|_| {
-            BitcoinError::InvalidPayload("Invalid merkle root hash length".to_string())
-        })?;
-        let timestamp =
-            u32::from_le_bytes(payload[68..72].try_into().map_err(|_| {
-                BitcoinError::InvalidPayload("Invalid timestamp length".to_string())
-            })?);
-        let bits = u32::from_le_bytes(
-            payload[72..76]
-                .try_into()
-                .map_err(|_| BitcoinError::InvalidPayload("Invalid bits length".to_string()))?,
+        assert_eq!(
+            header_with_bits(0x1b0404cb).target().to_be_bytes(),
+            [
+                0x00, 0x00, 0x00, 0x00, 0x00, 0x04, 0x04, 0xcb, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+                0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+                0x00, 0x00, 0x00, 0x00,
+            ]
         );
-        let nonce = u32::from_le_bytes(
-            payload[76..80]
-                .try_into()
-                .map_err(|_| BitcoinError::InvalidPayload("Invalid nonce length".to_string()))?,
+        // Small exponents shift the mantissa right
+        assert_eq!(header_with_bits(0x01123456).target(), U256::from(0x12));
+        assert_eq!(header_with_bits(0x02123456).target(), U256::from(0x1234));
+        assert_eq!(header_with_bits(0x03123456).target(), U256::from(0x123456));
+        assert_eq!(
+            header_with_bits(0x04123456).target(),
+            U256::from(0x12345600)
         );
-
-        return Ok(Self {
-            version,
-            previous_block_hash,
-            merkle_root_hash,
-            timestamp,
-            bits,
-            nonce,
-        });
+        // Negative and overflowing encodings
+        assert_eq!(header_with_bits(0x04923456).target(), U256::ZERO);
+        assert_eq!(header_with_bits(0xff123456).target(), U256::ZERO);
+        assert_eq!(header_with_bits(0x21010000).target(), U256::ZERO);
+        assert_eq!(header_with_bits(0x20010000).target(), U256::ONE << 248);
+        assert!(header_with_bits(0x00000000).validate_pow().is_err());
     }
 
-    pub fn serialize(&self) -> [u8; 80] {
-        let mut payload = Vec::new();
-        payload.extend_from_slice(&self.version.to_le_bytes());
-        payload.extend_from_slice(&self.previous_block_hash);
-        payload.extend_from_slice(&self.merkle_root_hash);
-        payload.extend_from_slice(&self.timestamp.to_le_bytes());
-        payload.extend_from_slice(&self.bits.to_le_bytes());
-        payload.extend_from_slice(&self.nonce.to_le_bytes());
-        payload.try_into().unwrap()
+    #[test]
+    fn test_work() {
+        // Every difficulty 1 block adds 0x100010001 to mainnet chainwork
+        assert_eq!(header_with_bits(0x1d00ffff).work(), U256::from(0x100010001));
+        assert_eq!(header_with_bits(0x207fffff).work(), U256::from(2));
+        assert_eq!(header_with_bits(0x04923456).work(), U256::ZERO);
     }
 }
@@ -0,0 +1,395 @@
+use std::io::{self, Read};
+
+use crate::{BitcoinError, Result, MAX_BLOCK_SERIALIZED_SIZE};
+
+/// Wraps any reader carrying a consensus-encoded payload. Every read names the
+/// field being decoded so failures report what was expected and at which byte
+/// offset.
+///
+/// Decoding is strict by default: CompactSize values must be minimally encoded,
+/// as consensus requires.
+#[derive(Debug)]
+pub struct Decoder<R> {
+    reader: R,
+    offset: usize,
+    end: Option<usize>,
+    strict: bool,
+}
+
+impl<'a> Decoder<&'a [u8]> {
+    /// Decoder over an in-memory payload, whose length bounds the counts it accepts.
+    pub fn from_slice(payload: &'a [u8]) -> Self {
+        Self::new(payload).with_limit(payload.len())
+    }
+}
+
+impl<R: Read> Decoder<R> {
+    pub fn new(reader: R) -> Self {
+        Self::with_offset(reader, 0)
+    }
+
+    /// Starts counting at `offset`, for readers positioned inside a larger payload.
+    pub fn with_offset(reader: R, offset: usize) -> Self {
+        Self {
+            reader,
+            offset,
+            end: None,
+            strict: true,
+        }
+    }
+
+    /// Declares that at most `len` more bytes are available, so item counts that
+    /// could not possibly fit are rejected before anything is decoded.
+    pub fn with_limit(mut self, len: usize) -> Self {
+        self.end = Some(self.offset.saturating_add(len));
+        self
+    }
+
+    /// Accepts non-minimal CompactSize encodings.
+    pub fn lenient(mut self) -> Self {
+        self.strict = false;
+        self
+    }
+
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+
+    /// Bytes left before the limit, if one was set.
+    pub fn remaining(&self) -> Option<usize> {
+        self.end.map(|end| end.saturating_sub(self.offset))
+    }
+
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+
+    fn io_error(&self, error: io::Error, field: &'static str) -> BitcoinError {
+        if error.kind() == io::ErrorKind::UnexpectedEof {
+            BitcoinError::UnexpectedEof {
+                field,
+                offset: self.offset,
+            }
+        } else {
+            BitcoinError::Io(error)
+        }
+    }
+
+    pub fn read_exact(&mut self, buf: &mut [u8], field: &'static str) -> Result<()> {
+        self.reader
+            .read_exact(buf)
+            .map_err(|e| self.io_error(e, field))?;
+        self.offset += buf.len();
+        Ok(())
+    }
+
+    /// Reads `len` bytes. The buffer grows with the data actually read, so a
+    /// bogus length cannot trigger a huge allocation up front.
+    pub fn read_bytes(&mut self, len: usize, field: &'static str) -> Result<Vec<u8>> {
+        let mut bytes = Vec::new();
+        (&mut self.reader)
+            .take(len as u64)
+            .read_to_end(&mut bytes)
+            .map_err(|e| self.io_error(e, field))?;
+        if bytes.len() != len {
+            return Err(BitcoinError::UnexpectedEof {
+                field,
+                offset: self.offset,
+            });
+        }
+        self.offset += len;
+        Ok(bytes)
+    }
+
+    pub fn read_array<const N: usize>(&mut self, field: &'static str) -> Result<[u8; N]> {
+        let mut array = [0u8; N];
+        self.read_exact(&mut array, field)?;
+        Ok(array)
+    }
+
+    pub fn read_u8(&mut self, field: &'static str) -> Result<u8> {
+        Ok(self.read_array::<1>(field)?[0])
+    }
+
+    pub fn read_u16(&mut self, field: &'static str) -> Result<u16> {
+        Ok(u16::from_le_bytes(self.read_array(field)?))
+    }
+
+    pub fn read_u32(&mut self, field: &'static str) -> Result<u32> {
+        Ok(u32::from_le_bytes(self.read_array(field)?))
+    }
+
+    pub fn read_u64(&mut self, field: &'static str) -> Result<u64> {
+        Ok(u64::from_le_bytes(self.read_array(field)?))
+    }
+
+    pub fn read_varint(&mut self, field: &'static str) -> Result<u64> {
+        let start = self.offset;
+        let (value, min) = match self.read_u8(field)? {
+            0xFF => (self.read_u64(field)?, 0x1_0000_0000),
+            0xFE => (self.read_u32(field)? as u64, 0x1_0000),
+            0xFD => (self.read_u16(field)? as u64, 0xFD),
+            // Value is stored directly in the first byte.
+            first_byte => (first_byte as u64, 0),
+        };
+        if self.strict && value < min {
+            return Err(BitcoinError::InvalidField {
+                field,
+                offset: start,
+                reason: format!("non-canonical CompactSize encoding of {}", value),
+            });
+        }
+        Ok(value)
+    }
+
+    /// Reads a CompactSize count of items that take at least `min_item_size`
+    /// bytes each. Counts that could not fit in the remaining payload, or in a
+    /// maximum size block, are rejected.
+    pub fn read_count(&mut self, field: &'static str, min_item_size: usize) -> Result<usize> {
+        let start = self.offset;
+        let count = self.read_varint(field)?;
+        let available = self
+            .remaining()
+            .map_or(MAX_BLOCK_SERIALIZED_SIZE, |remaining| {
+                remaining.min(MAX_BLOCK_SERIALIZED_SIZE)
+            });
+        if count > (available / min_item_size.max(1)) as u64 {
+            return Err(BitcoinError::InvalidField {
+                field,
+                offset: start,
+                reason: format!(
+                    "{} items of at least {} bytes cannot fit in {} bytes",
+                    count, min_item_size, available
+                ),
+            });
+        }
+        Ok(count as usize)
+    }
+
+    /// Reads a CompactSize length prefix followed by that many bytes.
+    pub fn read_var_bytes(&mut self, field: &'static str) -> Result<Vec<u8>> {
+        let start = self.offset;
+        let len = self.read_varint(field)?;
+        if len > MAX_BLOCK_SERIALIZED_SIZE as u64 {
+            return Err(BitcoinError::InvalidField {
+                field,
+                offset: start,
+                reason: format!("length {} exceeds {}", len, MAX_BLOCK_SERIALIZED_SIZE),
+            });
+        }
+        self.read_bytes(len as usize, field)
+    }
+
+    pub fn invalid(&self, field: &'static str, reason: impl Into<String>) -> BitcoinError {
+        BitcoinError::InvalidField {
+            field,
+            offset: self.offset,
+            reason: reason.into(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        Block, BlockHeader, BlockView, OutPoint, Transaction, TransactionInput, TransactionOutput,
+        TransactionView,
+    };
+
+    const BLOCK_1: &str = "010000006fe28c0ab6f1b372c1a6a246ae63f74f931e8365e15a089c68d6190000000000982051fd1e4ba744bbbe680e1fee14677ba1a3c3540bf7b1cdb606e857233e0e61bc6649ffff001d01e362990101000000010000000000000000000000000000000000000000000000000000000000000000ffffffff0704ffff001d0104ffffffff0100f2052a0100000043410496b538e853519c726a2c91e61ec11600ae1390813a627c66fb8be7947be63c52da7589379515d4e0a604f8141781e62294721166bf621e73a82cbf2342c858eeac00000000";
+    const SEGWIT_TRANSACTION: &str = "02000000000101595895ea20179de87052b4046dfe6fd515860505d6511a9004cf12a1f93cac7c0100000000ffffffff01deb807000000000017a9140f3444e271620c736808aa7b33e370bd87cb5a078702483045022100fb60dad8df4af2841adc0346638c16d0b8035f5e3f3753b88db122e70c79f9370220756e6633b17fd2710e626347d28d60b0a2d6cbb41de51740644b9fb3ba7751040121028fa937ca8cba2197a37c007176ed8941055d3bcb8627d085e94553e62f057dcc00000000";
+
+    // xorshift64*, good enough to spray bytes at the parsers deterministically
+    struct Rng(u64);
+
+    impl Rng {
+        fn next(&mut self) -> u64 {
+            self.0 ^= self.0 >> 12;
+            self.0 ^= self.0 << 25;
+            self.0 ^= self.0 >> 27;
+            self.0.wrapping_mul(0x2545F4914F6CDD1D)
+        }
+
+        fn bytes(&mut self, len: usize) -> Vec<u8> {
+            (0..len).map(|_| self.next() as u8).collect()
+        }
+    }
+
+    fn decode_all(payload: &[u8]) {
+        let _ = Block::deserialize(payload);
+        let _ = BlockHeader::deserialize(payload);
+        let _ = Transaction::deserialize(payload);
+        let _ = TransactionInput::deserialize(payload);
+        let _ = TransactionOutput::deserialize(payload);
+        let _ = OutPoint::deserialize(payload);
+        let _ = BlockView::new(payload);
+        let _ = TransactionView::new(payload);
+    }
+
+    #[test]
+    fn test_decoder_reports_field_and_offset() {
+        let mut decoder = Decoder::new(&[0x01, 0x00, 0x00, 0x00, 0xfd, 0x01][..]);
+        assert_eq!(decoder.read_u32("version").unwrap(), 1);
+        match decoder.read_varint("input count") {
+            Err(BitcoinError::UnexpectedEof { field, offset }) => {
+                assert_eq!(field, "input count");
+                assert_eq!(offset, 5);
+            }
+            other => panic!("unexpected result {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_non_canonical_compact_size() {
+        for (payload, value) in [
+            (&[0xfc][..], 0xfc),
+            (&[0xfd, 0xfd, 0x00][..], 0xfd),
+            (&[0xfe, 0x00, 0x00, 0x01, 0x00][..], 0x10000),
+            (
+                &[0xff, 0x00, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00][..],
+                0x100000000,
+            ),
+        ] {
+            assert_eq!(Decoder::new(payload).read_varint("count").unwrap(), value);
+        }
+
+        for payload in [
+            &[0xfd, 0x01, 0x00][..],
+            &[0xfd, 0xfc, 0x00][..],
+            &[0xfe, 0xff, 0xff, 0x00, 0x00][..],
+            &[0xff, 0xff, 0xff, 0xff, 0xff, 0x00, 0x00, 0x00, 0x00][..],
+        ] {
+            match Decoder::new(payload).read_varint("count") {
+                Err(BitcoinError::InvalidField { field, offset, .. }) => {
+                    assert_eq!(field, "count");
+                    assert_eq!(offset, 0);
+                }
+                other => panic!("unexpected result {:?}", other),
+            }
+        }
+        assert_eq!(
+            Decoder::new(&[0xfd, 0x01, 0x00][..])
+                .lenient()
+                .read_varint("count")
+                .unwrap(),
+            1
+        );
+
+        // A one-input transaction whose input count is padded to three bytes
+        let mut payload = hex::decode(crate::TRANSACTION_SERIALIZED).unwrap();
+        payload.splice(4..5, [0xfd, 0x01, 0x00]);
+        assert!(matches!(
+            Transaction::deserialize(&payload),
+            Err(BitcoinError::InvalidField {
+                field: "input count",
+                offset: 4,
+                ..
+            })
+        ));
+        assert!(TransactionView::new(&payload).is_err());
+    }
+
+    #[test]
+    fn test_counts_bounded_by_payload() {
+        // Claims 2^64 - 1 inputs in a 13-byte payload
+        let payload = hex::decode("01000000ffffffffffffffffff").unwrap();
+        match Transaction::deserialize(&payload) {
+            Err(BitcoinError::InvalidField { field, offset, .. }) => {
+                assert_eq!(field, "input count");
+                assert_eq!(offset, 4);
+            }
+            other => panic!("unexpected result {:?}", other),
+        }
+        assert!(TransactionView::new(&payload).is_err());
+
+        // Two inputs fit in 82 bytes but not in 81
+        let mut decoder = Decoder::from_slice(&[0x02; 83]);
+        assert_eq!(decoder.read_count("input count", 41).unwrap(), 2);
+        let mut decoder = Decoder::from_slice(&[0x02; 82]);
+        assert!(decoder.read_count("input count", 41).is_err());
+
+        // Without a known length, counts are bounded by the maximum block size
+        let mut decoder = Decoder::new(&[0xfe, 0x00, 0x09, 0x3d, 0x00][..]);
+        assert_eq!(decoder.read_count("count", 1).unwrap(), 4_000_000);
+        let mut decoder = Decoder::new(&[0xfe, 0x00, 0x09, 0x3d, 0x00][..]);
+        assert!(decoder.read_count("count", 10).is_err());
+        let mut decoder = Decoder::new(&[0xfe, 0x01, 0x09, 0x3d, 0x00][..]);
+        assert!(decoder.read_var_bytes("script").is_err());
+    }
+
+    #[test]
+    fn test_truncated_transaction_error() {
+        let payload = hex::decode(crate::TRANSACTION_SERIALIZED).unwrap();
+        match Transaction::deserialize(&payload[..payload.len() - 2]) {
+            Err(BitcoinError::UnexpectedEof { field, offset }) => {
+                assert_eq!(field, "locktime");
+                assert_eq!(offset, payload.len() - 4);
+            }
+            other => panic!("unexpected result {:?}", other),
+        }
+
+        // Cut inside the scriptSig of the first input
+        match Transaction::deserialize(&payload[..60]) {
+            Err(BitcoinError::UnexpectedEof { field, offset }) => {
+                assert_eq!(field, "script_sig");
+                assert_eq!(offset, 42);
+            }
+            other => panic!("unexpected result {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_truncated_payloads_never_panic() {
+        let block = hex::decode(BLOCK_1).unwrap();
+        for len in 0..block.len() {
+            decode_all(&block[..len]);
+            assert!(Block::deserialize(&block[..len]).is_err());
+        }
+
+        for transaction in [
+            hex::decode(SEGWIT_TRANSACTION).unwrap(),
+            hex::decode(crate::TRANSACTION_SERIALIZED).unwrap(),
+        ] {
+            for len in 0..transaction.len() {
+                decode_all(&transaction[..len]);
+                assert!(Transaction::deserialize(&transaction[..len]).is_err());
+            }
+        }
+    }
+
+    #[test]
+    fn test_mutated_payloads_never_panic() {
+        let mut rng = Rng(0x5eed);
+        let payloads = [
+            hex::decode(BLOCK_1).unwrap(),
+            hex::decode(SEGWIT_TRANSACTION).unwrap(),
+        ];
+        for _ in 0..2000 {
+            let mut payload = payloads[(rng.next() % 2) as usize].clone();
+            for _ in 0..=rng.next() % 4 {
+                let position = (rng.next() as usize) % payload.len();
+                payload[position] = rng.next() as u8;
+            }
+            decode_all(&payload);
+        }
+    }
+
+    #[test]
+    fn test_random_bytes_never_panic() {
+        let mut rng = Rng(0xdecafbad);
+        for _ in 0..5000 {
+            let len = (rng.next() % 300) as usize;
+            let mut payload = rng.bytes(len);
+            // Steer some inputs towards the segwit marker and large CompactSize prefixes
+            if len > 6 && rng.next().is_multiple_of(3) {
+                payload[4] = 0x00;
+                payload[5] = 0x01;
+            }
+            if len > 7 && rng.next().is_multiple_of(3) {
+                payload[6] = 0xff;
+            }
+            decode_all(&payload);
+        }
+    }
+}
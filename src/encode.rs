@@ -0,0 +1,154 @@
+use std::io::{self, Read, Write};
+
+use crate::{decoder::Decoder, utils::varint_size, Result};
+
+pub trait Encodable {
+    /// Writes the consensus encoding and returns the number of bytes written.
+    fn consensus_encode<W: Write + ?Sized>(&self, writer: &mut W) -> io::Result<usize>;
+
+    fn encoded_size(&self) -> usize {
+        self.consensus_encode(&mut io::sink())
+            .expect("writing to a sink never fails")
+    }
+}
+
+pub trait Decodable: Sized {
+    fn consensus_decode<R: Read>(decoder: &mut Decoder<R>) -> Result<Self>;
+}
+
+pub fn serialize<T: Encodable + ?Sized>(value: &T) -> Vec<u8> {
+    let mut payload = Vec::with_capacity(value.encoded_size());
+    value
+        .consensus_encode(&mut payload)
+        .expect("writing to a Vec never fails");
+    payload
+}
+
+pub fn deserialize<T: Decodable>(payload: &[u8]) -> Result<T> {
+    T::consensus_decode(&mut Decoder::from_slice(payload))
+}
+
+/// Bitcoin's variable length integer, used for counts and lengths.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CompactSize(pub u64);
+
+impl Encodable for CompactSize {
+    fn consensus_encode<W: Write + ?Sized>(&self, writer: &mut W) -> io::Result<usize> {
+        let value = self.0;
+        if value < 0xFD {
+            writer.write_all(&[value as u8])?;
+        } else if value <= 0xFFFF {
+            writer.write_all(&[0xFD])?;
+            writer.write_all(&(value as u16).to_le_bytes())?;
+        } else if value <= 0xFFFFFFFF {
+            writer.write_all(&[0xFE])?;
+            writer.write_all(&(value as u32).to_le_bytes())?;
+        } else {
+            writer.write_all(&[0xFF])?;
+            writer.write_all(&value.to_le_bytes())?;
+        }
+        Ok(varint_size(value))
+    }
+
+    fn encoded_size(&self) -> usize {
+        varint_size(self.0)
+    }
+}
+
+impl Decodable for CompactSize {
+    fn consensus_decode<R: Read>(decoder: &mut Decoder<R>) -> Result<Self> {
+        Ok(Self(decoder.read_varint("compact size")?))
+    }
+}
+
+/// Writes a CompactSize length prefix followed by the bytes themselves.
+pub(crate) fn encode_var_bytes<W: Write + ?Sized>(
+    bytes: &[u8],
+    writer: &mut W,
+) -> io::Result<usize> {
+    let len = CompactSize(bytes.len() as u64).consensus_encode(writer)?;
+    writer.write_all(bytes)?;
+    Ok(len + bytes.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Block, BlockHeader, OutPoint, Transaction, TransactionInput, TransactionOutput};
+
+    #[test]
+    fn test_compact_size_round_trip() {
+        let cases: [(u64, &str); 7] = [
+            (0, "00"),
+            (0xFC, "fc"),
+            (0xFD, "fdfd00"),
+            (0xFFFF, "fdffff"),
+            (0x10000, "fe00000100"),
+            (0xFFFFFFFF, "feffffffff"),
+            (0x100000000, "ff0000000001000000"),
+        ];
+        for (value, expected) in cases {
+            let payload = serialize(&CompactSize(value));
+            assert_eq!(hex::encode(&payload), expected);
+            assert_eq!(CompactSize(value).encoded_size(), payload.len());
+            assert_eq!(deserialize::<CompactSize>(&payload).unwrap().0, value);
+        }
+    }
+
+    #[test]
+    fn test_encodable_matches_serialize() {
+        let payload = hex::decode(crate::TRANSACTION_SERIALIZED).unwrap();
+        let transaction: Transaction = deserialize(&payload).unwrap();
+
+        assert_eq!(serialize(&transaction), payload);
+        assert_eq!(transaction.encoded_size(), payload.len());
+
+        let input = &transaction.inputs[0];
+        assert_eq!(serialize(input), payload[5..5 + input.size()]);
+        assert_eq!(
+            TransactionInput::deserialize(&payload[5..])
+                .unwrap()
+                .sequence,
+            input.sequence
+        );
+        assert_eq!(input.encoded_size(), input.size());
+        assert_eq!(serialize(&input.previous_output), payload[5..41]);
+        assert_eq!(
+            deserialize::<OutPoint>(&payload[5..41]).unwrap().index,
+            input.previous_output.index
+        );
+
+        let output = &transaction.outputs[0];
+        assert_eq!(output.encoded_size(), output.size());
+        assert_eq!(
+            serialize(&deserialize::<TransactionOutput>(&serialize(output)).unwrap()),
+            serialize(output)
+        );
+    }
+
+    #[test]
+    fn test_stream_block_through_reader_and_writer() {
+        let payload = hex::decode("010000006fe28c0ab6f1b372c1a6a246ae63f74f931e8365e15a089c68d6190000000000982051fd1e4ba744bbbe680e1fee14677ba1a3c3540bf7b1cdb606e857233e0e61bc6649ffff001d01e362990101000000010000000000000000000000000000000000000000000000000000000000000000ffffffff0704ffff001d0104ffffffff0100f2052a0100000043410496b538e853519c726a2c91e61ec11600ae1390813a627c66fb8be7947be63c52da7589379515d4e0a604f8141781e62294721166bf621e73a82cbf2342c858eeac00000000").unwrap();
+
+        // Two blocks back to back in a buffered stream, decoded one after the other
+        let mut stream = payload.clone();
+        stream.extend_from_slice(&payload);
+        let mut decoder = Decoder::new(io::BufReader::with_capacity(7, stream.as_slice()));
+        let first = Block::consensus_decode(&mut decoder).unwrap();
+        let second = Block::consensus_decode(&mut decoder).unwrap();
+        assert_eq!(decoder.offset(), stream.len());
+
+        let mut written = Vec::new();
+        let len = first.consensus_encode(&mut written).unwrap();
+        assert_eq!(len, payload.len());
+        assert_eq!(written, payload);
+        assert_eq!(second.encoded_size(), payload.len());
+        assert_eq!(serialize(&second.block_header), payload[..80]);
+        assert_eq!(
+            BlockHeader::consensus_decode(&mut Decoder::new(&payload[..80]))
+                .unwrap()
+                .nonce,
+            first.block_header.nonce
+        );
+    }
+}
@@ -1,5 +1,7 @@
 use std::io;
 
+use crate::Network;
+
 #[derive(thiserror::Error, Debug)]
 pub enum BitcoinError {
     #[error("IO error: {0}")]
@@ -8,6 +10,155 @@ pub enum BitcoinError {
     InvalidPayload(String),
     #[error("Invalid hash: {0}")]
     InvalidHash(String),
+    #[error("Unexpected end of payload reading {field} at byte offset {offset}")]
+    UnexpectedEof { field: &'static str, offset: usize },
+    #[error("Invalid {field} at byte offset {offset}: {reason}")]
+    InvalidField {
+        field: &'static str,
+        offset: usize,
+        reason: String,
+    },
+    #[error("Invalid block: {0}")]
+    InvalidBlock(String),
+    #[error("Invalid script: {0}")]
+    InvalidScript(String),
+    #[error("Invalid amount: {0}")]
+    InvalidAmount(String),
+    #[error("Invalid PSBT: {0}")]
+    InvalidPsbt(String),
+    #[error("Invalid address: {0}")]
+    InvalidAddress(#[from] AddressError),
+    #[error("Script failed: {0}")]
+    ScriptFailed(#[from] ScriptError),
+    #[error("Cannot compute signature hash: {0}")]
+    InvalidSighash(#[from] SighashError),
+    #[error("Unknown output spent by input {0}")]
+    MissingPrevout(usize),
+    #[error("secp256k1: {0}")]
+    Secp256k1(#[from] Secp256k1Error),
+}
+
+/// Why an address string or script could not be converted.
+#[derive(thiserror::Error, Debug, Clone, PartialEq, Eq)]
+pub enum AddressError {
+    #[error("invalid character {0:?}")]
+    InvalidCharacter(char),
+    #[error("mixed case")]
+    MixedCase,
+    #[error("bad checksum")]
+    InvalidChecksum,
+    #[error("invalid length {0}")]
+    InvalidLength(usize),
+    #[error("unknown version byte {0:#04x}")]
+    UnknownPrefix(u8),
+    #[error("unknown human readable part {0:?}")]
+    UnknownHrp(String),
+    #[error("address belongs to a network other than {0}")]
+    WrongNetwork(Network),
+    #[error("invalid witness version {0}")]
+    InvalidWitnessVersion(u8),
+    #[error("invalid program length {length} for witness version {version}")]
+    InvalidWitnessProgramLength { version: u8, length: usize },
+    #[error("witness version {0} with the wrong checksum variant, v0 uses Bech32 and v1+ Bech32m")]
+    WrongChecksumVariant(u8),
+    #[error("invalid padding")]
+    InvalidPadding,
+    #[error("{0} scripts have no address")]
+    UnsupportedScript(&'static str),
+}
+
+/// Why a signature hash could not be computed.
+#[derive(thiserror::Error, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SighashError {
+    #[error("input {index} out of range for {inputs} inputs")]
+    InputIndex { index: usize, inputs: usize },
+    #[error("output spent by input {0} is unknown")]
+    MissingPrevout(usize),
+    #[error("SIGHASH_SINGLE for input {0} without a matching output")]
+    SingleWithoutOutput(usize),
+    #[error("invalid hash type {0:#x}")]
+    InvalidHashType(u32),
+    #[error("annex does not start with 0x50")]
+    InvalidAnnex,
+}
+
+#[derive(thiserror::Error, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Secp256k1Error {
+    #[error("invalid public key")]
+    InvalidPublicKey,
+    #[error("invalid signature encoding")]
+    InvalidSignature,
 }
 
 pub type Result<T> = std::result::Result<T, BitcoinError>;
+
+/// Why a script failed to evaluate, Core's `ScriptError_t`.
+#[derive(thiserror::Error, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScriptError {
+    #[error("script evaluated without error but finished with a false or empty top stack element")]
+    EvalFalse,
+    #[error("OP_RETURN was encountered")]
+    OpReturn,
+    #[error("script is too big")]
+    ScriptSize,
+    #[error("push value size limit exceeded")]
+    PushSize,
+    #[error("operation limit exceeded")]
+    OpCount,
+    #[error("stack size limit exceeded")]
+    StackSize,
+    #[error("signature count negative or greater than pubkey count")]
+    SigCount,
+    #[error("pubkey count negative or limit exceeded")]
+    PubkeyCount,
+    #[error("script failed an OP_VERIFY operation")]
+    Verify,
+    #[error("script failed an OP_EQUALVERIFY operation")]
+    EqualVerify,
+    #[error("script failed an OP_CHECKMULTISIGVERIFY operation")]
+    CheckMultisigVerify,
+    #[error("script failed an OP_CHECKSIGVERIFY operation")]
+    CheckSigVerify,
+    #[error("script failed an OP_NUMEQUALVERIFY operation")]
+    NumEqualVerify,
+    #[error("opcode missing or not understood")]
+    BadOpcode,
+    #[error("attempted to use a disabled opcode")]
+    DisabledOpcode,
+    #[error("operation not valid with the current stack size")]
+    InvalidStackOperation,
+    #[error("operation not valid with the current altstack size")]
+    InvalidAltstackOperation,
+    #[error("invalid OP_IF construction")]
+    UnbalancedConditional,
+    #[error("script number overflow or not minimally encoded")]
+    InvalidNumber,
+    #[error("negative locktime")]
+    NegativeLocktime,
+    #[error("locktime requirement not satisfied")]
+    UnsatisfiedLocktime,
+    #[error("only push operators allowed in signatures")]
+    SigPushOnly,
+    #[error("dummy CHECKMULTISIG argument must be zero")]
+    SigNullDummy,
+    #[error("signature must be zero for failed CHECK(MULTI)SIG operation")]
+    SigNullFail,
+    #[error("using OP_CODESEPARATOR in non-witness script")]
+    OpCodeSeparator,
+    #[error("non-canonical DER signature")]
+    SigDer,
+    #[error("non-canonical signature: S value is unnecessarily high")]
+    SigHighS,
+    #[error("signature hash type missing or not understood")]
+    SigHashType,
+    #[error("public key is neither compressed or uncompressed")]
+    PubkeyType,
+    #[error("signature is found in scriptCode")]
+    SigFindAndDelete,
+    #[error("data push larger than necessary")]
+    MinimalData,
+    #[error("NOPx reserved for soft-fork upgrades")]
+    DiscourageUpgradableNops,
+    #[error("stack size must be exactly one after execution")]
+    CleanStack,
+}
@@ -0,0 +1,140 @@
+use std::{fmt, str::FromStr};
+
+use crate::BitcoinError;
+
+/// Defines a 32-byte double-SHA256 hash newtype. Bytes are stored in internal
+/// (serialization) order; `Display` and `FromStr` use the byte-reversed order
+/// shown by the RPC interface and block explorers.
+macro_rules! hash_newtype {
+    ($(#[$doc:meta])* $name:ident) => {
+        $(#[$doc])*
+        #[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+        pub struct $name([u8; 32]);
+
+        impl $name {
+            pub const fn from_byte_array(bytes: [u8; 32]) -> Self {
+                Self(bytes)
+            }
+
+            pub const fn all_zeros() -> Self {
+                Self([0; 32])
+            }
+
+            pub const fn as_byte_array(&self) -> &[u8; 32] {
+                &self.0
+            }
+
+            pub const fn to_byte_array(self) -> [u8; 32] {
+                self.0
+            }
+        }
+
+        impl AsRef<[u8]> for $name {
+            fn as_ref(&self) -> &[u8] {
+                &self.0
+            }
+        }
+
+        impl fmt::Display for $name {
+            fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                let mut reversed = self.0;
+                reversed.reverse();
+                f.write_str(&hex::encode(reversed))
+            }
+        }
+
+        impl fmt::Debug for $name {
+            fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                write!(f, "{}({})", stringify!($name), self)
+            }
+        }
+
+        impl FromStr for $name {
+            type Err = BitcoinError;
+
+            fn from_str(s: &str) -> Result<Self, Self::Err> {
+                let bytes = hex::decode(s).map_err(|e| BitcoinError::InvalidHash(e.to_string()))?;
+                let mut bytes: [u8; 32] = bytes.try_into().map_err(|bytes: Vec<u8>| {
+                    BitcoinError::InvalidHash(format!("expected 32 bytes, found {}", bytes.len()))
+                })?;
+                bytes.reverse();
+                Ok(Self(bytes))
+            }
+        }
+
+        #[cfg(feature = "serde")]
+        impl serde::Serialize for $name {
+            fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                crate::serde_utils::hex_hash::serialize(&self.0, serializer)
+            }
+        }
+
+        #[cfg(feature = "serde")]
+        impl<'de> serde::Deserialize<'de> for $name {
+            fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+                crate::serde_utils::hex_hash::deserialize(deserializer).map(Self)
+            }
+        }
+    };
+}
+
+hash_newtype!(
+    /// Transaction id, the hash of the transaction serialized without witness.
+    Txid
+);
+hash_newtype!(
+    /// Witness transaction id, the hash of the full BIP144 serialization.
+    Wtxid
+);
+hash_newtype!(
+    /// Hash of an 80-byte block header.
+    BlockHash
+);
+hash_newtype!(
+    /// Node of the transaction merkle tree, the root being committed to in the header.
+    TxMerkleNode
+);
+hash_newtype!(
+    /// Node of the BIP141 witness merkle tree.
+    WitnessMerkleNode
+);
+hash_newtype!(
+    /// Message a transaction signature commits to.
+    Sighash
+);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GENESIS_HASH: &str = "000000000019d6689c085ae165831e934ff763ae46a2a6c172b3f1b60a8ce26f";
+
+    #[test]
+    fn test_display_is_byte_reversed() {
+        let hash: BlockHash = GENESIS_HASH.parse().unwrap();
+        assert_eq!(hash.as_byte_array()[0], 0x6f);
+        assert_eq!(hash.as_byte_array()[31], 0x00);
+        assert_eq!(hash.to_string(), GENESIS_HASH);
+        assert_eq!(
+            format!("{:?}", hash),
+            format!("BlockHash({})", GENESIS_HASH)
+        );
+
+        let mut bytes = hex::decode(GENESIS_HASH).unwrap();
+        bytes.reverse();
+        assert_eq!(hash.as_ref(), bytes);
+    }
+
+    #[test]
+    fn test_from_str_errors() {
+        assert!(matches!(
+            "00".parse::<Txid>(),
+            Err(BitcoinError::InvalidHash(_))
+        ));
+        assert!(matches!(
+            GENESIS_HASH.replace('0', "g").parse::<Txid>(),
+            Err(BitcoinError::InvalidHash(_))
+        ));
+        assert_eq!("0".repeat(64).parse::<Txid>().unwrap(), Txid::all_zeros());
+    }
+}
@@ -0,0 +1,1544 @@
+use std::ops::BitOr;
+
+use ripemd::Ripemd160;
+use sha1::Sha1;
+use sha2::{Digest, Sha256};
+
+use crate::{
+    opcodes::*,
+    script::{decode_script_num, encode_script_num},
+    secp256k1::is_valid_signature_encoding,
+    EcdsaSignature, Instruction, PublicKey, Result, Script, ScriptError, ScriptType, Transaction,
+    TransactionOutput, SIGHASH_ALL, SIGHASH_ANYONECANPAY, SIGHASH_SINGLE,
+};
+
+/// Largest script that can be executed.
+pub const MAX_SCRIPT_SIZE: usize = 10_000;
+/// Largest element that can be pushed onto the stack.
+pub const MAX_SCRIPT_ELEMENT_SIZE: usize = 520;
+/// Limit on non-push opcodes per script, public keys of `OP_CHECKMULTISIG`
+/// included.
+pub const MAX_OPS_PER_SCRIPT: usize = 201;
+/// Limit on the combined size of the main and alt stacks.
+pub const MAX_STACK_SIZE: usize = 1000;
+pub const MAX_PUBKEYS_PER_MULTISIG: usize = 20;
+/// Sequence bit that turns `OP_CHECKSEQUENCEVERIFY` into a NOP.
+const SEQUENCE_LOCKTIME_DISABLE_FLAG: i64 = 1 << 31;
+/// Relative lock times with this bit set count units of 512 seconds, blocks
+/// otherwise.
+const SEQUENCE_LOCKTIME_TYPE_FLAG: i64 = 1 << 22;
+const SEQUENCE_LOCKTIME_MASK: i64 = 0xffff;
+/// Lock times below this are block heights, timestamps from it on.
+const LOCKTIME_THRESHOLD: i64 = 500_000_000;
+
+type ScriptResult<T> = std::result::Result<T, ScriptError>;
+
+/// Script verification flags, with the same bits as Core's `SCRIPT_VERIFY_*`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct VerifyFlags(u32);
+
+impl VerifyFlags {
+    pub const NONE: Self = Self(0);
+    /// BIP16: evaluate the redeem script of P2SH outputs.
+    pub const P2SH: Self = Self(1 << 0);
+    /// Signatures need a defined hash type and public keys have to be
+    /// compressed or uncompressed, not hybrid. Implies strict DER.
+    pub const STRICTENC: Self = Self(1 << 1);
+    /// BIP66: signatures have to be strict DER.
+    pub const DERSIG: Self = Self(1 << 2);
+    /// Signatures have to use the lower of the two valid `s` values. Implies
+    /// strict DER.
+    pub const LOW_S: Self = Self(1 << 3);
+    /// BIP147: the `OP_CHECKMULTISIG` dummy element has to be empty.
+    pub const NULLDUMMY: Self = Self(1 << 4);
+    /// The scriptSig may only push data.
+    pub const SIGPUSHONLY: Self = Self(1 << 5);
+    /// Pushes and numbers have to use their smallest encoding.
+    pub const MINIMALDATA: Self = Self(1 << 6);
+    /// Fail on the NOPs reserved for soft forks.
+    pub const DISCOURAGE_UPGRADABLE_NOPS: Self = Self(1 << 7);
+    /// Exactly one element has to be left on the stack. Needs `P2SH`.
+    pub const CLEANSTACK: Self = Self(1 << 8);
+    /// BIP65 `OP_CHECKLOCKTIMEVERIFY`.
+    pub const CHECKLOCKTIMEVERIFY: Self = Self(1 << 9);
+    /// BIP112 `OP_CHECKSEQUENCEVERIFY`.
+    pub const CHECKSEQUENCEVERIFY: Self = Self(1 << 10);
+    /// BIP141 segregated witness. Only sigop counting looks at it so far,
+    /// witnesses are not evaluated.
+    pub const WITNESS: Self = Self(1 << 11);
+    /// A failed signature check has to be given an empty signature.
+    pub const NULLFAIL: Self = Self(1 << 14);
+    /// Fail when the signature is found in the script code or on
+    /// `OP_CODESEPARATOR` in legacy scripts.
+    pub const CONST_SCRIPTCODE: Self = Self(1 << 16);
+
+    /// Consensus rules, Core's `MANDATORY_SCRIPT_VERIFY_FLAGS` as far as they
+    /// are defined here. A script failing them makes a block invalid.
+    pub const MANDATORY: Self = Self(
+        Self::P2SH.0
+            | Self::DERSIG.0
+            | Self::NULLDUMMY.0
+            | Self::CHECKLOCKTIMEVERIFY.0
+            | Self::CHECKSEQUENCEVERIFY.0
+            | Self::WITNESS.0,
+    );
+    /// Relay policy on top of [`VerifyFlags::MANDATORY`], Core's
+    /// `STANDARD_SCRIPT_VERIFY_FLAGS` as far as they are defined here. A
+    /// script failing only these is non-standard, but can be mined.
+    pub const STANDARD: Self = Self(
+        Self::MANDATORY.0
+            | Self::STRICTENC.0
+            | Self::LOW_S.0
+            | Self::MINIMALDATA.0
+            | Self::DISCOURAGE_UPGRADABLE_NOPS.0
+            | Self::CLEANSTACK.0
+            | Self::NULLFAIL.0
+            | Self::CONST_SCRIPTCODE.0,
+    );
+
+    pub const fn bits(self) -> u32 {
+        self.0
+    }
+
+    pub const fn contains(self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    pub const fn intersects(self, other: Self) -> bool {
+        self.0 & other.0 != 0
+    }
+}
+
+impl BitOr for VerifyFlags {
+    type Output = Self;
+
+    fn bitor(self, rhs: Self) -> Self {
+        Self(self.0 | rhs.0)
+    }
+}
+
+/// Checks signatures and lock times against the spending transaction on
+/// behalf of the interpreter. Everything fails by default.
+pub trait SignatureChecker {
+    /// `signature` still ends with its hash type byte. `script_code` is the
+    /// executed script from the last `OP_CODESEPARATOR` on, with the
+    /// signature removed.
+    fn check_ecdsa_signature(
+        &self,
+        _signature: &[u8],
+        _pubkey: &[u8],
+        _script_code: &[u8],
+    ) -> bool {
+        false
+    }
+
+    fn check_lock_time(&self, _lock_time: i64) -> bool {
+        false
+    }
+
+    fn check_sequence(&self, _sequence: i64) -> bool {
+        false
+    }
+}
+
+/// Fails every check, Core's `BaseSignatureChecker`. Enough for scripts
+/// without signatures or lock times.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NullSignatureChecker;
+
+impl SignatureChecker for NullSignatureChecker {}
+
+/// Checks signatures and lock times against an input of a transaction,
+/// Core's `TransactionSignatureChecker` for legacy scripts.
+#[derive(Debug, Clone, Copy)]
+pub struct TransactionSignatureChecker<'a> {
+    transaction: &'a Transaction,
+    input_index: usize,
+}
+
+impl<'a> TransactionSignatureChecker<'a> {
+    pub fn new(transaction: &'a Transaction, input_index: usize) -> Self {
+        Self {
+            transaction,
+            input_index,
+        }
+    }
+}
+
+impl SignatureChecker for TransactionSignatureChecker<'_> {
+    fn check_ecdsa_signature(&self, signature: &[u8], pubkey: &[u8], script_code: &[u8]) -> bool {
+        let Some((&hash_type, signature)) = signature.split_last() else {
+            return false;
+        };
+        let (Ok(pubkey), Ok(signature)) = (
+            PublicKey::from_slice(pubkey),
+            EcdsaSignature::from_der_lax(signature),
+        ) else {
+            return false;
+        };
+        let sighash =
+            self.transaction
+                .legacy_sighash(self.input_index, script_code, hash_type as u32);
+        pubkey.verify(&sighash, &signature)
+    }
+
+    fn check_lock_time(&self, lock_time: i64) -> bool {
+        let tx_lock_time = self.transaction.locktime as i64;
+        // Heights can only be compared to heights and times to times
+        (tx_lock_time < LOCKTIME_THRESHOLD) == (lock_time < LOCKTIME_THRESHOLD)
+            && lock_time <= tx_lock_time
+            // A final input would let the lock time be ignored
+            && self.transaction.inputs[self.input_index].sequence != u32::MAX
+    }
+
+    fn check_sequence(&self, sequence: i64) -> bool {
+        let tx_sequence = self.transaction.inputs[self.input_index].sequence as i64;
+        // Relative lock times came with version 2, BIP68
+        if self.transaction.version < 2 || tx_sequence & SEQUENCE_LOCKTIME_DISABLE_FLAG != 0 {
+            return false;
+        }
+        let mask = SEQUENCE_LOCKTIME_TYPE_FLAG | SEQUENCE_LOCKTIME_MASK;
+        let (tx_sequence, sequence) = (tx_sequence & mask, sequence & mask);
+        (tx_sequence < SEQUENCE_LOCKTIME_TYPE_FLAG) == (sequence < SEQUENCE_LOCKTIME_TYPE_FLAG)
+            && sequence <= tx_sequence
+    }
+}
+
+impl Transaction {
+    /// Runs the scriptSig of input `input_index` against the output it
+    /// spends, checking signatures against this transaction. Witnesses are
+    /// not evaluated.
+    ///
+    /// # Panics
+    ///
+    /// If `input_index` is out of range.
+    pub fn verify_input(
+        &self,
+        input_index: usize,
+        spent: &TransactionOutput,
+        flags: VerifyFlags,
+    ) -> Result<()> {
+        verify_script(
+            &self.inputs[input_index].script_sig,
+            &spent.script_pub_key,
+            flags,
+            &TransactionSignatureChecker::new(self, input_index),
+        )
+    }
+}
+
+/// Runs `script` on `stack`, Core's `EvalScript` for legacy scripts.
+pub fn eval_script(
+    stack: &mut Vec<Vec<u8>>,
+    script: &[u8],
+    flags: VerifyFlags,
+    checker: &dyn SignatureChecker,
+) -> Result<()> {
+    Ok(eval(stack, script, flags, checker)?)
+}
+
+/// Checks that `script_sig` satisfies `script_pub_key`, running the redeem
+/// script of P2SH outputs when `flags` has [`VerifyFlags::P2SH`].
+pub fn verify_script(
+    script_sig: &[u8],
+    script_pub_key: &[u8],
+    flags: VerifyFlags,
+    checker: &dyn SignatureChecker,
+) -> Result<()> {
+    Ok(verify(script_sig, script_pub_key, flags, checker)?)
+}
+
+fn verify(
+    script_sig: &[u8],
+    script_pub_key: &[u8],
+    flags: VerifyFlags,
+    checker: &dyn SignatureChecker,
+) -> ScriptResult<()> {
+    let sig_push_only = Script::new(script_sig).is_push_only();
+    if flags.contains(VerifyFlags::SIGPUSHONLY) && !sig_push_only {
+        return Err(ScriptError::SigPushOnly);
+    }
+
+    let mut stack = Vec::new();
+    eval(&mut stack, script_sig, flags, checker)?;
+    let sig_stack = stack.clone();
+    eval(&mut stack, script_pub_key, flags, checker)?;
+    if !stack.last().is_some_and(|top| cast_to_bool(top)) {
+        return Err(ScriptError::EvalFalse);
+    }
+
+    let is_p2sh = matches!(
+        Script::new(script_pub_key).script_type(),
+        ScriptType::ScriptHash(_)
+    );
+    if flags.contains(VerifyFlags::P2SH) && is_p2sh {
+        if !sig_push_only {
+            return Err(ScriptError::SigPushOnly);
+        }
+        stack = sig_stack;
+        // The scriptSig left something true for the hash check to pass
+        let redeem_script = stack.pop().unwrap();
+        eval(&mut stack, &redeem_script, flags, checker)?;
+        if !stack.last().is_some_and(|top| cast_to_bool(top)) {
+            return Err(ScriptError::EvalFalse);
+        }
+    }
+
+    if flags.contains(VerifyFlags::CLEANSTACK) && stack.len() != 1 {
+        return Err(ScriptError::CleanStack);
+    }
+    Ok(())
+}
+
+fn eval(
+    stack: &mut Vec<Vec<u8>>,
+    script: &[u8],
+    flags: VerifyFlags,
+    checker: &dyn SignatureChecker,
+) -> ScriptResult<()> {
+    if script.len() > MAX_SCRIPT_SIZE {
+        return Err(ScriptError::ScriptSize);
+    }
+    let require_minimal = flags.contains(VerifyFlags::MINIMALDATA);
+    let num = |bytes: &[u8]| script_num(bytes, require_minimal, 4);
+
+    let mut alt_stack: Vec<Vec<u8>> = Vec::new();
+    // One entry per open OP_IF, whether its current branch runs
+    let mut exec_stack: Vec<bool> = Vec::new();
+    let mut op_count = 0;
+    let mut code_start = 0;
+    let mut instructions = Script::new(script).instructions();
+
+    loop {
+        let position = instructions.position();
+        let Some(instruction) = instructions.next() else {
+            break;
+        };
+        let executing = !exec_stack.contains(&false);
+        let opcode = match instruction.map_err(|_| ScriptError::BadOpcode)? {
+            Instruction::PushBytes(data) => {
+                if data.len() > MAX_SCRIPT_ELEMENT_SIZE {
+                    return Err(ScriptError::PushSize);
+                }
+                if executing {
+                    if require_minimal && !is_minimal_push(data, script[position]) {
+                        return Err(ScriptError::MinimalData);
+                    }
+                    stack.push(data.to_vec());
+                }
+                check_stack_size(stack, &alt_stack)?;
+                continue;
+            }
+            Instruction::Op(opcode) => opcode,
+        };
+
+        if opcode > OP_16 {
+            op_count += 1;
+            if op_count > MAX_OPS_PER_SCRIPT {
+                return Err(ScriptError::OpCount);
+            }
+        }
+        // Disabled opcodes fail even in a branch that is not taken
+        if matches!(
+            opcode,
+            OP_CAT
+                | OP_SUBSTR
+                | OP_LEFT
+                | OP_RIGHT
+                | OP_INVERT
+                | OP_AND
+                | OP_OR
+                | OP_XOR
+                | OP_2MUL
+                | OP_2DIV
+                | OP_MUL
+                | OP_DIV
+                | OP_MOD
+                | OP_LSHIFT
+                | OP_RSHIFT
+        ) {
+            return Err(ScriptError::DisabledOpcode);
+        }
+        if opcode == OP_CODESEPARATOR && flags.contains(VerifyFlags::CONST_SCRIPTCODE) {
+            return Err(ScriptError::OpCodeSeparator);
+        }
+        if !executing && !(OP_IF..=OP_ENDIF).contains(&opcode) {
+            continue;
+        }
+
+        match opcode {
+            OP_1NEGATE | OP_1..=OP_16 => {
+                stack.push(encode_script_num(opcode as i64 - (OP_1 as i64 - 1)))
+            }
+            OP_NOP => {}
+            OP_CHECKLOCKTIMEVERIFY if flags.contains(VerifyFlags::CHECKLOCKTIMEVERIFY) => {
+                // Lock times go up to 2^39 - 1, hence the 5 byte numbers
+                let lock_time = script_num(top(stack, 1)?, require_minimal, 5)?;
+                if lock_time < 0 {
+                    return Err(ScriptError::NegativeLocktime);
+                }
+                if !checker.check_lock_time(lock_time) {
+                    return Err(ScriptError::UnsatisfiedLocktime);
+                }
+            }
+            OP_CHECKSEQUENCEVERIFY if flags.contains(VerifyFlags::CHECKSEQUENCEVERIFY) => {
+                let sequence = script_num(top(stack, 1)?, require_minimal, 5)?;
+                if sequence < 0 {
+                    return Err(ScriptError::NegativeLocktime);
+                }
+                if sequence & SEQUENCE_LOCKTIME_DISABLE_FLAG == 0
+                    && !checker.check_sequence(sequence)
+                {
+                    return Err(ScriptError::UnsatisfiedLocktime);
+                }
+            }
+            OP_NOP1 | OP_CHECKLOCKTIMEVERIFY | OP_CHECKSEQUENCEVERIFY | OP_NOP4..=OP_NOP10 => {
+                if flags.contains(VerifyFlags::DISCOURAGE_UPGRADABLE_NOPS) {
+                    return Err(ScriptError::DiscourageUpgradableNops);
+                }
+            }
+
+            OP_IF | OP_NOTIF => {
+                let mut value = false;
+                if executing {
+                    let condition = stack.pop().ok_or(ScriptError::UnbalancedConditional)?;
+                    value = cast_to_bool(&condition) == (opcode == OP_IF);
+                }
+                exec_stack.push(value);
+            }
+            OP_ELSE => {
+                let branch = exec_stack
+                    .last_mut()
+                    .ok_or(ScriptError::UnbalancedConditional)?;
+                *branch = !*branch;
+            }
+            OP_ENDIF => {
+                exec_stack.pop().ok_or(ScriptError::UnbalancedConditional)?;
+            }
+            OP_VERIFY => {
+                if !cast_to_bool(top(stack, 1)?) {
+                    return Err(ScriptError::Verify);
+                }
+                stack.pop();
+            }
+            OP_RETURN => return Err(ScriptError::OpReturn),
+
+            OP_TOALTSTACK => alt_stack.push(pop(stack)?),
+            OP_FROMALTSTACK => {
+                let element = alt_stack
+                    .pop()
+                    .ok_or(ScriptError::InvalidAltstackOperation)?;
+                stack.push(element);
+            }
+            OP_2DROP => {
+                require_depth(stack, 2)?;
+                stack.truncate(stack.len() - 2);
+            }
+            OP_2DUP | OP_3DUP | OP_2OVER => {
+                let (depth, count) = match opcode {
+                    OP_2DUP => (2, 2),
+                    OP_3DUP => (3, 3),
+                    _ => (4, 2),
+                };
+                require_depth(stack, depth)?;
+                let start = stack.len() - depth;
+                stack.extend_from_within(start..start + count);
+            }
+            OP_2ROT => {
+                require_depth(stack, 6)?;
+                let start = stack.len() - 6;
+                let pair: Vec<_> = stack.drain(start..start + 2).collect();
+                stack.extend(pair);
+            }
+            OP_2SWAP => {
+                require_depth(stack, 4)?;
+                let len = stack.len();
+                stack.swap(len - 4, len - 2);
+                stack.swap(len - 3, len - 1);
+            }
+            OP_IFDUP => {
+                let element = top(stack, 1)?;
+                if cast_to_bool(element) {
+                    stack.push(element.clone());
+                }
+            }
+            OP_DEPTH => stack.push(encode_script_num(stack.len() as i64)),
+            OP_DROP => {
+                pop(stack)?;
+            }
+            OP_DUP => stack.push(top(stack, 1)?.clone()),
+            OP_NIP => {
+                require_depth(stack, 2)?;
+                stack.remove(stack.len() - 2);
+            }
+            OP_OVER => stack.push(top(stack, 2)?.clone()),
+            OP_PICK | OP_ROLL => {
+                require_depth(stack, 2)?;
+                let n = num(&pop(stack)?)?;
+                if n < 0 || n as usize >= stack.len() {
+                    return Err(ScriptError::InvalidStackOperation);
+                }
+                let index = stack.len() - 1 - n as usize;
+                let element = if opcode == OP_ROLL {
+                    stack.remove(index)
+                } else {
+                    stack[index].clone()
+                };
+                stack.push(element);
+            }
+            OP_ROT => {
+                require_depth(stack, 3)?;
+                let element = stack.remove(stack.len() - 3);
+                stack.push(element);
+            }
+            OP_SWAP => {
+                require_depth(stack, 2)?;
+                let len = stack.len();
+                stack.swap(len - 2, len - 1);
+            }
+            OP_TUCK => {
+                require_depth(stack, 2)?;
+                let element = stack[stack.len() - 1].clone();
+                stack.insert(stack.len() - 2, element);
+            }
+            OP_SIZE => stack.push(encode_script_num(top(stack, 1)?.len() as i64)),
+
+            OP_EQUAL | OP_EQUALVERIFY => {
+                require_depth(stack, 2)?;
+                let equal = pop(stack)? == pop(stack)?;
+                if opcode == OP_EQUALVERIFY {
+                    if !equal {
+                        return Err(ScriptError::EqualVerify);
+                    }
+                } else {
+                    stack.push(encode_bool(equal));
+                }
+            }
+
+            OP_1ADD | OP_1SUB | OP_NEGATE | OP_ABS | OP_NOT | OP_0NOTEQUAL => {
+                let n = num(top(stack, 1)?)?;
+                let result = match opcode {
+                    OP_1ADD => n + 1,
+                    OP_1SUB => n - 1,
+                    OP_NEGATE => -n,
+                    OP_ABS => n.abs(),
+                    OP_NOT => (n == 0) as i64,
+                    _ => (n != 0) as i64,
+                };
+                stack.pop();
+                stack.push(encode_script_num(result));
+            }
+            OP_ADD
+            | OP_SUB
+            | OP_BOOLAND
+            | OP_BOOLOR
+            | OP_NUMEQUAL
+            | OP_NUMEQUALVERIFY
+            | OP_NUMNOTEQUAL
+            | OP_LESSTHAN
+            | OP_GREATERTHAN
+            | OP_LESSTHANOREQUAL
+            | OP_GREATERTHANOREQUAL
+            | OP_MIN
+            | OP_MAX => {
+                let a = num(top(stack, 2)?)?;
+                let b = num(top(stack, 1)?)?;
+                let result = match opcode {
+                    OP_ADD => a + b,
+                    OP_SUB => a - b,
+                    OP_BOOLAND => (a != 0 && b != 0) as i64,
+                    OP_BOOLOR => (a != 0 || b != 0) as i64,
+                    OP_NUMEQUAL | OP_NUMEQUALVERIFY => (a == b) as i64,
+                    OP_NUMNOTEQUAL => (a != b) as i64,
+                    OP_LESSTHAN => (a < b) as i64,
+                    OP_GREATERTHAN => (a > b) as i64,
+                    OP_LESSTHANOREQUAL => (a <= b) as i64,
+                    OP_GREATERTHANOREQUAL => (a >= b) as i64,
+                    OP_MIN => a.min(b),
+                    _ => a.max(b),
+                };
+                stack.truncate(stack.len() - 2);
+                if opcode == OP_NUMEQUALVERIFY {
+                    if result == 0 {
+                        return Err(ScriptError::NumEqualVerify);
+                    }
+                } else {
+                    stack.push(encode_script_num(result));
+                }
+            }
+            OP_WITHIN => {
+                let x = num(top(stack, 3)?)?;
+                let min = num(top(stack, 2)?)?;
+                let max = num(top(stack, 1)?)?;
+                stack.truncate(stack.len() - 3);
+                stack.push(encode_bool(min <= x && x < max));
+            }
+
+            OP_RIPEMD160 | OP_SHA1 | OP_SHA256 | OP_HASH160 | OP_HASH256 => {
+                let data = pop(stack)?;
+                stack.push(match opcode {
+                    OP_RIPEMD160 => Ripemd160::digest(&data).to_vec(),
+                    OP_SHA1 => Sha1::digest(&data).to_vec(),
+                    OP_SHA256 => Sha256::digest(&data).to_vec(),
+                    OP_HASH160 => Ripemd160::digest(Sha256::digest(&data)).to_vec(),
+                    _ => Sha256::digest(Sha256::digest(&data)).to_vec(),
+                });
+            }
+            OP_CODESEPARATOR => code_start = instructions.position(),
+            OP_CHECKSIG | OP_CHECKSIGVERIFY => {
+                let signature = top(stack, 2)?;
+                let pubkey = top(stack, 1)?;
+                let script_code = script_code(&script[code_start..], &[signature], flags)?;
+                check_signature_encoding(signature, flags)?;
+                check_pubkey_encoding(pubkey, flags)?;
+                let success = checker.check_ecdsa_signature(signature, pubkey, &script_code);
+                if !success && flags.contains(VerifyFlags::NULLFAIL) && !signature.is_empty() {
+                    return Err(ScriptError::SigNullFail);
+                }
+                stack.truncate(stack.len() - 2);
+                if opcode == OP_CHECKSIGVERIFY {
+                    if !success {
+                        return Err(ScriptError::CheckSigVerify);
+                    }
+                } else {
+                    stack.push(encode_bool(success));
+                }
+            }
+            OP_CHECKMULTISIG | OP_CHECKMULTISIGVERIFY => {
+                let key_count = num(top(stack, 1)?)?;
+                if !(0..=MAX_PUBKEYS_PER_MULTISIG as i64).contains(&key_count) {
+                    return Err(ScriptError::PubkeyCount);
+                }
+                let key_count = key_count as usize;
+                op_count += key_count;
+                if op_count > MAX_OPS_PER_SCRIPT {
+                    return Err(ScriptError::OpCount);
+                }
+                let sig_count = num(top(stack, key_count + 2)?)?;
+                if !(0..=key_count as i64).contains(&sig_count) {
+                    return Err(ScriptError::SigCount);
+                }
+                let sig_count = sig_count as usize;
+                // Keys, key count, signatures, signature count and the dummy
+                let depth = key_count + sig_count + 3;
+                require_depth(stack, depth)?;
+
+                let args = &stack[stack.len() - depth..];
+                let signatures = &args[1..1 + sig_count];
+                let pubkeys = &args[2 + sig_count..2 + sig_count + key_count];
+                let script_code = script_code(&script[code_start..], signatures, flags)?;
+
+                // Signatures have to match keys in order, so each key is tried
+                // once, against the first signature that is still unmatched
+                let (mut next_sig, mut next_key) = (0, 0);
+                let mut success = true;
+                while success && next_sig < sig_count {
+                    let signature = &signatures[sig_count - 1 - next_sig];
+                    let pubkey = &pubkeys[key_count - 1 - next_key];
+                    check_signature_encoding(signature, flags)?;
+                    check_pubkey_encoding(pubkey, flags)?;
+                    if checker.check_ecdsa_signature(signature, pubkey, &script_code) {
+                        next_sig += 1;
+                    }
+                    next_key += 1;
+                    if sig_count - next_sig > key_count - next_key {
+                        success = false;
+                    }
+                }
+
+                if !success
+                    && flags.contains(VerifyFlags::NULLFAIL)
+                    && signatures.iter().any(|signature| !signature.is_empty())
+                {
+                    return Err(ScriptError::SigNullFail);
+                }
+                // Core's off-by-one: an extra element is consumed and has to
+                // be empty under BIP147
+                if flags.contains(VerifyFlags::NULLDUMMY) && !args[0].is_empty() {
+                    return Err(ScriptError::SigNullDummy);
+                }
+                stack.truncate(stack.len() - depth);
+                if opcode == OP_CHECKMULTISIGVERIFY {
+                    if !success {
+                        return Err(ScriptError::CheckMultisigVerify);
+                    }
+                } else {
+                    stack.push(encode_bool(success));
+                }
+            }
+
+            _ => return Err(ScriptError::BadOpcode),
+        }
+        check_stack_size(stack, &alt_stack)?;
+    }
+
+    if !exec_stack.is_empty() {
+        return Err(ScriptError::UnbalancedConditional);
+    }
+    Ok(())
+}
+
+/// Core's `CheckSignatureEncoding`. An empty signature is always allowed, it
+/// is the way to make a check fail on purpose.
+fn check_signature_encoding(signature: &[u8], flags: VerifyFlags) -> ScriptResult<()> {
+    let Some((&hash_type, der)) = signature.split_last() else {
+        return Ok(());
+    };
+    let strict_der = VerifyFlags::DERSIG | VerifyFlags::LOW_S | VerifyFlags::STRICTENC;
+    if flags.intersects(strict_der) && !is_valid_signature_encoding(signature) {
+        return Err(ScriptError::SigDer);
+    }
+    if flags.contains(VerifyFlags::LOW_S)
+        && !EcdsaSignature::from_der_lax(der).is_ok_and(|signature| signature.is_low_s())
+    {
+        return Err(ScriptError::SigHighS);
+    }
+    let base_type = (hash_type & !(SIGHASH_ANYONECANPAY as u8)) as u32;
+    if flags.contains(VerifyFlags::STRICTENC)
+        && !(SIGHASH_ALL..=SIGHASH_SINGLE).contains(&base_type)
+    {
+        return Err(ScriptError::SigHashType);
+    }
+    Ok(())
+}
+
+/// Core's `CheckPubKeyEncoding`: only the sizes and prefixes are checked, not
+/// whether the key is on the curve.
+fn check_pubkey_encoding(pubkey: &[u8], flags: VerifyFlags) -> ScriptResult<()> {
+    let compressed_or_uncompressed = match pubkey.first() {
+        Some(0x04) => pubkey.len() == 65,
+        Some(0x02 | 0x03) => pubkey.len() == 33,
+        _ => false,
+    };
+    if flags.contains(VerifyFlags::STRICTENC) && !compressed_or_uncompressed {
+        return Err(ScriptError::PubkeyType);
+    }
+    Ok(())
+}
+
+/// Truth value of a stack element: anything but zero, negative zero included.
+pub fn cast_to_bool(element: &[u8]) -> bool {
+    match element.split_last() {
+        Some((last, rest)) => rest.iter().any(|&byte| byte != 0) || last & 0x7f != 0,
+        None => false,
+    }
+}
+
+fn encode_bool(value: bool) -> Vec<u8> {
+    if value {
+        vec![1]
+    } else {
+        Vec::new()
+    }
+}
+
+/// Stack element as a CScriptNum of at most `max_len` bytes. Arithmetic takes
+/// 4 byte operands but may produce 5 byte results.
+fn script_num(bytes: &[u8], require_minimal: bool, max_len: usize) -> ScriptResult<i64> {
+    if bytes.len() > max_len {
+        return Err(ScriptError::InvalidNumber);
+    }
+    if require_minimal {
+        if let Some((last, rest)) = bytes.split_last() {
+            // A zero last byte is only needed to hold the sign bit
+            if last & 0x7f == 0 && rest.last().is_none_or(|byte| byte & 0x80 == 0) {
+                return Err(ScriptError::InvalidNumber);
+            }
+        }
+    }
+    Ok(decode_script_num(bytes))
+}
+
+/// Core's `CheckMinimalPush`: the data could not have been pushed by a
+/// shorter opcode.
+fn is_minimal_push(data: &[u8], opcode: u8) -> bool {
+    match data {
+        [] => opcode == OP_0,
+        [1..=16] | [0x81] => false,
+        _ if data.len() < OP_PUSHDATA1 as usize => opcode as usize == data.len(),
+        _ if data.len() <= 0xff => opcode == OP_PUSHDATA1,
+        _ if data.len() <= 0xffff => opcode == OP_PUSHDATA2,
+        _ => true,
+    }
+}
+
+/// The script a legacy signature commits to: `script` with every push of one
+/// of the `signatures` removed, since a signature cannot sign itself.
+fn script_code(
+    script: &[u8],
+    signatures: &[impl AsRef<[u8]>],
+    flags: VerifyFlags,
+) -> ScriptResult<Vec<u8>> {
+    let mut script_code = script.to_vec();
+    for signature in signatures {
+        let (without, found) = find_and_delete(&script_code, &push_encoding(signature.as_ref()));
+        if found && flags.contains(VerifyFlags::CONST_SCRIPTCODE) {
+            return Err(ScriptError::SigFindAndDelete);
+        }
+        script_code = without;
+    }
+    Ok(script_code)
+}
+
+/// Push of `data` the way Core's `CScript << data` writes it, which unlike
+/// [`crate::ScriptBuilder::push_slice`] never substitutes a small integer
+/// opcode.
+fn push_encoding(data: &[u8]) -> Vec<u8> {
+    let len = data.len();
+    let mut encoded = if len < OP_PUSHDATA1 as usize {
+        vec![len as u8]
+    } else if len <= 0xff {
+        vec![OP_PUSHDATA1, len as u8]
+    } else if len <= 0xffff {
+        let mut prefix = vec![OP_PUSHDATA2];
+        prefix.extend_from_slice(&(len as u16).to_le_bytes());
+        prefix
+    } else {
+        let mut prefix = vec![OP_PUSHDATA4];
+        prefix.extend_from_slice(&(len as u32).to_le_bytes());
+        prefix
+    };
+    encoded.extend_from_slice(data);
+    encoded
+}
+
+/// Core's `FindAndDelete`: removes every occurrence of `pattern` that starts
+/// on an instruction boundary, and whether anything was removed.
+fn find_and_delete(script: &[u8], pattern: &[u8]) -> (Vec<u8>, bool) {
+    if pattern.is_empty() {
+        return (script.to_vec(), false);
+    }
+    let mut result = Vec::with_capacity(script.len());
+    let mut found = false;
+    let (mut position, mut kept_from) = (0, 0);
+    loop {
+        result.extend_from_slice(&script[kept_from..position]);
+        while script[position..].starts_with(pattern) {
+            position += pattern.len();
+            found = true;
+        }
+        kept_from = position;
+        let mut instructions = Script::new(&script[position..]).instructions();
+        match instructions.next() {
+            Some(Ok(_)) => position += instructions.position(),
+            _ => break,
+        }
+    }
+    if !found {
+        return (script.to_vec(), false);
+    }
+    result.extend_from_slice(&script[kept_from..]);
+    (result, true)
+}
+
+/// The element `depth` places from the top, 1 being the top itself.
+fn top(stack: &[Vec<u8>], depth: usize) -> ScriptResult<&Vec<u8>> {
+    require_depth(stack, depth)?;
+    Ok(&stack[stack.len() - depth])
+}
+
+fn pop(stack: &mut Vec<Vec<u8>>) -> ScriptResult<Vec<u8>> {
+    stack.pop().ok_or(ScriptError::InvalidStackOperation)
+}
+
+fn require_depth(stack: &[Vec<u8>], depth: usize) -> ScriptResult<()> {
+    if stack.len() < depth {
+        return Err(ScriptError::InvalidStackOperation);
+    }
+    Ok(())
+}
+
+fn check_stack_size(stack: &[Vec<u8>], alt_stack: &[Vec<u8>]) -> ScriptResult<()> {
+    if stack.len() + alt_stack.len() > MAX_STACK_SIZE {
+        return Err(ScriptError::StackSize);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+
+    use super::*;
+    use crate::{BitcoinError, ScriptBuilder, Transaction};
+
+    fn run(asm: &str, flags: VerifyFlags) -> ScriptResult<Vec<Vec<u8>>> {
+        let script = Script::from_asm(asm).unwrap();
+        let mut stack = Vec::new();
+        eval(&mut stack, &script, flags, &NullSignatureChecker).map(|()| stack)
+    }
+
+    fn numbers(asm: &str) -> Vec<i64> {
+        run(asm, VerifyFlags::NONE)
+            .unwrap()
+            .iter()
+            .map(|element| decode_script_num(element))
+            .collect()
+    }
+
+    fn error(asm: &str, flags: VerifyFlags) -> ScriptError {
+        run(asm, flags).unwrap_err()
+    }
+
+    /// Accepts the listed signature and key pairs, and lock times up to 500.
+    #[derive(Default)]
+    struct MockChecker {
+        valid: Vec<(Vec<u8>, Vec<u8>)>,
+        script_codes: RefCell<Vec<Vec<u8>>>,
+    }
+
+    impl SignatureChecker for MockChecker {
+        fn check_ecdsa_signature(
+            &self,
+            signature: &[u8],
+            pubkey: &[u8],
+            script_code: &[u8],
+        ) -> bool {
+            self.script_codes.borrow_mut().push(script_code.to_vec());
+            self.valid
+                .iter()
+                .any(|(sig, key)| sig == signature && key == pubkey)
+        }
+
+        fn check_lock_time(&self, lock_time: i64) -> bool {
+            lock_time <= 500
+        }
+    }
+
+    #[test]
+    fn test_block_170_spend() {
+        let transaction =
+            Transaction::deserialize(&hex::decode(crate::TRANSACTION_SERIALIZED).unwrap()).unwrap();
+        let script_sig = &transaction.inputs[0].script_sig;
+        // The spent block 9 coinbase pays to the same key as the change output
+        let script_pub_key = &transaction.outputs[1].script_pub_key;
+        let signature = Script::new(script_sig)
+            .instructions()
+            .next()
+            .unwrap()
+            .unwrap()
+            .push_bytes()
+            .unwrap();
+        let checker = MockChecker {
+            valid: vec![(signature.to_vec(), script_pub_key[1..66].to_vec())],
+            ..Default::default()
+        };
+        let flags = VerifyFlags::P2SH | VerifyFlags::NULLFAIL;
+        verify_script(script_sig, script_pub_key, flags, &checker).unwrap();
+        assert_eq!(checker.script_codes.borrow()[0], *script_pub_key);
+
+        assert!(matches!(
+            verify_script(
+                script_sig,
+                script_pub_key,
+                VerifyFlags::P2SH,
+                &NullSignatureChecker
+            ),
+            Err(BitcoinError::ScriptFailed(ScriptError::EvalFalse))
+        ));
+        assert!(matches!(
+            verify_script(script_sig, script_pub_key, flags, &NullSignatureChecker),
+            Err(BitcoinError::ScriptFailed(ScriptError::SigNullFail))
+        ));
+    }
+
+    #[test]
+    fn test_verify_block_170_input() {
+        let transaction =
+            Transaction::deserialize(&hex::decode(crate::TRANSACTION_SERIALIZED).unwrap()).unwrap();
+        let coinbase =
+            Transaction::deserialize(&hex::decode(crate::BLOCK_9_COINBASE_SERIALIZED).unwrap())
+                .unwrap();
+        assert_eq!(coinbase.txid(), transaction.inputs[0].previous_output.hash);
+        let spent = &coinbase.outputs[0];
+        let flags = VerifyFlags::P2SH | VerifyFlags::NULLFAIL;
+        transaction.verify_input(0, spent, flags).unwrap();
+
+        // Changing what is signed, or who signs, breaks the signature
+        let mut tampered = transaction.clone();
+        tampered.locktime = 1;
+        assert!(matches!(
+            tampered.verify_input(0, spent, flags),
+            Err(BitcoinError::ScriptFailed(ScriptError::SigNullFail))
+        ));
+        let other_key = crate::TransactionOutput {
+            value: spent.value,
+            script_pub_key: transaction.outputs[0].script_pub_key.clone(),
+        };
+        assert!(matches!(
+            transaction.verify_input(0, &other_key, VerifyFlags::P2SH),
+            Err(BitcoinError::ScriptFailed(ScriptError::EvalFalse))
+        ));
+    }
+
+    #[test]
+    fn test_signature_encoding_flags() {
+        let transaction =
+            Transaction::deserialize(&hex::decode(crate::TRANSACTION_SERIALIZED).unwrap()).unwrap();
+        let coinbase =
+            Transaction::deserialize(&hex::decode(crate::BLOCK_9_COINBASE_SERIALIZED).unwrap())
+                .unwrap();
+        let r = "4e45e16932b8af514961a1d3a1a25fdf3f4f7732e9d624c6c61548ab5fb8cd41";
+        // The same signature, with n - s and with a padded r. Legacy sighashes
+        // leave out the scriptSig, so both still verify.
+        let high_s = format!(
+            "3045 0220 {} 022100 e7eadd137135f821b79f5b5322ed6f6137921779f39c5a19b7b03ce459a92438 01",
+            r
+        );
+        let not_der = format!(
+            "3045 022100 {} 0220 181522ec8eca07de4860a4acdd12909d831cc56cbbac4622082221a8768d1d09 01",
+            r
+        );
+        let verify = |signature: &str, script_pub_key: &[u8], flags| {
+            let mut spending = transaction.clone();
+            spending.inputs[0].script_sig = ScriptBuilder::new()
+                .push_slice(&hex::decode(signature.replace(' ', "")).unwrap())
+                .into_bytes();
+            let spent = crate::TransactionOutput {
+                value: coinbase.outputs[0].value,
+                script_pub_key: script_pub_key.to_vec(),
+            };
+            match spending.verify_input(0, &spent, flags) {
+                Ok(()) => Ok(()),
+                Err(BitcoinError::ScriptFailed(error)) => Err(error),
+                Err(error) => panic!("{}", error),
+            }
+        };
+        let p2pk = &coinbase.outputs[0].script_pub_key;
+        assert_eq!(verify(&high_s, p2pk, VerifyFlags::MANDATORY), Ok(()));
+        assert_eq!(
+            verify(&high_s, p2pk, VerifyFlags::STANDARD),
+            Err(ScriptError::SigHighS)
+        );
+        assert_eq!(verify(&not_der, p2pk, VerifyFlags::P2SH), Ok(()));
+        assert_eq!(
+            verify(&not_der, p2pk, VerifyFlags::MANDATORY),
+            Err(ScriptError::SigDer)
+        );
+        assert_eq!(
+            verify(&not_der, p2pk, VerifyFlags::LOW_S),
+            Err(ScriptError::SigDer)
+        );
+
+        // An undefined hash type changes the sighash too, only the error differs
+        let undefined_type = high_s.replace("2438 01", "2438 04");
+        assert_eq!(
+            verify(&undefined_type, p2pk, VerifyFlags::MANDATORY),
+            Err(ScriptError::EvalFalse)
+        );
+        assert_eq!(
+            verify(&undefined_type, p2pk, VerifyFlags::STRICTENC),
+            Err(ScriptError::SigHashType)
+        );
+
+        // Hybrid encoding of the same key, its y is odd. The script code is
+        // signed, so only the error differs again.
+        let mut hybrid = p2pk.clone();
+        hybrid[1] = 0x07;
+        assert_eq!(
+            verify(&high_s, &hybrid, VerifyFlags::MANDATORY),
+            Err(ScriptError::EvalFalse)
+        );
+        assert_eq!(
+            verify(&high_s, &hybrid, VerifyFlags::STRICTENC),
+            Err(ScriptError::PubkeyType)
+        );
+
+        // An empty signature fails the check without breaking the encoding rules
+        assert_eq!(
+            verify("", p2pk, VerifyFlags::STANDARD),
+            Err(ScriptError::EvalFalse)
+        );
+        assert_eq!(
+            verify("", &hybrid, VerifyFlags::STANDARD),
+            Err(ScriptError::PubkeyType)
+        );
+    }
+
+    #[test]
+    fn test_transaction_lock_times() {
+        let mut transaction =
+            Transaction::deserialize(&hex::decode(crate::TRANSACTION_SERIALIZED).unwrap()).unwrap();
+        transaction.locktime = 100;
+        transaction.inputs[0].sequence = 10;
+        let checker = TransactionSignatureChecker::new(&transaction, 0);
+        assert!(checker.check_lock_time(100));
+        assert!(!checker.check_lock_time(101));
+        assert!(!checker.check_lock_time(LOCKTIME_THRESHOLD));
+        // Relative lock times need version 2
+        assert!(!checker.check_sequence(10));
+
+        transaction.version = 2;
+        let checker = TransactionSignatureChecker::new(&transaction, 0);
+        assert!(checker.check_sequence(10));
+        assert!(!checker.check_sequence(11));
+        assert!(!checker.check_sequence(SEQUENCE_LOCKTIME_TYPE_FLAG | 10));
+
+        transaction.inputs[0].sequence = u32::MAX;
+        let checker = TransactionSignatureChecker::new(&transaction, 0);
+        assert!(!checker.check_lock_time(100));
+        assert!(!checker.check_sequence(10));
+    }
+
+    #[test]
+    fn test_arithmetic() {
+        assert_eq!(numbers("2 3 OP_ADD"), [5]);
+        assert_eq!(numbers("2 3 OP_SUB OP_ABS OP_NEGATE"), [-1]);
+        assert_eq!(numbers("0 OP_NOT 5 OP_NOT 7 OP_0NOTEQUAL"), [1, 0, 1]);
+        assert_eq!(numbers("3 7 OP_MIN 3 7 OP_MAX"), [3, 7]);
+        assert_eq!(numbers("1 0 OP_BOOLAND 1 0 OP_BOOLOR"), [0, 1]);
+        assert_eq!(numbers("2 2 5 OP_WITHIN 5 2 5 OP_WITHIN"), [1, 0]);
+        assert_eq!(
+            numbers("-5 -6 OP_LESSTHAN 4 4 OP_GREATERTHANOREQUAL"),
+            [0, 1]
+        );
+        assert_eq!(numbers("2147483647 OP_DUP OP_ADD"), [4294967294]);
+        assert_eq!(numbers("5 5 OP_NUMEQUALVERIFY"), Vec::<i64>::new());
+        assert_eq!(
+            error("5 6 OP_NUMEQUALVERIFY", VerifyFlags::NONE),
+            ScriptError::NumEqualVerify
+        );
+
+        // Results may be 5 bytes long but operands may not
+        assert_eq!(
+            error("2147483647 OP_DUP OP_ADD OP_1ADD", VerifyFlags::NONE),
+            ScriptError::InvalidNumber
+        );
+        // Negative zero and a padded one
+        assert_eq!(numbers("0x0180 OP_1ADD 0x020100 OP_1ADD"), [1, 2]);
+        assert_eq!(
+            error("0x020100 OP_1ADD", VerifyFlags::MINIMALDATA),
+            ScriptError::InvalidNumber
+        );
+        assert!(!cast_to_bool(&[0x00, 0x80]));
+        assert!(cast_to_bool(&[0x80, 0x00]));
+        assert!(!cast_to_bool(&[]));
+    }
+
+    #[test]
+    fn test_stack_operations() {
+        assert_eq!(numbers("1 2 3 4 5 6 OP_2ROT"), [3, 4, 5, 6, 1, 2]);
+        assert_eq!(numbers("1 2 3 4 OP_2SWAP"), [3, 4, 1, 2]);
+        assert_eq!(numbers("1 2 3 4 OP_2OVER"), [1, 2, 3, 4, 1, 2]);
+        assert_eq!(numbers("1 2 3 OP_3DUP OP_DEPTH"), [1, 2, 3, 1, 2, 3, 6]);
+        assert_eq!(numbers("1 2 3 OP_ROT"), [2, 3, 1]);
+        assert_eq!(numbers("1 2 OP_TUCK"), [2, 1, 2]);
+        assert_eq!(numbers("1 2 OP_NIP OP_DUP OP_OVER"), [2, 2, 2]);
+        assert_eq!(numbers("1 2 3 2 OP_PICK"), [1, 2, 3, 1]);
+        assert_eq!(numbers("1 2 3 2 OP_ROLL"), [2, 3, 1]);
+        assert_eq!(numbers("0 OP_IFDUP 1 OP_IFDUP"), [0, 1, 1]);
+        assert_eq!(numbers("1 OP_TOALTSTACK 2 OP_FROMALTSTACK"), [2, 1]);
+        assert_eq!(numbers("'abc' OP_SIZE OP_NIP"), [3]);
+
+        assert_eq!(
+            error("1 2 OP_PICK", VerifyFlags::NONE),
+            ScriptError::InvalidStackOperation
+        );
+        assert_eq!(
+            error("1 -1 OP_ROLL", VerifyFlags::NONE),
+            ScriptError::InvalidStackOperation
+        );
+        assert_eq!(
+            error("1 OP_SWAP", VerifyFlags::NONE),
+            ScriptError::InvalidStackOperation
+        );
+        assert_eq!(
+            error("OP_FROMALTSTACK", VerifyFlags::NONE),
+            ScriptError::InvalidAltstackOperation
+        );
+        assert_eq!(
+            error("1 2 OP_EQUALVERIFY", VerifyFlags::NONE),
+            ScriptError::EqualVerify
+        );
+        assert_eq!(error("0 OP_VERIFY", VerifyFlags::NONE), ScriptError::Verify);
+    }
+
+    #[test]
+    fn test_flow_control() {
+        assert_eq!(
+            numbers("1 OP_IF 0 OP_IF 2 OP_ELSE 3 OP_ENDIF OP_ELSE 4 OP_ENDIF"),
+            [3]
+        );
+        assert_eq!(
+            numbers("0 OP_IF 0 OP_IF 2 OP_ELSE 3 OP_ENDIF OP_ELSE 4 OP_ENDIF"),
+            [4]
+        );
+        assert_eq!(numbers("0 OP_NOTIF 5 OP_ENDIF"), [5]);
+        // Core allows several OP_ELSE, each one flipping the branch
+        assert_eq!(numbers("1 OP_IF 2 OP_ELSE 3 OP_ELSE 4 OP_ENDIF"), [2, 4]);
+
+        for unbalanced in ["1 OP_IF", "OP_ENDIF", "OP_ELSE", "OP_IF 1 OP_ENDIF"] {
+            assert_eq!(
+                error(unbalanced, VerifyFlags::NONE),
+                ScriptError::UnbalancedConditional,
+                "{}",
+                unbalanced
+            );
+        }
+
+        // Only executed branches fail on OP_RETURN and reserved opcodes, but
+        // disabled opcodes and OP_VERIF fail anywhere
+        assert_eq!(
+            numbers("0 OP_IF OP_RETURN OP_RESERVED OP_VER OP_ENDIF 1"),
+            [1]
+        );
+        assert_eq!(
+            error("0 OP_IF OP_CAT OP_ENDIF", VerifyFlags::NONE),
+            ScriptError::DisabledOpcode
+        );
+        assert_eq!(
+            error("0 OP_IF OP_VERIF OP_ENDIF", VerifyFlags::NONE),
+            ScriptError::BadOpcode
+        );
+        assert_eq!(error("OP_RETURN", VerifyFlags::NONE), ScriptError::OpReturn);
+        assert_eq!(
+            error("OP_RESERVED", VerifyFlags::NONE),
+            ScriptError::BadOpcode
+        );
+        assert_eq!(error("0x4c05", VerifyFlags::NONE), ScriptError::BadOpcode);
+    }
+
+    #[test]
+    fn test_hashes() {
+        for (opcode, digest) in [
+            ("OP_RIPEMD160", "9c1185a5c5e9fc54612808977ee8f548b2258d31"),
+            ("OP_SHA1", "da39a3ee5e6b4b0d3255bfef95601890afd80709"),
+            (
+                "OP_SHA256",
+                "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855",
+            ),
+            ("OP_HASH160", "b472a266d0bd89c13706a4132ccfb16f7c3b9fcb"),
+            (
+                "OP_HASH256",
+                "5df6e0e2761359d30a8275058e299fcc0381534545f55cf43e41983f5d4c9456",
+            ),
+        ] {
+            let stack = run(&format!("0 {}", opcode), VerifyFlags::NONE).unwrap();
+            assert_eq!(hex::encode(&stack[0]), digest, "{}", opcode);
+        }
+    }
+
+    #[test]
+    fn test_limits() {
+        let script = format!("1{}", " OP_NOP".repeat(MAX_OPS_PER_SCRIPT));
+        assert!(run(&script, VerifyFlags::NONE).is_ok());
+        assert_eq!(
+            error(&format!("{} OP_NOP", script), VerifyFlags::NONE),
+            ScriptError::OpCount
+        );
+        // Unexecuted opcodes count too
+        let script = format!("0 OP_IF{} OP_ENDIF", " OP_NOP".repeat(MAX_OPS_PER_SCRIPT));
+        assert_eq!(error(&script, VerifyFlags::NONE), ScriptError::OpCount);
+
+        let script = "1 ".repeat(MAX_STACK_SIZE);
+        assert!(run(&script, VerifyFlags::NONE).is_ok());
+        assert_eq!(
+            error(&format!("{} 1", script), VerifyFlags::NONE),
+            ScriptError::StackSize
+        );
+        let script = format!("{} OP_TOALTSTACK 1", script);
+        assert_eq!(error(&script, VerifyFlags::NONE), ScriptError::StackSize);
+
+        let push = |len: usize| {
+            ScriptBuilder::new()
+                .push_slice(&vec![0xaa; len])
+                .into_bytes()
+        };
+        let mut stack = Vec::new();
+        let checker = NullSignatureChecker;
+        assert!(eval(
+            &mut stack,
+            &push(MAX_SCRIPT_ELEMENT_SIZE),
+            VerifyFlags::NONE,
+            &checker
+        )
+        .is_ok());
+        assert_eq!(
+            eval(
+                &mut stack,
+                &push(MAX_SCRIPT_ELEMENT_SIZE + 1),
+                VerifyFlags::NONE,
+                &checker
+            ),
+            Err(ScriptError::PushSize)
+        );
+        let script = vec![OP_NOP; MAX_SCRIPT_SIZE + 1];
+        assert_eq!(
+            eval(&mut stack, &script, VerifyFlags::NONE, &checker),
+            Err(ScriptError::ScriptSize)
+        );
+    }
+
+    #[test]
+    fn test_minimal_data() {
+        let flags = VerifyFlags::MINIMALDATA;
+        assert_eq!(error("0x0105", flags), ScriptError::MinimalData);
+        assert_eq!(error("0x0181", flags), ScriptError::MinimalData);
+        assert_eq!(error("0x4c0100", flags), ScriptError::MinimalData);
+        assert_eq!(error("0x4c00", flags), ScriptError::MinimalData);
+        assert!(run("0x0105 0x4c00", VerifyFlags::NONE).is_ok());
+        assert!(run("0x0111 0 -1 5", flags).is_ok());
+    }
+
+    #[test]
+    fn test_lock_time_and_nops() {
+        let cltv = |asm: &str, flags: VerifyFlags| {
+            let script = Script::from_asm(asm).unwrap();
+            eval(&mut Vec::new(), &script, flags, &MockChecker::default())
+        };
+        let flags = VerifyFlags::CHECKLOCKTIMEVERIFY;
+        assert_eq!(cltv("500 OP_CHECKLOCKTIMEVERIFY", flags), Ok(()));
+        assert_eq!(
+            cltv("501 OP_CHECKLOCKTIMEVERIFY", flags),
+            Err(ScriptError::UnsatisfiedLocktime)
+        );
+        assert_eq!(
+            cltv("-1 OP_CHECKLOCKTIMEVERIFY", flags),
+            Err(ScriptError::NegativeLocktime)
+        );
+        assert_eq!(
+            cltv("OP_CHECKLOCKTIMEVERIFY", flags),
+            Err(ScriptError::InvalidStackOperation)
+        );
+        assert_eq!(
+            cltv("501 OP_CHECKLOCKTIMEVERIFY", VerifyFlags::NONE),
+            Ok(())
+        );
+        // The disable bit skips the check, the mock rejects every sequence
+        assert_eq!(
+            cltv(
+                "2147483648 OP_CHECKSEQUENCEVERIFY",
+                VerifyFlags::CHECKSEQUENCEVERIFY
+            ),
+            Ok(())
+        );
+        assert_eq!(
+            cltv("1 OP_CHECKSEQUENCEVERIFY", VerifyFlags::CHECKSEQUENCEVERIFY),
+            Err(ScriptError::UnsatisfiedLocktime)
+        );
+
+        assert!(run("OP_NOP1 OP_NOP4 OP_NOP10", VerifyFlags::NONE).is_ok());
+        assert_eq!(
+            error("OP_NOP4", VerifyFlags::DISCOURAGE_UPGRADABLE_NOPS),
+            ScriptError::DiscourageUpgradableNops
+        );
+    }
+
+    #[test]
+    fn test_checksig_script_code() {
+        let (sig, key) = (vec![0x30, 0x01], vec![0x02; 33]);
+        let checker = MockChecker {
+            valid: vec![(sig.clone(), key.clone())],
+            ..Default::default()
+        };
+        let script = ScriptBuilder::new()
+            .push_slice(&sig)
+            .push_slice(&key)
+            .push_opcode(OP_CHECKSIGVERIFY)
+            .push_slice(&sig)
+            .push_slice(&key)
+            .push_opcode(OP_CODESEPARATOR)
+            .push_opcode(OP_CHECKSIG)
+            .into_bytes();
+        let mut stack = Vec::new();
+        eval(&mut stack, &script, VerifyFlags::NONE, &checker).unwrap();
+        assert_eq!(stack, [vec![1]]);
+        let script_codes = checker.script_codes.borrow();
+        // The signature pushes are removed, and the second check only covers
+        // what follows the OP_CODESEPARATOR
+        let expected = ScriptBuilder::new()
+            .push_slice(&key)
+            .push_opcode(OP_CHECKSIGVERIFY)
+            .push_slice(&key)
+            .push_opcode(OP_CODESEPARATOR)
+            .push_opcode(OP_CHECKSIG)
+            .into_bytes();
+        assert_eq!(script_codes[0], expected);
+        assert_eq!(script_codes[1], [OP_CHECKSIG]);
+
+        assert_eq!(
+            eval(
+                &mut Vec::new(),
+                &script,
+                VerifyFlags::CONST_SCRIPTCODE,
+                &checker
+            ),
+            Err(ScriptError::SigFindAndDelete)
+        );
+        assert_eq!(
+            error("OP_CODESEPARATOR", VerifyFlags::CONST_SCRIPTCODE),
+            ScriptError::OpCodeSeparator
+        );
+    }
+
+    #[test]
+    fn test_checkmultisig() {
+        let keys: Vec<Vec<u8>> = (1..=3).map(|i| vec![i; 33]).collect();
+        let sigs: Vec<Vec<u8>> = (1..=3).map(|i| vec![0x30, i]).collect();
+        let checker = MockChecker {
+            valid: (0..3).map(|i| (sigs[i].clone(), keys[i].clone())).collect(),
+            ..Default::default()
+        };
+        let multisig = |dummy: &[u8], sigs: &[&Vec<u8>], required: i64| {
+            let mut builder = ScriptBuilder::new().push_slice(dummy);
+            for sig in sigs {
+                builder = builder.push_slice(sig);
+            }
+            builder = builder.push_int(required);
+            for key in &keys {
+                builder = builder.push_slice(key);
+            }
+            builder
+                .push_int(3)
+                .push_opcode(OP_CHECKMULTISIG)
+                .into_bytes()
+        };
+        let eval_multisig = |script: &[u8], flags: VerifyFlags| {
+            let mut stack = Vec::new();
+            eval(&mut stack, script, flags, &checker).map(|()| stack)
+        };
+
+        let script = multisig(&[], &[&sigs[0], &sigs[2]], 2);
+        assert_eq!(
+            eval_multisig(&script, VerifyFlags::NULLDUMMY),
+            Ok(vec![vec![1]])
+        );
+        // Signatures out of key order
+        let script = multisig(&[], &[&sigs[2], &sigs[0]], 2);
+        assert_eq!(eval_multisig(&script, VerifyFlags::NONE), Ok(vec![vec![]]));
+        assert_eq!(
+            eval_multisig(&script, VerifyFlags::NULLFAIL),
+            Err(ScriptError::SigNullFail)
+        );
+        let script = multisig(&[], &[&vec![], &vec![]], 2);
+        assert_eq!(
+            eval_multisig(&script, VerifyFlags::NULLFAIL),
+            Ok(vec![vec![]])
+        );
+
+        let script = multisig(&[0x01], &[&sigs[1]], 1);
+        assert_eq!(eval_multisig(&script, VerifyFlags::NONE), Ok(vec![vec![1]]));
+        assert_eq!(
+            eval_multisig(&script, VerifyFlags::NULLDUMMY),
+            Err(ScriptError::SigNullDummy)
+        );
+        // Without the dummy element
+        assert_eq!(
+            eval_multisig(&script[1..], VerifyFlags::NONE),
+            Err(ScriptError::InvalidStackOperation)
+        );
+        assert_eq!(
+            eval_multisig(&multisig(&[], &[], 4), VerifyFlags::NONE),
+            Err(ScriptError::SigCount)
+        );
+        assert_eq!(
+            error("21 OP_CHECKMULTISIG", VerifyFlags::NONE),
+            ScriptError::PubkeyCount
+        );
+
+        // Every key counts towards the operation limit
+        let script = format!(
+            "{}0 0 {}20 OP_CHECKMULTISIG",
+            "OP_NOP ".repeat(180),
+            "1 ".repeat(20)
+        );
+        assert_eq!(numbers(&script), [1]);
+        let script = format!("OP_NOP {}", script);
+        assert_eq!(error(&script, VerifyFlags::NONE), ScriptError::OpCount);
+    }
+
+    #[test]
+    fn test_p2sh() {
+        let redeem_script = Script::from_asm("OP_2 OP_3 OP_ADD OP_5 OP_EQUAL").unwrap();
+        let p2sh = |redeem_script: &[u8]| {
+            ScriptBuilder::new()
+                .push_opcode(OP_HASH160)
+                .push_slice(&Ripemd160::digest(Sha256::digest(redeem_script)))
+                .push_opcode(OP_EQUAL)
+                .into_bytes()
+        };
+        let verify = |script_sig: &[u8], script_pub_key: &[u8], flags: VerifyFlags| {
+            verify(script_sig, script_pub_key, flags, &NullSignatureChecker)
+        };
+
+        let script_sig = ScriptBuilder::new().push_slice(&redeem_script).into_bytes();
+        let flags = VerifyFlags::P2SH | VerifyFlags::CLEANSTACK;
+        assert_eq!(verify(&script_sig, &p2sh(&redeem_script), flags), Ok(()));
+
+        // Only the hash is checked before BIP16
+        let script_sig = ScriptBuilder::new().push_slice(&[0x00]).into_bytes();
+        assert_eq!(
+            verify(&script_sig, &p2sh(&[0x00]), VerifyFlags::NONE),
+            Ok(())
+        );
+        assert_eq!(
+            verify(&script_sig, &p2sh(&[0x00]), VerifyFlags::P2SH),
+            Err(ScriptError::EvalFalse)
+        );
+
+        let script_sig = ScriptBuilder::new()
+            .push_opcode(OP_NOP)
+            .push_slice(&redeem_script)
+            .into_bytes();
+        assert_eq!(
+            verify(&script_sig, &p2sh(&redeem_script), VerifyFlags::P2SH),
+            Err(ScriptError::SigPushOnly)
+        );
+        assert_eq!(
+            verify(&script_sig, &[OP_TRUE], VerifyFlags::SIGPUSHONLY),
+            Err(ScriptError::SigPushOnly)
+        );
+
+        let script_sig = ScriptBuilder::new()
+            .push_int(1)
+            .push_slice(&redeem_script)
+            .into_bytes();
+        assert_eq!(
+            verify(&script_sig, &p2sh(&redeem_script), flags),
+            Err(ScriptError::CleanStack)
+        );
+        assert_eq!(
+            verify(&[], &[], VerifyFlags::NONE),
+            Err(ScriptError::EvalFalse)
+        );
+    }
+
+    #[test]
+    fn test_find_and_delete() {
+        let find_and_delete = |script: &str, pattern: &str| {
+            let (script, _) = find_and_delete(
+                &hex::decode(script).unwrap(),
+                &hex::decode(pattern).unwrap(),
+            );
+            hex::encode(script)
+        };
+        // Core's script_FindAndDelete cases
+        assert_eq!(find_and_delete("5152", ""), "5152");
+        assert_eq!(find_and_delete("0302ff03", "0302ff03"), "");
+        assert_eq!(find_and_delete("0302ff030302ff03", "0302ff03"), "");
+        assert_eq!(
+            find_and_delete("0302ff030302ff03", "02"),
+            "0302ff030302ff03"
+        );
+        assert_eq!(
+            find_and_delete("0302ff030302ff03", "ff"),
+            "0302ff030302ff03"
+        );
+        assert_eq!(find_and_delete("0302ff030302ff03", "03"), "02ff0302ff03");
+        assert_eq!(find_and_delete("02feed5169", "feed51"), "02feed5169");
+        assert_eq!(find_and_delete("02feed5169", "02feed51"), "69");
+        assert_eq!(
+            find_and_delete("516902feed5169", "feed51"),
+            "516902feed5169"
+        );
+        assert_eq!(find_and_delete("516902feed5169", "02feed51"), "516969");
+        assert_eq!(find_and_delete("0003feed", "03feed"), "00");
+        assert_eq!(find_and_delete("0003feed", "00"), "03feed");
+        // Matches are only looked for at instruction boundaries
+        assert_eq!(find_and_delete("0301aa0101aa", "01aa"), "0301aa01");
+
+        assert_eq!(push_encoding(&[0x05]), [0x01, 0x05]);
+        assert_eq!(push_encoding(&[]), [OP_0]);
+        assert_eq!(push_encoding(&[0xaa; 76])[..2], [OP_PUSHDATA1, 76]);
+    }
+}
@@ -0,0 +1,258 @@
+use crate::{Address, Block, BlockHash, Network, Script, Transaction};
+use serde_json::{json, Map, Value};
+
+impl Transaction {
+    /// Same shape as Bitcoin Core's `decoderawtransaction`, with addresses
+    /// encoded for `network`. The output descriptor (`desc`) is left out.
+    /// Values are BTC as `f64`, so `50.0` where Core prints `50.00000000`:
+    /// compare parsed JSON against a node rather than text.
+    pub fn to_verbose_json(&self, network: Network) -> Value {
+        let coinbase = self.is_coinbase();
+        let vin: Vec<Value> = self
+            .inputs
+            .iter()
+            .map(|input| {
+                let mut entry = Map::new();
+                if coinbase {
+                    entry.insert("coinbase".into(), json!(hex::encode(&input.script_sig)));
+                } else {
+                    entry.insert("txid".into(), json!(input.previous_output.hash.to_string()));
+                    entry.insert("vout".into(), json!(input.previous_output.index));
+                    entry.insert(
+                        "scriptSig".into(),
+                        json!({
+                            "asm": Script::new(&input.script_sig).to_asm(true),
+                            "hex": hex::encode(&input.script_sig),
+                        }),
+                    );
+                }
+                if !input.witness.is_empty() {
+                    let witness: Vec<String> = input.witness.iter().map(hex::encode).collect();
+                    entry.insert("txinwitness".into(), json!(witness));
+                }
+                entry.insert("sequence".into(), json!(input.sequence));
+                Value::Object(entry)
+            })
+            .collect();
+
+        let vout: Vec<Value> = self
+            .outputs
+            .iter()
+            .enumerate()
+            .map(|(n, output)| {
+                let script = Script::new(&output.script_pub_key);
+                let mut script_pub_key = Map::new();
+                script_pub_key.insert("asm".into(), json!(script.to_asm(false)));
+                script_pub_key.insert("hex".into(), json!(hex::encode(script.as_bytes())));
+                if let Ok(address) = Address::from_script(script.as_bytes()) {
+                    script_pub_key.insert("address".into(), json!(address.to_string_in(network)));
+                }
+                script_pub_key.insert("type".into(), json!(script.script_type().name()));
+                json!({
+                    "value": output.value.to_btc(),
+                    "n": n,
+                    "scriptPubKey": script_pub_key,
+                })
+            })
+            .collect();
+
+        json!({
+            "txid": self.txid().to_string(),
+            "hash": self.wtxid().to_string(),
+            "version": self.version,
+            "size": self.total_size(),
+            "vsize": self.vsize(),
+            "weight": self.weight(),
+            "locktime": self.locktime,
+            "vin": vin,
+            "vout": vout,
+        })
+    }
+}
+
+impl Block {
+    /// Same shape as Bitcoin Core's `getblock <hash> 2`, without the fields that
+    /// depend on the chain state (confirmations, height, chainwork, ...). See
+    /// [`Transaction::to_verbose_json`] for what differs in the transactions.
+    pub fn to_verbose_json(&self, network: Network) -> Value {
+        let header = &self.block_header;
+        let transactions: Vec<Value> = self
+            .transactions
+            .iter()
+            .map(|transaction| {
+                let mut entry = transaction.to_verbose_json(network);
+                entry["hex"] = json!(hex::encode(transaction.serialize()));
+                entry
+            })
+            .collect();
+
+        let mut block = Map::new();
+        block.insert("hash".into(), json!(header.block_hash().to_string()));
+        block.insert("version".into(), json!(header.version));
+        block.insert(
+            "versionHex".into(),
+            json!(format!("{:08x}", header.version)),
+        );
+        block.insert(
+            "merkleroot".into(),
+            json!(header.merkle_root_hash.to_string()),
+        );
+        block.insert("time".into(), json!(header.timestamp));
+        block.insert("nonce".into(), json!(header.nonce));
+        block.insert("bits".into(), json!(format!("{:08x}", header.bits)));
+        block.insert("difficulty".into(), json!(header.difficulty()));
+        block.insert("nTx".into(), json!(self.transactions.len()));
+        if header.previous_block_hash != BlockHash::all_zeros() {
+            block.insert(
+                "previousblockhash".into(),
+                json!(header.previous_block_hash.to_string()),
+            );
+        }
+        block.insert("strippedsize".into(), json!(self.stripped_size()));
+        block.insert("size".into(), json!(self.total_size()));
+        block.insert("weight".into(), json!(self.weight()));
+        block.insert("tx".into(), json!(transactions));
+        Value::Object(block)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BLOCK_170: &str = "0100000055bd840a78798ad0da853f68974f3d183e2bd1db6a842c1feecf222a00000000ff104ccb05421ab93e63f8c3ce5c2c2e9dbb37de2764b3a3175c8166562cac7d51b96a49ffff001d283e9e700201000000010000000000000000000000000000000000000000000000000000000000000000ffffffff0704ffff001d0102ffffffff0100f2052a01000000434104d46c4968bde02899d2aa0963367c7a6ce34eec332b32e42e5f3407e052d64ac625da6f0718e7b302140434bd725706957c092db53805b821a85b23a7ac61725bac000000000100000001c997a5e56e104102fa209c6a852dd90660a20b2d9c352423edce25857fcd3704000000004847304402204e45e16932b8af514961a1d3a1a25fdf3f4f7732e9d624c6c61548ab5fb8cd410220181522ec8eca07de4860a4acdd12909d831cc56cbbac4622082221a8768d1d0901ffffffff0200ca9a3b00000000434104ae1a62fe09c5f51b13905f07f06b99a2f7159b2225f374cd378d71302fa28414e7aab37397f554a7df5f142c21c1b7303b8a0626f1baded5c72a704f7e6cd84cac00286bee0000000043410411db93e1dcdb8a016b49840f8c53bc1eb68a382e97b1482ecad7b148a6909a5cb2e0eaddfb84ccf9744464f82e160bfa9b8b64f9d4c03f999b8643f656b412a3ac00000000";
+
+    #[test]
+    fn test_block_170_verbose_json() {
+        let block = Block::deserialize(&hex::decode(BLOCK_170).unwrap()).unwrap();
+        let json = block.to_verbose_json(Network::Bitcoin);
+
+        assert_eq!(
+            json["hash"],
+            "00000000d1145790a8694403d4063f323d499e655c83426834d4ce2f8dd4a2ee"
+        );
+        assert_eq!(
+            json["merkleroot"],
+            "7dac2c5666815c17a3b36427de37bb9d2e2c5ccec3f8633eb91a4205cb4c10ff"
+        );
+        assert_eq!(
+            json["previousblockhash"],
+            "000000002a22cfee1f2c846adbd12b3e183d4f97683f85dad08a79780a84bd55"
+        );
+        assert_eq!(json["versionHex"], "00000001");
+        assert_eq!(json["bits"], "1d00ffff");
+        assert_eq!(json["difficulty"], 1.0);
+        assert_eq!(json["time"], 1231731025);
+        assert_eq!(json["nonce"], 1889418792);
+        assert_eq!(json["nTx"], 2);
+        assert_eq!(json["size"], 490);
+        assert_eq!(json["strippedsize"], 490);
+        assert_eq!(json["weight"], 1960);
+
+        let keys: Vec<&str> = json
+            .as_object()
+            .unwrap()
+            .keys()
+            .map(|k| k.as_str())
+            .collect();
+        assert_eq!(
+            keys,
+            [
+                "hash",
+                "version",
+                "versionHex",
+                "merkleroot",
+                "time",
+                "nonce",
+                "bits",
+                "difficulty",
+                "nTx",
+                "previousblockhash",
+                "strippedsize",
+                "size",
+                "weight",
+                "tx"
+            ]
+        );
+
+        let coinbase = &json["tx"][0];
+        assert_eq!(coinbase["vin"][0]["coinbase"], "04ffff001d0102");
+        assert_eq!(coinbase["vin"][0]["sequence"], 4294967295u32);
+        assert_eq!(coinbase["vout"][0]["value"], 50.0);
+
+        let transaction = &json["tx"][1];
+        assert_eq!(
+            transaction["txid"],
+            "f4184fc596403b9d638783cf57adfe4c75c605f6356fbc91338530e9831e9e16"
+        );
+        assert_eq!(transaction["hash"], transaction["txid"]);
+        assert_eq!(transaction["size"], 275);
+        assert_eq!(transaction["vsize"], 275);
+        assert_eq!(transaction["weight"], 1100);
+        assert_eq!(transaction["hex"], crate::TRANSACTION_SERIALIZED);
+
+        let vin = &transaction["vin"][0];
+        assert_eq!(
+            vin["txid"],
+            "0437cd7f8525ceed2324359c2d0ba26006d92d856a9c20fa0241106ee5a597c9"
+        );
+        assert_eq!(vin["vout"], 0);
+        assert_eq!(
+            vin["scriptSig"]["asm"],
+            "304402204e45e16932b8af514961a1d3a1a25fdf3f4f7732e9d624c6c61548ab5fb8cd410220181522ec8eca07de4860a4acdd12909d831cc56cbbac4622082221a8768d1d09[ALL]"
+        );
+
+        let vout = &transaction["vout"];
+        assert_eq!(vout[0]["value"], 10.0);
+        assert_eq!(vout[1]["value"], 40.0);
+        assert_eq!(vout[1]["n"], 1);
+        assert_eq!(
+            vout[0]["scriptPubKey"]["asm"],
+            "04ae1a62fe09c5f51b13905f07f06b99a2f7159b2225f374cd378d71302fa28414e7aab37397f554a7df5f142c21c1b7303b8a0626f1baded5c72a704f7e6cd84c OP_CHECKSIG"
+        );
+        assert_eq!(vout[0]["scriptPubKey"]["type"], "pubkey");
+        // P2PK outputs have no address
+        assert!(vout[0]["scriptPubKey"].get("address").is_none());
+    }
+
+    #[test]
+    fn test_segwit_transaction_verbose_json() {
+        let transaction = Transaction::deserialize(&hex::decode("02000000000101595895ea20179de87052b4046dfe6fd515860505d6511a9004cf12a1f93cac7c0100000000ffffffff01deb807000000000017a9140f3444e271620c736808aa7b33e370bd87cb5a078702483045022100fb60dad8df4af2841adc0346638c16d0b8035f5e3f3753b88db122e70c79f9370220756e6633b17fd2710e626347d28d60b0a2d6cbb41de51740644b9fb3ba7751040121028fa937ca8cba2197a37c007176ed8941055d3bcb8627d085e94553e62f057dcc00000000").unwrap()).unwrap();
+        let json = transaction.to_verbose_json(Network::Bitcoin);
+
+        assert_eq!(
+            json["txid"],
+            "f5864806e3565c34d1b41e716f72609d00b55ea5eac5b924c9719a842ef42206"
+        );
+        assert_eq!(
+            json["hash"],
+            "80b7d8a82d5d5bf92905b06f2014dd699e03837ca172e3a59d51426ebbe3e7f5"
+        );
+        assert_eq!(json["vsize"], 111);
+        assert_eq!(json["weight"], 442);
+        assert_eq!(json["vin"][0]["scriptSig"]["hex"], "");
+        assert_eq!(json["vin"][0]["txinwitness"].as_array().unwrap().len(), 2);
+        assert_eq!(json["vout"][0]["value"], 0.00506078);
+        assert_eq!(
+            json["vout"][0]["scriptPubKey"]["asm"],
+            "OP_HASH160 0f3444e271620c736808aa7b33e370bd87cb5a07 OP_EQUAL"
+        );
+        assert_eq!(
+            json["vout"][0]["scriptPubKey"]["address"],
+            "335Qg979J2ZDHzBzMuisgikxnfEi9ERNU7"
+        );
+        assert_eq!(json["vout"][0]["scriptPubKey"]["type"], "scripthash");
+        let keys: Vec<&str> = json["vout"][0]["scriptPubKey"]
+            .as_object()
+            .unwrap()
+            .keys()
+            .map(|k| k.as_str())
+            .collect();
+        assert_eq!(keys, ["asm", "hex", "address", "type"]);
+        assert_eq!(
+            transaction.to_verbose_json(Network::Testnet)["vout"][0]["scriptPubKey"]["address"],
+            "2Mtdcjt3AuV4ZVmpY33LkJfkE11SsyqdAvj"
+        );
+        assert!(json.get("hex").is_none());
+    }
+}
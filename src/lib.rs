@@ -1,19 +1,72 @@
+mod address;
+mod amount;
 mod block;
+mod block_file;
 mod block_header;
+mod decoder;
+mod encode;
 mod error;
+mod hash_types;
+mod interpreter;
+mod json;
 mod merkle_root;
 mod miner;
+mod network;
+pub mod opcodes;
+mod psbt;
+mod script;
+mod script_type;
+mod secp256k1;
+#[cfg(feature = "serde")]
+mod serde_utils;
+mod sighash;
+mod sigops;
 mod transaction;
+mod uint;
 mod utils;
+mod view;
 
+pub use address::Address;
+pub use amount::{Amount, Denomination};
 pub use block::Block;
+pub use block_file::{
+    BlockFileReader, MAINNET_MAGIC, REGTEST_MAGIC, SIGNET_MAGIC, TESTNET4_MAGIC, TESTNET_MAGIC,
+};
 pub use block_header::BlockHeader;
-pub use error::{BitcoinError, Result};
+pub use decoder::Decoder;
+pub use encode::{deserialize, serialize, CompactSize, Decodable, Encodable};
+pub use error::{AddressError, BitcoinError, Result, ScriptError, Secp256k1Error, SighashError};
+pub use hash_types::{BlockHash, Sighash, TxMerkleNode, Txid, WitnessMerkleNode, Wtxid};
+pub use interpreter::{
+    cast_to_bool, eval_script, verify_script, NullSignatureChecker, SignatureChecker,
+    TransactionSignatureChecker, VerifyFlags, MAX_OPS_PER_SCRIPT, MAX_PUBKEYS_PER_MULTISIG,
+    MAX_SCRIPT_ELEMENT_SIZE, MAX_SCRIPT_SIZE, MAX_STACK_SIZE,
+};
 pub use merkle_root::MerkleRoot;
 pub use miner::Miner;
+pub use network::Network;
+pub use psbt::{KeySource, Psbt, PsbtInput, PsbtOutput};
+pub use script::{Instruction, Instructions, Script, ScriptBuilder};
+pub use script_type::{ScriptType, MAX_OP_RETURN_RELAY};
+pub use secp256k1::{is_valid_signature_encoding, EcdsaSignature, PublicKey};
+pub use sighash::{
+    tap_leaf_hash, PrevoutProvider, SighashCache, TapscriptExtension, SIGHASH_ALL,
+    SIGHASH_ANYONECANPAY, SIGHASH_DEFAULT, SIGHASH_NONE, SIGHASH_SINGLE, TAPROOT_LEAF_TAPSCRIPT,
+};
 pub use transaction::{OutPoint, Transaction, TransactionInput, TransactionOutput};
+pub use uint::U256;
+pub use view::{BlockView, InputView, Inputs, OutputView, Outputs, TransactionView, WitnessView};
 
+pub const WITNESS_SCALE_FACTOR: usize = 4;
+pub const MAX_BLOCK_WEIGHT: usize = 4_000_000;
+/// Limit on the sigop cost of a block, legacy and P2SH sigops costing
+/// [`WITNESS_SCALE_FACTOR`] each.
+pub const MAX_BLOCK_SIGOPS_COST: usize = 80_000;
+/// Upper bound on the serialized size of a block, and so of anything inside one.
+pub const MAX_BLOCK_SERIALIZED_SIZE: usize = 4_000_000;
 pub const DIFFICULTY_TARGET: u32 = 0x1e0377ae;
 pub const PREVIOUS_BLOCK_HASH: &str =
     "000000002a22cfee1f2c846adbd12b3e183d4f97683f85dad08a79780a84bd55";
 pub const TRANSACTION_SERIALIZED: &str = "0100000001c997a5e56e104102fa209c6a852dd90660a20b2d9c352423edce25857fcd3704000000004847304402204e45e16932b8af514961a1d3a1a25fdf3f4f7732e9d624c6c61548ab5fb8cd410220181522ec8eca07de4860a4acdd12909d831cc56cbbac4622082221a8768d1d0901ffffffff0200ca9a3b00000000434104ae1a62fe09c5f51b13905f07f06b99a2f7159b2225f374cd378d71302fa28414e7aab37397f554a7df5f142c21c1b7303b8a0626f1baded5c72a704f7e6cd84cac00286bee0000000043410411db93e1dcdb8a016b49840f8c53bc1eb68a382e97b1482ecad7b148a6909a5cb2e0eaddfb84ccf9744464f82e160bfa9b8b64f9d4c03f999b8643f656b412a3ac00000000";
+/// Coinbase of block 9, whose P2PK output the transaction above spends.
+pub const BLOCK_9_COINBASE_SERIALIZED: &str = "01000000010000000000000000000000000000000000000000000000000000000000000000ffffffff0704ffff001d0134ffffffff0100f2052a0100000043410411db93e1dcdb8a016b49840f8c53bc1eb68a382e97b1482ecad7b148a6909a5cb2e0eaddfb84ccf9744464f82e160bfa9b8b64f9d4c03f999b8643f656b412a3ac00000000";
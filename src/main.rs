@@ -1,6 +1,7 @@
 use mine_block::{
-    Block, BlockHeader, MerkleRoot, Miner, OutPoint, Transaction, TransactionInput,
-    TransactionOutput, DIFFICULTY_TARGET, PREVIOUS_BLOCK_HASH, TRANSACTION_SERIALIZED,
+    opcodes::OP_TRUE, Address, Amount, Block, BlockHash, BlockHeader, MerkleRoot, Miner, Network,
+    Transaction, TransactionOutput, VerifyFlags, BLOCK_9_COINBASE_SERIALIZED, DIFFICULTY_TARGET,
+    PREVIOUS_BLOCK_HASH, TRANSACTION_SERIALIZED,
 };
 use std::{
     fs::File,
@@ -11,36 +12,34 @@ fn main() -> anyhow::Result<()> {
     let transaction_payload = hex::decode(TRANSACTION_SERIALIZED)?;
     let transaction = Transaction::deserialize(&transaction_payload)?;
 
-    let coinbase_transaction = Transaction {
-        version: 1,
-        inputs: vec![TransactionInput {
-            previous_output: OutPoint {
-                hash: [0; 32],
-                index: 0xFFFFFFFF,
-            },
-            script_sig: b"erickcestari".to_vec(),
-            sequence: 0xFFFFFFFF,
-        }],
-        outputs: vec![TransactionOutput {
-            value: 5000000000,
-            script_pub_key: vec![51],
-        }],
-        locktime: 0,
+    // No point mining a block whose transaction does not spend its input
+    let spent_coinbase = Transaction::deserialize(&hex::decode(BLOCK_9_COINBASE_SERIALIZED)?)?;
+    transaction.verify_input(0, &spent_coinbase.outputs[0], VerifyFlags::P2SH)?;
+
+    // Anyone can spend the reward unless a mainnet address is given
+    let script_pub_key = match std::env::args().nth(1) {
+        Some(address) => Address::from_str_in(&address, Network::Bitcoin)?.script_pub_key(),
+        None => vec![OP_TRUE],
     };
 
-    let previous_block_hash: [u8; 32] = hex::decode(PREVIOUS_BLOCK_HASH)?
-        .try_into()
-        .map_err(|e: Vec<u8>| anyhow::anyhow!("Conversion error: {:?}", e))?;
+    // Block 170 sits on top of block 169
+    let coinbase_transaction = Transaction::coinbase(
+        170,
+        0,
+        b"erickcestari",
+        vec![TransactionOutput {
+            value: Amount::from_int_btc(50),
+            script_pub_key,
+        }],
+    )?;
 
-    let timestamp = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs() as u32;
+    // The constant is in RPC display order, the header stores internal byte order
+    let previous_block_hash: BlockHash = PREVIOUS_BLOCK_HASH.parse()?;
 
-    let mut transactions_txid = Vec::new();
-    let txid = transaction.txid();
-    transactions_txid.push(txid.as_slice());
-    let txid = coinbase_transaction.txid();
-    transactions_txid.push(txid.as_slice());
+    let timestamp = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs() as u32;
 
-    let merkle_root_hash = MerkleRoot::calculate(&transactions_txid);
+    let merkle_root_hash =
+        MerkleRoot::calculate(&[coinbase_transaction.txid(), transaction.txid()]);
 
     let block_header = BlockHeader {
         version: 1,
@@ -60,7 +59,10 @@ fn main() -> anyhow::Result<()> {
     let block_mined = miner.mine();
     match block_mined {
         Some(block) => {
-            println!("valid block found {:?}", block);
+            println!(
+                "valid block found {}",
+                serde_json::to_string_pretty(&block.to_verbose_json(Network::Bitcoin))?
+            );
             let mut file = File::create("block.txt")?;
             writeln!(file, "{}", hex::encode(block.serialize()))?;
         }
@@ -1,46 +1,56 @@
 use sha2::{Digest, Sha256};
 
+use crate::{TxMerkleNode, Txid, WitnessMerkleNode, Wtxid};
+
 #[derive(Debug)]
 pub struct MerkleRoot {
     pub hashes: Vec<[u8; 32]>,
 }
 
 impl MerkleRoot {
-    pub fn calculate(hashes: &[&[u8]]) -> [u8; 32] {
-        if hashes.is_empty() {
+    pub fn calculate(txids: &[Txid]) -> TxMerkleNode {
+        TxMerkleNode::from_byte_array(Self::root(txids.iter().map(|txid| txid.to_byte_array())))
+    }
+
+    /// BIP141 witness root. The caller is responsible for zeroing the coinbase wtxid.
+    pub fn calculate_witness(wtxids: &[Wtxid]) -> WitnessMerkleNode {
+        WitnessMerkleNode::from_byte_array(Self::root(
+            wtxids.iter().map(|wtxid| wtxid.to_byte_array()),
+        ))
+    }
+
+    fn root(hashes: impl Iterator<Item = [u8; 32]>) -> [u8; 32] {
+        let mut current_level: Vec<[u8; 32]> = hashes.collect();
+        if current_level.is_empty() {
             return [0u8; 32];
         }
 
-        let mut current_level: Vec<Vec<u8>> = hashes.iter().map(|h| h.to_vec()).collect();
-
         while current_level.len() > 1 {
             let mut next_level = Vec::new();
 
             if current_level.len() % 2 == 1 {
                 // Odd number of hashes
                 // so it is safe to just push the last one
-                current_level.push(current_level.last().unwrap().clone());
+                current_level.push(*current_level.last().unwrap());
             }
 
             for chunk in current_level.chunks(2) {
                 let mut hasher = Sha256::new();
-                hasher.update(&chunk[0]);
-                hasher.update(&chunk[1]);
+                hasher.update(chunk[0]);
+                hasher.update(chunk[1]);
                 let first_hash = hasher.finalize();
 
                 let mut hasher = Sha256::new();
-                hasher.update(&first_hash);
+                hasher.update(first_hash);
                 let double_hash = hasher.finalize();
 
-                next_level.push(double_hash.to_vec());
+                next_level.push(double_hash.into());
             }
 
             current_level = next_level;
         }
 
-        let mut result = [0u8; 32];
-        result.copy_from_slice(&current_level[0]);
-        result
+        current_level[0]
     }
 }
 
@@ -49,35 +59,39 @@ mod tests {
     use super::*;
     use sha2::{Digest, Sha256};
 
+    fn txid(hash: &[u8]) -> Txid {
+        Txid::from_byte_array(hash.try_into().unwrap())
+    }
+
     #[test]
     fn test_empty_merkle_root() {
         let result = MerkleRoot::calculate(&[]);
-        assert_eq!(result, [0u8; 32]);
+        assert_eq!(result, TxMerkleNode::all_zeros());
     }
 
     #[test]
     fn test_single_node() {
         let data = b"hello";
         let hash = Sha256::digest(Sha256::digest(data));
-        let input = [hash.as_slice()];
+        let input = [Txid::from_byte_array(hash.into())];
         let result = MerkleRoot::calculate(&input);
-        assert_eq!(result, hash.as_slice());
+        assert_eq!(result.as_byte_array(), hash.as_slice());
     }
 
     #[test]
     fn test_two_nodes() {
         let a = Sha256::digest(Sha256::digest(b"a"));
         let b = Sha256::digest(Sha256::digest(b"b"));
-        let input = [a.as_slice(), b.as_slice()];
+        let input = [txid(&a), txid(&b)];
 
         let mut concat = a.to_vec();
         concat.extend_from_slice(&b);
         let first = Sha256::digest(&concat);
-        let second = Sha256::digest(&first);
+        let second = Sha256::digest(first);
         let expected = second;
 
         let result = MerkleRoot::calculate(&input);
-        assert_eq!(result[..], expected[..]);
+        assert_eq!(result.as_byte_array()[..], expected[..]);
     }
 
     #[test]
@@ -85,24 +99,24 @@ mod tests {
         let a = Sha256::digest(Sha256::digest(b"a"));
         let b = Sha256::digest(Sha256::digest(b"b"));
         let c = Sha256::digest(Sha256::digest(b"c"));
-        let input = [a.as_slice(), b.as_slice(), c.as_slice()];
+        let input = [txid(&a), txid(&b), txid(&c)];
 
-        let d = c.clone();
+        let d = c;
 
         let ab_concat = [a.as_slice(), b.as_slice()].concat();
         let ab_first = Sha256::digest(&ab_concat);
-        let ab_hash = Sha256::digest(&ab_first);
+        let ab_hash = Sha256::digest(ab_first);
 
         let cd_concat = [c.as_slice(), d.as_slice()].concat();
         let cd_first = Sha256::digest(&cd_concat);
-        let cd_hash = Sha256::digest(&cd_first);
+        let cd_hash = Sha256::digest(cd_first);
 
         let ab_cd_concat = [ab_hash.to_vec(), cd_hash.to_vec()].concat();
         let ab_cd_first = Sha256::digest(&ab_cd_concat);
-        let expected_root = Sha256::digest(&ab_cd_first);
+        let expected_root = Sha256::digest(ab_cd_first);
 
         let result = MerkleRoot::calculate(&input);
-        assert_eq!(result[..], expected_root[..]);
+        assert_eq!(result.as_byte_array()[..], expected_root[..]);
     }
 
     #[test]
@@ -111,21 +125,21 @@ mod tests {
         let b = Sha256::digest(Sha256::digest(b"b"));
         let c = Sha256::digest(Sha256::digest(b"c"));
         let d = Sha256::digest(Sha256::digest(b"d"));
-        let input = [a.as_slice(), b.as_slice(), c.as_slice(), d.as_slice()];
+        let input = [txid(&a), txid(&b), txid(&c), txid(&d)];
 
         let ab_concat = [a.as_slice(), b.as_slice()].concat();
         let ab_first = Sha256::digest(&ab_concat);
-        let ab_hash = Sha256::digest(&ab_first);
+        let ab_hash = Sha256::digest(ab_first);
 
         let cd_concat = [c.as_slice(), d.as_slice()].concat();
         let cd_first = Sha256::digest(&cd_concat);
-        let cd_hash = Sha256::digest(&cd_first);
+        let cd_hash = Sha256::digest(cd_first);
 
         let ab_cd_concat = [ab_hash.to_vec(), cd_hash.to_vec()].concat();
         let ab_cd_first = Sha256::digest(&ab_cd_concat);
-        let expected_root = Sha256::digest(&ab_cd_first);
+        let expected_root = Sha256::digest(ab_cd_first);
 
         let result = MerkleRoot::calculate(&input);
-        assert_eq!(result[..], expected_root[..]);
+        assert_eq!(result.as_byte_array()[..], expected_root[..]);
     }
 }
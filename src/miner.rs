@@ -1,4 +1,4 @@
-use crate::{utils, Block};
+use crate::{Block, U256};
 use rayon::prelude::*;
 use sha2::{Digest, Sha256};
 use std::sync::atomic::{AtomicBool, Ordering};
@@ -22,7 +22,7 @@ impl Miner {
             .map(|n| n.get())
             .unwrap_or(1);
 
-        let target = utils::bits_to_target(self.block.block_header.bits);
+        let target = self.block.block_header.target();
         let found = Arc::new(AtomicBool::new(false));
 
         let block_header_serialized = self.block.block_header.serialize();
@@ -44,7 +44,7 @@ impl Miner {
                     start_nonce + nonce_range - 1
                 };
 
-                let mut local_header = block_header_serialized.clone();
+                let mut local_header = block_header_serialized;
                 let mut hasher = Sha256::new();
                 let mut hash_buffer = [0u8; 32];
 
@@ -58,16 +58,14 @@ impl Miner {
 
                     local_header[76..80].copy_from_slice(&nonce.to_le_bytes());
 
-                    hasher.update(&local_header);
+                    hasher.update(local_header);
                     let first_hash = hasher.finalize_reset();
 
-                    hasher.update(&first_hash);
+                    hasher.update(first_hash);
                     hash_buffer.copy_from_slice(&hasher.finalize_reset());
                     hasher.reset();
 
-                    hash_buffer.reverse();
-
-                    if hash_buffer < target {
+                    if U256::from_le_bytes(hash_buffer) <= target {
                         found.store(true, Ordering::Relaxed);
 
                         let mut mined_block = self.block.clone();
@@ -89,7 +87,9 @@ impl Miner {
 
 #[cfg(test)]
 mod tests {
-    use crate::{merkle_root, BlockHeader, Transaction, TRANSACTION_SERIALIZED};
+    use crate::{
+        merkle_root, BlockHeader, Transaction, PREVIOUS_BLOCK_HASH, TRANSACTION_SERIALIZED,
+    };
 
     use super::*;
 
@@ -104,19 +104,14 @@ mod tests {
         let transaction = Transaction::deserialize(&transaction_bytes).unwrap();
 
         let merkle_root_hash =
-            merkle_root::MerkleRoot::calculate(&[&coinbase.txid(), &transaction.txid()]);
+            merkle_root::MerkleRoot::calculate(&[coinbase.txid(), transaction.txid()]);
 
         let block_header = BlockHeader {
             bits: mainet_bits,
             nonce: 0,
             timestamp,
             version: 1,
-            previous_block_hash: hex::decode(
-                "55bd840a78798ad0da853f68974f3d183e2bd1db6a842c1feecf222a00000000",
-            )
-            .unwrap()
-            .try_into()
-            .unwrap(),
+            previous_block_hash: PREVIOUS_BLOCK_HASH.parse().unwrap(),
             merkle_root_hash,
         };
 
@@ -130,4 +125,26 @@ mod tests {
 
         assert_eq!(block.block_header.nonce, 1889418792);
     }
+
+    #[test]
+    fn test_mine_regtest_target() {
+        let transaction =
+            Transaction::deserialize(&hex::decode(TRANSACTION_SERIALIZED).unwrap()).unwrap();
+        let block_header = BlockHeader {
+            version: 1,
+            previous_block_hash: PREVIOUS_BLOCK_HASH.parse().unwrap(),
+            merkle_root_hash: merkle_root::MerkleRoot::calculate(&[transaction.txid()]),
+            timestamp: 0x496ab951,
+            bits: 0x207fffff,
+            nonce: 0,
+        };
+        let block = Miner::new(Block {
+            block_header,
+            transactions: vec![transaction],
+        })
+        .mine()
+        .unwrap();
+
+        block.block_header.validate_pow().unwrap();
+    }
 }
@@ -0,0 +1,99 @@
+use std::{fmt, str::FromStr};
+
+use crate::{
+    BitcoinError, Result, MAINNET_MAGIC, REGTEST_MAGIC, SIGNET_MAGIC, TESTNET4_MAGIC, TESTNET_MAGIC,
+};
+
+/// Chain a block file or address belongs to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Network {
+    Bitcoin,
+    Testnet,
+    Testnet4,
+    Signet,
+    Regtest,
+}
+
+impl Network {
+    pub const ALL: [Network; 5] = [
+        Network::Bitcoin,
+        Network::Testnet,
+        Network::Testnet4,
+        Network::Signet,
+        Network::Regtest,
+    ];
+
+    /// Message start bytes, which also prefix every block in the `blk*.dat` files.
+    pub fn magic(self) -> [u8; 4] {
+        match self {
+            Network::Bitcoin => MAINNET_MAGIC,
+            Network::Testnet => TESTNET_MAGIC,
+            Network::Testnet4 => TESTNET4_MAGIC,
+            Network::Signet => SIGNET_MAGIC,
+            Network::Regtest => REGTEST_MAGIC,
+        }
+    }
+
+    /// Base58 version byte of P2PKH addresses.
+    pub fn p2pkh_prefix(self) -> u8 {
+        match self {
+            Network::Bitcoin => 0x00,
+            _ => 0x6f,
+        }
+    }
+
+    /// Base58 version byte of P2SH addresses.
+    pub fn p2sh_prefix(self) -> u8 {
+        match self {
+            Network::Bitcoin => 0x05,
+            _ => 0xc4,
+        }
+    }
+
+    /// Human readable part of segwit addresses.
+    pub fn bech32_hrp(self) -> &'static str {
+        match self {
+            Network::Bitcoin => "bc",
+            Network::Testnet | Network::Testnet4 | Network::Signet => "tb",
+            Network::Regtest => "bcrt",
+        }
+    }
+}
+
+/// Core's chain name, as accepted by `-chain=`.
+impl fmt::Display for Network {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Network::Bitcoin => "main",
+            Network::Testnet => "test",
+            Network::Testnet4 => "testnet4",
+            Network::Signet => "signet",
+            Network::Regtest => "regtest",
+        })
+    }
+}
+
+impl FromStr for Network {
+    type Err = BitcoinError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Network::ALL
+            .into_iter()
+            .find(|network| network.to_string() == s)
+            .ok_or_else(|| BitcoinError::InvalidPayload(format!("unknown network {:?}", s)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_chain_names() {
+        for network in Network::ALL {
+            assert_eq!(network.to_string().parse::<Network>().unwrap(), network);
+        }
+        assert_eq!("signet".parse::<Network>().unwrap().magic(), SIGNET_MAGIC);
+        assert!("mainnet".parse::<Network>().is_err());
+    }
+}
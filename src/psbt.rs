@@ -0,0 +1,1122 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt,
+    io::{self, Read, Write},
+    str::FromStr,
+};
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use ripemd::Ripemd160;
+use sha2::{Digest, Sha256};
+
+use crate::{
+    decoder::Decoder,
+    encode::{encode_var_bytes, CompactSize, Decodable, Encodable},
+    BitcoinError, Result, Script, ScriptBuilder, ScriptType, Transaction, TransactionOutput,
+};
+
+/// Final scriptSig and witness of an input.
+type FinalScripts = (Option<Vec<u8>>, Option<Vec<Vec<u8>>>);
+
+const PSBT_MAGIC: [u8; 5] = *b"psbt\xff";
+
+const PSBT_GLOBAL_UNSIGNED_TX: u8 = 0x00;
+const PSBT_GLOBAL_XPUB: u8 = 0x01;
+const PSBT_GLOBAL_VERSION: u8 = 0xfb;
+
+const PSBT_IN_NON_WITNESS_UTXO: u8 = 0x00;
+const PSBT_IN_WITNESS_UTXO: u8 = 0x01;
+const PSBT_IN_PARTIAL_SIG: u8 = 0x02;
+const PSBT_IN_SIGHASH_TYPE: u8 = 0x03;
+const PSBT_IN_REDEEM_SCRIPT: u8 = 0x04;
+const PSBT_IN_WITNESS_SCRIPT: u8 = 0x05;
+const PSBT_IN_BIP32_DERIVATION: u8 = 0x06;
+const PSBT_IN_FINAL_SCRIPTSIG: u8 = 0x07;
+const PSBT_IN_FINAL_SCRIPTWITNESS: u8 = 0x08;
+
+const PSBT_OUT_REDEEM_SCRIPT: u8 = 0x00;
+const PSBT_OUT_WITNESS_SCRIPT: u8 = 0x01;
+const PSBT_OUT_BIP32_DERIVATION: u8 = 0x02;
+
+/// Length of a serialized BIP32 extended public key.
+const XPUB_SIZE: usize = 78;
+
+/// Master key fingerprint and derivation path of a BIP32 key.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KeySource {
+    pub fingerprint: [u8; 4],
+    pub path: Vec<u32>,
+}
+
+impl KeySource {
+    fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = self.fingerprint.to_vec();
+        for index in &self.path {
+            bytes.extend_from_slice(&index.to_le_bytes());
+        }
+        bytes
+    }
+}
+
+/// Partially signed transaction, BIP174 version 0. Keys without a dedicated
+/// field, proprietary ones included, are kept in `unknown` with their type byte
+/// so they survive a round trip.
+#[derive(Debug, Clone)]
+pub struct Psbt {
+    pub unsigned_tx: Transaction,
+    pub version: u32,
+    /// Serialized extended public keys.
+    pub xpubs: BTreeMap<Vec<u8>, KeySource>,
+    pub unknown: BTreeMap<Vec<u8>, Vec<u8>>,
+    pub inputs: Vec<PsbtInput>,
+    pub outputs: Vec<PsbtOutput>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct PsbtInput {
+    pub non_witness_utxo: Option<Transaction>,
+    pub witness_utxo: Option<TransactionOutput>,
+    /// Signatures, hash type byte included, by public key.
+    pub partial_sigs: BTreeMap<Vec<u8>, Vec<u8>>,
+    pub sighash_type: Option<u32>,
+    pub redeem_script: Option<Vec<u8>>,
+    pub witness_script: Option<Vec<u8>>,
+    pub bip32_derivation: BTreeMap<Vec<u8>, KeySource>,
+    pub final_script_sig: Option<Vec<u8>>,
+    pub final_script_witness: Option<Vec<Vec<u8>>>,
+    pub unknown: BTreeMap<Vec<u8>, Vec<u8>>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct PsbtOutput {
+    pub redeem_script: Option<Vec<u8>>,
+    pub witness_script: Option<Vec<u8>>,
+    pub bip32_derivation: BTreeMap<Vec<u8>, KeySource>,
+    pub unknown: BTreeMap<Vec<u8>, Vec<u8>>,
+}
+
+impl Psbt {
+    /// Starts a PSBT with empty input and output maps. The transaction must not
+    /// carry any scriptSig or witness.
+    pub fn from_unsigned_tx(unsigned_tx: Transaction) -> Result<Self> {
+        if !is_unsigned(&unsigned_tx) {
+            return Err(BitcoinError::InvalidPsbt(
+                "unsigned transaction has a scriptSig or witness".to_string(),
+            ));
+        }
+        Ok(Self {
+            inputs: vec![PsbtInput::default(); unsigned_tx.inputs.len()],
+            outputs: vec![PsbtOutput::default(); unsigned_tx.outputs.len()],
+            unsigned_tx,
+            version: 0,
+            xpubs: BTreeMap::new(),
+            unknown: BTreeMap::new(),
+        })
+    }
+
+    pub fn deserialize(payload: &[u8]) -> Result<Self> {
+        let mut decoder = Decoder::from_slice(payload);
+        let psbt = Self::consensus_decode(&mut decoder)?;
+        if decoder.remaining() != Some(0) {
+            return Err(decoder.invalid("psbt", "trailing data"));
+        }
+        Ok(psbt)
+    }
+
+    pub fn serialize(&self) -> Vec<u8> {
+        crate::encode::serialize(self)
+    }
+
+    pub fn from_base64(s: &str) -> Result<Self> {
+        let payload = STANDARD
+            .decode(s.trim())
+            .map_err(|e| BitcoinError::InvalidPsbt(format!("invalid base64: {}", e)))?;
+        Self::deserialize(&payload)
+    }
+
+    pub fn to_base64(&self) -> String {
+        STANDARD.encode(self.serialize())
+    }
+
+    /// The output spent by input `index`, from whichever UTXO field is present.
+    pub fn spent_output(&self, index: usize) -> Option<TransactionOutput> {
+        let input = self.inputs.get(index)?;
+        if let Some(witness_utxo) = &input.witness_utxo {
+            return Some(witness_utxo.clone());
+        }
+        let vout = self.unsigned_tx.inputs.get(index)?.previous_output.index;
+        input
+            .non_witness_utxo
+            .as_ref()?
+            .outputs
+            .get(vout as usize)
+            .cloned()
+    }
+
+    /// BIP174 combiner: merges the maps of another PSBT for the same transaction.
+    /// Where both carry the same key, the value already present is kept.
+    pub fn combine(&mut self, other: Psbt) -> Result<()> {
+        if self.unsigned_tx.txid() != other.unsigned_tx.txid() {
+            return Err(BitcoinError::InvalidPsbt(format!(
+                "cannot combine PSBTs for transactions {} and {}",
+                self.unsigned_tx.txid(),
+                other.unsigned_tx.txid()
+            )));
+        }
+        self.version = self.version.max(other.version);
+        merge_map(&mut self.xpubs, other.xpubs);
+        merge_map(&mut self.unknown, other.unknown);
+        for (input, other) in self.inputs.iter_mut().zip(other.inputs) {
+            input.combine(other);
+        }
+        for (output, other) in self.outputs.iter_mut().zip(other.outputs) {
+            output.combine(other);
+        }
+        Ok(())
+    }
+
+    /// Finalizes every input that is not final yet. Only single-key spends are
+    /// supported: P2PK, P2PKH, P2WPKH and P2SH-wrapped P2WPKH, each with exactly
+    /// one partial signature whose key matches the spent output. On error no
+    /// input is changed.
+    pub fn finalize(&mut self) -> Result<()> {
+        let mut finalized = Vec::new();
+        for (index, input) in self.inputs.iter().enumerate() {
+            if input.is_finalized() {
+                continue;
+            }
+            let spent_output = self.spent_output(index).ok_or_else(|| {
+                BitcoinError::InvalidPsbt(format!("input {}: missing UTXO", index))
+            })?;
+            let scripts = input
+                .finalize_single_sig(&spent_output.script_pub_key)
+                .map_err(|reason| {
+                    BitcoinError::InvalidPsbt(format!("input {}: {}", index, reason))
+                })?;
+            finalized.push((index, scripts));
+        }
+        for (index, scripts) in finalized {
+            self.inputs[index].set_final(scripts);
+        }
+        Ok(())
+    }
+
+    /// Builds the network transaction once every input is finalized.
+    pub fn extract_tx(&self) -> Result<Transaction> {
+        let mut transaction = self.unsigned_tx.clone();
+        for (index, (input, psbt_input)) in
+            transaction.inputs.iter_mut().zip(&self.inputs).enumerate()
+        {
+            if !psbt_input.is_finalized() {
+                return Err(BitcoinError::InvalidPsbt(format!(
+                    "input {} is not finalized",
+                    index
+                )));
+            }
+            input.script_sig = psbt_input.final_script_sig.clone().unwrap_or_default();
+            input.witness = psbt_input.final_script_witness.clone().unwrap_or_default();
+        }
+        Ok(transaction)
+    }
+}
+
+impl fmt::Display for Psbt {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.to_base64())
+    }
+}
+
+impl FromStr for Psbt {
+    type Err = BitcoinError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Self::from_base64(s)
+    }
+}
+
+impl PsbtInput {
+    pub fn is_finalized(&self) -> bool {
+        self.final_script_sig.is_some() || self.final_script_witness.is_some()
+    }
+
+    fn combine(&mut self, other: PsbtInput) {
+        merge_option(&mut self.non_witness_utxo, other.non_witness_utxo);
+        merge_option(&mut self.witness_utxo, other.witness_utxo);
+        merge_map(&mut self.partial_sigs, other.partial_sigs);
+        merge_option(&mut self.sighash_type, other.sighash_type);
+        merge_option(&mut self.redeem_script, other.redeem_script);
+        merge_option(&mut self.witness_script, other.witness_script);
+        merge_map(&mut self.bip32_derivation, other.bip32_derivation);
+        merge_option(&mut self.final_script_sig, other.final_script_sig);
+        merge_option(&mut self.final_script_witness, other.final_script_witness);
+        merge_map(&mut self.unknown, other.unknown);
+    }
+
+    /// Final scriptSig and witness for a single-key spend of `script_pub_key`,
+    /// leaving the input untouched.
+    fn finalize_single_sig(
+        &self,
+        script_pub_key: &[u8],
+    ) -> std::result::Result<FinalScripts, String> {
+        if self.partial_sigs.len() != 1 {
+            return Err(format!(
+                "expected exactly one partial signature, found {}",
+                self.partial_sigs.len()
+            ));
+        }
+        let (pubkey, signature) = self.partial_sigs.iter().next().unwrap();
+        if let Some(sighash_type) = self.sighash_type {
+            if signature.last().map(|&byte| byte as u32) != Some(sighash_type) {
+                return Err(format!(
+                    "signature does not use sighash type {}",
+                    sighash_type
+                ));
+            }
+        }
+
+        let mut script_sig = ScriptBuilder::new();
+        let mut witness = None;
+        match Script::new(script_pub_key).script_type() {
+            ScriptType::PubKey(key) => {
+                if key != pubkey.as_slice() {
+                    return Err("signature is not for the P2PK key".to_string());
+                }
+                script_sig = script_sig.push_slice(signature);
+            }
+            ScriptType::PubKeyHash(hash) => {
+                if hash160(pubkey) != *hash {
+                    return Err("signature is not for the P2PKH key".to_string());
+                }
+                script_sig = script_sig.push_slice(signature).push_slice(pubkey);
+            }
+            ScriptType::WitnessV0KeyHash(hash) => {
+                if hash160(pubkey) != *hash {
+                    return Err("signature is not for the P2WPKH key".to_string());
+                }
+                witness = Some(vec![signature.clone(), pubkey.clone()]);
+            }
+            ScriptType::ScriptHash(hash) => {
+                let redeem_script = self
+                    .redeem_script
+                    .as_ref()
+                    .ok_or_else(|| "missing redeem script".to_string())?;
+                if hash160(redeem_script) != *hash {
+                    return Err("redeem script does not match the P2SH hash".to_string());
+                }
+                match Script::new(redeem_script).script_type() {
+                    ScriptType::WitnessV0KeyHash(key_hash) if hash160(pubkey) == *key_hash => {
+                        script_sig = script_sig.push_slice(redeem_script);
+                        witness = Some(vec![signature.clone(), pubkey.clone()]);
+                    }
+                    ScriptType::WitnessV0KeyHash(_) => {
+                        return Err("signature is not for the P2SH-P2WPKH key".to_string());
+                    }
+                    _ => return Err("unsupported redeem script".to_string()),
+                }
+            }
+            script_type => {
+                return Err(format!("unsupported {} script", script_type.name()));
+            }
+        }
+        Ok((
+            (!script_sig.is_empty()).then(|| script_sig.into_bytes()),
+            witness,
+        ))
+    }
+
+    fn set_final(&mut self, (script_sig, witness): FinalScripts) {
+        // BIP174: everything but the UTXOs and final fields is dropped once final
+        self.final_script_sig = script_sig;
+        self.final_script_witness = witness;
+        self.partial_sigs.clear();
+        self.sighash_type = None;
+        self.redeem_script = None;
+        self.witness_script = None;
+        self.bip32_derivation.clear();
+    }
+}
+
+impl PsbtOutput {
+    fn combine(&mut self, other: PsbtOutput) {
+        merge_option(&mut self.redeem_script, other.redeem_script);
+        merge_option(&mut self.witness_script, other.witness_script);
+        merge_map(&mut self.bip32_derivation, other.bip32_derivation);
+        merge_map(&mut self.unknown, other.unknown);
+    }
+}
+
+fn merge_option<T>(value: &mut Option<T>, other: Option<T>) {
+    if value.is_none() {
+        *value = other;
+    }
+}
+
+fn merge_map<V>(map: &mut BTreeMap<Vec<u8>, V>, other: BTreeMap<Vec<u8>, V>) {
+    for (key, value) in other {
+        map.entry(key).or_insert(value);
+    }
+}
+
+fn hash160(data: &[u8]) -> [u8; 20] {
+    Ripemd160::digest(Sha256::digest(data)).into()
+}
+
+fn is_unsigned(transaction: &Transaction) -> bool {
+    transaction
+        .inputs
+        .iter()
+        .all(|input| input.script_sig.is_empty() && input.witness.is_empty())
+}
+
+/// One key-value pair of a PSBT map, with the offsets used for error reporting.
+struct Pair {
+    key: Vec<u8>,
+    value: Vec<u8>,
+    key_offset: usize,
+    value_offset: usize,
+}
+
+impl Pair {
+    /// Reads the next pair, `None` at the separator that ends a map.
+    fn read<R: Read>(
+        decoder: &mut Decoder<R>,
+        seen: &mut BTreeSet<Vec<u8>>,
+    ) -> Result<Option<Self>> {
+        let key_offset = decoder.offset();
+        let key = decoder.read_var_bytes("psbt key")?;
+        if key.is_empty() {
+            return Ok(None);
+        }
+        let value = decoder.read_var_bytes("psbt value")?;
+        let value_offset = decoder.offset() - value.len();
+        let pair = Self {
+            key,
+            value,
+            key_offset,
+            value_offset,
+        };
+        if !seen.insert(pair.key.clone()) {
+            return Err(pair.invalid("psbt key", "duplicate key"));
+        }
+        Ok(Some(pair))
+    }
+
+    fn key_type(&self) -> u8 {
+        self.key[0]
+    }
+
+    fn key_data(&self) -> &[u8] {
+        &self.key[1..]
+    }
+
+    fn invalid(&self, field: &'static str, reason: impl Into<String>) -> BitcoinError {
+        BitcoinError::InvalidField {
+            field,
+            offset: self.key_offset,
+            reason: reason.into(),
+        }
+    }
+
+    fn expect_no_key_data(&self, field: &'static str) -> Result<()> {
+        if !self.key_data().is_empty() {
+            return Err(self.invalid(field, "unexpected key data"));
+        }
+        Ok(())
+    }
+
+    fn pubkey(&self, field: &'static str) -> Result<Vec<u8>> {
+        match self.key_data().len() {
+            33 | 65 => Ok(self.key_data().to_vec()),
+            len => Err(self.invalid(field, format!("invalid public key length {}", len))),
+        }
+    }
+
+    /// Decodes the value as `T`, which has to consume it entirely.
+    fn decode<T: Decodable>(&self, field: &'static str) -> Result<T> {
+        self.decode_with(field, |decoder| T::consensus_decode(decoder))
+    }
+
+    fn decode_with<T>(
+        &self,
+        field: &'static str,
+        decode: impl FnOnce(&mut Decoder<&[u8]>) -> Result<T>,
+    ) -> Result<T> {
+        let mut decoder = Decoder::with_offset(self.value.as_slice(), self.value_offset)
+            .with_limit(self.value.len());
+        let value = decode(&mut decoder)?;
+        if decoder.remaining() != Some(0) {
+            return Err(decoder.invalid(field, "trailing data"));
+        }
+        Ok(value)
+    }
+
+    fn u32(&self, field: &'static str) -> Result<u32> {
+        let bytes: [u8; 4] = self
+            .value
+            .as_slice()
+            .try_into()
+            .map_err(|_| self.invalid(field, "expected 4 bytes"))?;
+        Ok(u32::from_le_bytes(bytes))
+    }
+
+    fn key_source(&self, field: &'static str) -> Result<KeySource> {
+        if self.value.len() < 4 || !self.value.len().is_multiple_of(4) {
+            return Err(self.invalid(field, "invalid key origin length"));
+        }
+        Ok(KeySource {
+            fingerprint: self.value[..4].try_into().unwrap(),
+            path: self.value[4..]
+                .chunks_exact(4)
+                .map(|index| u32::from_le_bytes(index.try_into().unwrap()))
+                .collect(),
+        })
+    }
+
+    fn witness(&self, field: &'static str) -> Result<Vec<Vec<u8>>> {
+        let mut decoder = Decoder::with_offset(self.value.as_slice(), self.value_offset)
+            .with_limit(self.value.len());
+        let count = decoder.read_count(field, 1)?;
+        let mut items = Vec::new();
+        for _ in 0..count {
+            items.push(decoder.read_var_bytes(field)?);
+        }
+        if decoder.remaining() != Some(0) {
+            return Err(decoder.invalid(field, "trailing data"));
+        }
+        Ok(items)
+    }
+}
+
+fn write_pair<W: Write + ?Sized>(
+    writer: &mut W,
+    key_type: u8,
+    key_data: &[u8],
+    value: &[u8],
+) -> io::Result<usize> {
+    let mut key = Vec::with_capacity(1 + key_data.len());
+    key.push(key_type);
+    key.extend_from_slice(key_data);
+    Ok(encode_var_bytes(&key, writer)? + encode_var_bytes(value, writer)?)
+}
+
+fn write_unknown<W: Write + ?Sized>(
+    writer: &mut W,
+    unknown: &BTreeMap<Vec<u8>, Vec<u8>>,
+) -> io::Result<usize> {
+    let mut len = 0;
+    for (key, value) in unknown {
+        len += encode_var_bytes(key, writer)?;
+        len += encode_var_bytes(value, writer)?;
+    }
+    Ok(len)
+}
+
+impl Encodable for Psbt {
+    fn consensus_encode<W: Write + ?Sized>(&self, writer: &mut W) -> io::Result<usize> {
+        writer.write_all(&PSBT_MAGIC)?;
+        let mut len = PSBT_MAGIC.len();
+        len += write_pair(
+            writer,
+            PSBT_GLOBAL_UNSIGNED_TX,
+            &[],
+            &self.unsigned_tx.serialize_without_witness(),
+        )?;
+        for (xpub, source) in &self.xpubs {
+            len += write_pair(writer, PSBT_GLOBAL_XPUB, xpub, &source.to_bytes())?;
+        }
+        if self.version != 0 {
+            len += write_pair(
+                writer,
+                PSBT_GLOBAL_VERSION,
+                &[],
+                &self.version.to_le_bytes(),
+            )?;
+        }
+        len += write_unknown(writer, &self.unknown)?;
+        writer.write_all(&[0x00])?;
+        len += 1;
+        for input in &self.inputs {
+            len += input.consensus_encode(writer)?;
+        }
+        for output in &self.outputs {
+            len += output.consensus_encode(writer)?;
+        }
+        Ok(len)
+    }
+}
+
+impl Encodable for PsbtInput {
+    fn consensus_encode<W: Write + ?Sized>(&self, writer: &mut W) -> io::Result<usize> {
+        let mut len = 0;
+        if let Some(transaction) = &self.non_witness_utxo {
+            len += write_pair(
+                writer,
+                PSBT_IN_NON_WITNESS_UTXO,
+                &[],
+                &transaction.serialize(),
+            )?;
+        }
+        if let Some(output) = &self.witness_utxo {
+            len += write_pair(
+                writer,
+                PSBT_IN_WITNESS_UTXO,
+                &[],
+                &crate::encode::serialize(output),
+            )?;
+        }
+        for (pubkey, signature) in &self.partial_sigs {
+            len += write_pair(writer, PSBT_IN_PARTIAL_SIG, pubkey, signature)?;
+        }
+        if let Some(sighash_type) = self.sighash_type {
+            len += write_pair(
+                writer,
+                PSBT_IN_SIGHASH_TYPE,
+                &[],
+                &sighash_type.to_le_bytes(),
+            )?;
+        }
+        if let Some(script) = &self.redeem_script {
+            len += write_pair(writer, PSBT_IN_REDEEM_SCRIPT, &[], script)?;
+        }
+        if let Some(script) = &self.witness_script {
+            len += write_pair(writer, PSBT_IN_WITNESS_SCRIPT, &[], script)?;
+        }
+        for (pubkey, source) in &self.bip32_derivation {
+            len += write_pair(writer, PSBT_IN_BIP32_DERIVATION, pubkey, &source.to_bytes())?;
+        }
+        if let Some(script) = &self.final_script_sig {
+            len += write_pair(writer, PSBT_IN_FINAL_SCRIPTSIG, &[], script)?;
+        }
+        if let Some(witness) = &self.final_script_witness {
+            let mut value = crate::encode::serialize(&CompactSize(witness.len() as u64));
+            for item in witness {
+                encode_var_bytes(item, &mut value)?;
+            }
+            len += write_pair(writer, PSBT_IN_FINAL_SCRIPTWITNESS, &[], &value)?;
+        }
+        len += write_unknown(writer, &self.unknown)?;
+        writer.write_all(&[0x00])?;
+        Ok(len + 1)
+    }
+}
+
+impl Encodable for PsbtOutput {
+    fn consensus_encode<W: Write + ?Sized>(&self, writer: &mut W) -> io::Result<usize> {
+        let mut len = 0;
+        if let Some(script) = &self.redeem_script {
+            len += write_pair(writer, PSBT_OUT_REDEEM_SCRIPT, &[], script)?;
+        }
+        if let Some(script) = &self.witness_script {
+            len += write_pair(writer, PSBT_OUT_WITNESS_SCRIPT, &[], script)?;
+        }
+        for (pubkey, source) in &self.bip32_derivation {
+            len += write_pair(
+                writer,
+                PSBT_OUT_BIP32_DERIVATION,
+                pubkey,
+                &source.to_bytes(),
+            )?;
+        }
+        len += write_unknown(writer, &self.unknown)?;
+        writer.write_all(&[0x00])?;
+        Ok(len + 1)
+    }
+}
+
+impl Decodable for Psbt {
+    fn consensus_decode<R: Read>(decoder: &mut Decoder<R>) -> Result<Self> {
+        let magic_offset = decoder.offset();
+        if decoder.read_array::<5>("psbt magic")? != PSBT_MAGIC {
+            return Err(BitcoinError::InvalidField {
+                field: "psbt magic",
+                offset: magic_offset,
+                reason: format!("expected {}", hex::encode(PSBT_MAGIC)),
+            });
+        }
+
+        let mut unsigned_tx = None;
+        let mut version = 0;
+        let mut xpubs = BTreeMap::new();
+        let mut unknown = BTreeMap::new();
+        let mut seen = BTreeSet::new();
+        while let Some(pair) = Pair::read(decoder, &mut seen)? {
+            match pair.key_type() {
+                PSBT_GLOBAL_UNSIGNED_TX => {
+                    pair.expect_no_key_data("unsigned transaction")?;
+                    // Always in the non-witness format, even with no inputs
+                    let transaction = pair.decode_with("unsigned transaction", |decoder| {
+                        Transaction::decode_with_witness(decoder, false)
+                    })?;
+                    if !is_unsigned(&transaction) {
+                        return Err(pair.invalid(
+                            "unsigned transaction",
+                            "transaction has a scriptSig or witness",
+                        ));
+                    }
+                    unsigned_tx = Some(transaction);
+                }
+                PSBT_GLOBAL_XPUB => {
+                    if pair.key_data().len() != XPUB_SIZE {
+                        return Err(pair.invalid("xpub", "invalid extended public key length"));
+                    }
+                    xpubs.insert(pair.key_data().to_vec(), pair.key_source("xpub")?);
+                }
+                PSBT_GLOBAL_VERSION => {
+                    pair.expect_no_key_data("psbt version")?;
+                    version = pair.u32("psbt version")?;
+                    if version != 0 {
+                        return Err(pair
+                            .invalid("psbt version", format!("unsupported version {}", version)));
+                    }
+                }
+                _ => {
+                    unknown.insert(pair.key, pair.value);
+                }
+            }
+        }
+        let unsigned_tx = unsigned_tx
+            .ok_or_else(|| BitcoinError::InvalidPsbt("missing unsigned transaction".to_string()))?;
+
+        let mut inputs = Vec::new();
+        for txin in &unsigned_tx.inputs {
+            let input = PsbtInput::consensus_decode(decoder)?;
+            if let Some(transaction) = &input.non_witness_utxo {
+                if transaction.txid() != txin.previous_output.hash {
+                    return Err(BitcoinError::InvalidPsbt(format!(
+                        "non-witness UTXO {} does not match the spent outpoint {}",
+                        transaction.txid(),
+                        txin.previous_output.hash
+                    )));
+                }
+            }
+            inputs.push(input);
+        }
+        let mut outputs = Vec::new();
+        for _ in &unsigned_tx.outputs {
+            outputs.push(PsbtOutput::consensus_decode(decoder)?);
+        }
+        Ok(Self {
+            unsigned_tx,
+            version,
+            xpubs,
+            unknown,
+            inputs,
+            outputs,
+        })
+    }
+}
+
+impl Decodable for PsbtInput {
+    fn consensus_decode<R: Read>(decoder: &mut Decoder<R>) -> Result<Self> {
+        let mut input = Self::default();
+        let mut seen = BTreeSet::new();
+        while let Some(pair) = Pair::read(decoder, &mut seen)? {
+            match pair.key_type() {
+                PSBT_IN_NON_WITNESS_UTXO => {
+                    pair.expect_no_key_data("non-witness utxo")?;
+                    input.non_witness_utxo = Some(pair.decode("non-witness utxo")?);
+                }
+                PSBT_IN_WITNESS_UTXO => {
+                    pair.expect_no_key_data("witness utxo")?;
+                    input.witness_utxo = Some(pair.decode("witness utxo")?);
+                }
+                PSBT_IN_PARTIAL_SIG => {
+                    let pubkey = pair.pubkey("partial signature")?;
+                    input.partial_sigs.insert(pubkey, pair.value);
+                }
+                PSBT_IN_SIGHASH_TYPE => {
+                    pair.expect_no_key_data("sighash type")?;
+                    input.sighash_type = Some(pair.u32("sighash type")?);
+                }
+                PSBT_IN_REDEEM_SCRIPT => {
+                    pair.expect_no_key_data("redeem script")?;
+                    input.redeem_script = Some(pair.value);
+                }
+                PSBT_IN_WITNESS_SCRIPT => {
+                    pair.expect_no_key_data("witness script")?;
+                    input.witness_script = Some(pair.value);
+                }
+                PSBT_IN_BIP32_DERIVATION => {
+                    let pubkey = pair.pubkey("bip32 derivation")?;
+                    let source = pair.key_source("bip32 derivation")?;
+                    input.bip32_derivation.insert(pubkey, source);
+                }
+                PSBT_IN_FINAL_SCRIPTSIG => {
+                    pair.expect_no_key_data("final scriptSig")?;
+                    input.final_script_sig = Some(pair.value);
+                }
+                PSBT_IN_FINAL_SCRIPTWITNESS => {
+                    pair.expect_no_key_data("final script witness")?;
+                    input.final_script_witness = Some(pair.witness("final script witness")?);
+                }
+                _ => {
+                    input.unknown.insert(pair.key, pair.value);
+                }
+            }
+        }
+        Ok(input)
+    }
+}
+
+impl Decodable for PsbtOutput {
+    fn consensus_decode<R: Read>(decoder: &mut Decoder<R>) -> Result<Self> {
+        let mut output = Self::default();
+        let mut seen = BTreeSet::new();
+        while let Some(pair) = Pair::read(decoder, &mut seen)? {
+            match pair.key_type() {
+                PSBT_OUT_REDEEM_SCRIPT => {
+                    pair.expect_no_key_data("redeem script")?;
+                    output.redeem_script = Some(pair.value);
+                }
+                PSBT_OUT_WITNESS_SCRIPT => {
+                    pair.expect_no_key_data("witness script")?;
+                    output.witness_script = Some(pair.value);
+                }
+                PSBT_OUT_BIP32_DERIVATION => {
+                    let pubkey = pair.pubkey("bip32 derivation")?;
+                    let source = pair.key_source("bip32 derivation")?;
+                    output.bip32_derivation.insert(pubkey, source);
+                }
+                _ => {
+                    output.unknown.insert(pair.key, pair.value);
+                }
+            }
+        }
+        Ok(output)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Amount, Txid};
+
+    // BIP174 test vectors
+    const VALID_NON_WITNESS_UTXO: &str = "70736274ff0100750200000001268171371edff285e937adeea4b37b78000c0566cbb3ad64641713ca42171bf60000000000feffffff02d3dff505000000001976a914d0c59903c5bac2868760e90fd521a4665aa7652088ac00e1f5050000000017a9143545e6e33b832c47050f24d3eeb93c9c03948bc787b32e1300000100fda5010100000000010289a3c71eab4d20e0371bbba4cc698fa295c9463afa2e397f8533ccb62f9567e50100000017160014be18d152a9b012039daf3da7de4f53349eecb985ffffffff86f8aa43a71dff1448893a530a7237ef6b4608bbb2dd2d0171e63aec6a4890b40100000017160014fe3e9ef1a745e974d902c4355943abcb34bd5353ffffffff0200c2eb0b000000001976a91485cff1097fd9e008bb34af709c62197b38978a4888ac72fef84e2c00000017a914339725ba21efd62ac753a9bcd067d6c7a6a39d05870247304402202712be22e0270f394f568311dc7ca9a68970b8025fdd3b240229f07f8a5f3a240220018b38d7dcd314e734c9276bd6fb40f673325bc4baa144c800d2f2f02db2765c012103d2e15674941bad4a996372cb87e1856d3652606d98562fe39c5e9e7e413f210502483045022100d12b852d85dcd961d2f5f4ab660654df6eedcc794c0c33ce5cc309ffb5fce58d022067338a8e0e1725c197fb1a88af59f51e44e4255b20167c8684031c05d1f2592a01210223b72beef0965d10be0778efecd61fcac6f79a4ea169393380734464f84f2ab300000000000000";
+    const VALID_NON_WITNESS_UTXO_BASE64: &str = "cHNidP8BAHUCAAAAASaBcTce3/KF6Tet7qSze3gADAVmy7OtZGQXE8pCFxv2AAAAAAD+////AtPf9QUAAAAAGXapFNDFmQPFusKGh2DpD9UhpGZap2UgiKwA4fUFAAAAABepFDVF5uM7gyxHBQ8k0+65PJwDlIvHh7MuEwAAAQD9pQEBAAAAAAECiaPHHqtNIOA3G7ukzGmPopXJRjr6Ljl/hTPMti+VZ+UBAAAAFxYAFL4Y0VKpsBIDna89p95PUzSe7LmF/////4b4qkOnHf8USIk6UwpyN+9rRgi7st0tAXHmOuxqSJC0AQAAABcWABT+Pp7xp0XpdNkCxDVZQ6vLNL1TU/////8CAMLrCwAAAAAZdqkUhc/xCX/Z4Ai7NK9wnGIZeziXikiIrHL++E4sAAAAF6kUM5cluiHv1irHU6m80GfWx6ajnQWHAkcwRAIgJxK+IuAnDzlPVoMR3HyppolwuAJf3TskAinwf4pfOiQCIAGLONfc0xTnNMkna9b7QPZzMlvEuqFEyADS8vAtsnZcASED0uFWdJQbrUqZY3LLh+GFbTZSYG2YVi/jnF6efkE/IQUCSDBFAiEA0SuFLYXc2WHS9fSrZgZU327tzHlMDDPOXMMJ/7X85Y0CIGczio4OFyXBl/saiK9Z9R5E5CVbIBZ8hoQDHAXR8lkqASECI7cr7vCWXRC+B3jv7NYfysb3mk6haTkzgHNEZPhPKrMAAAAAAAAA";
+    const VALID_FINAL_AND_P2SH_P2WPKH: &str = "70736274ff0100a00200000002ab0949a08c5af7c49b8212f417e2f15ab3f5c33dcf153821a8139f877a5b7be40000000000feffffffab0949a08c5af7c49b8212f417e2f15ab3f5c33dcf153821a8139f877a5b7be40100000000feffffff02603bea0b000000001976a914768a40bbd740cbe81d988e71de2a4d5c71396b1d88ac8e240000000000001976a9146f4620b553fa095e721b9ee0efe9fa039cca459788ac000000000001076a47304402204759661797c01b036b25928948686218347d89864b719e1f7fcf57d1e511658702205309eabf56aa4d8891ffd111fdf1336f3a29da866d7f8486d75546ceedaf93190121035cdc61fc7ba971c0b501a646a2a83b102cb43881217ca682dc86e2d73fa882920001012000e1f5050000000017a9143545e6e33b832c47050f24d3eeb93c9c03948bc787010416001485d13537f2e265405a34dbafa9e3dda01fb82308000000";
+    const VALID_BIP32_DERIVATIONS: &str = "70736274ff0100a00200000002ab0949a08c5af7c49b8212f417e2f15ab3f5c33dcf153821a8139f877a5b7be40000000000feffffffab0949a08c5af7c49b8212f417e2f15ab3f5c33dcf153821a8139f877a5b7be40100000000feffffff02603bea0b000000001976a914768a40bbd740cbe81d988e71de2a4d5c71396b1d88ac8e240000000000001976a9146f4620b553fa095e721b9ee0efe9fa039cca459788ac00000000000100df0200000001268171371edff285e937adeea4b37b78000c0566cbb3ad64641713ca42171bf6000000006a473044022070b2245123e6bf474d60c5b50c043d4c691a5d2435f09a34a7662a9dc251790a022001329ca9dacf280bdf30740ec0390422422c81cb45839457aeb76fc12edd95b3012102657d118d3357b8e0f4c2cd46db7b39f6d9c38d9a70abcb9b2de5dc8dbfe4ce31feffffff02d3dff505000000001976a914d0c59903c5bac2868760e90fd521a4665aa7652088ac00e1f5050000000017a9143545e6e33b832c47050f24d3eeb93c9c03948bc787b32e13000001012000e1f5050000000017a9143545e6e33b832c47050f24d3eeb93c9c03948bc787010416001485d13537f2e265405a34dbafa9e3dda01fb8230800220202ead596687ca806043edc3de116cdf29d5e9257c196cd055cf698c8d02bf24e9910b4a6ba670000008000000080020000800022020394f62be9df19952c5587768aeb7698061ad2c4a25c894f47d8c162b4d7213d0510b4a6ba6700000080010000800200008000";
+    const VALID_PARTIAL_SIG_P2WSH: &str = "70736274ff0100550200000001279a2323a5dfb51fc45f220fa58b0fc13e1e3342792a85d7e36cd6333b5cbc390000000000ffffffff01a05aea0b000000001976a914ffe9c0061097cc3b636f2cb0460fa4fc427d2b4588ac0000000000010120955eea0b0000000017a9146345200f68d189e1adc0df1c4d16ea8f14c0dbeb87220203b1341ccba7683b6af4f1238cd6e97e7167d569fac47f1e48d47541844355bd4646304302200424b58effaaa694e1559ea5c93bbfd4a89064224055cdf070b6771469442d07021f5c8eb0fea6516d60b8acb33ad64ede60e8785bfb3aa94b99bdf86151db9a9a010104220020771fd18ad459666dd49f3d564e3dbc42f4c84774e360ada16816a8ed488d5681010547522103b1341ccba7683b6af4f1238cd6e97e7167d569fac47f1e48d47541844355bd462103de55d1e1dac805e3f8a58c1fbf9b94c02f3dbaafe127fefca4995f26f82083bd52ae220603b1341ccba7683b6af4f1238cd6e97e7167d569fac47f1e48d47541844355bd4610b4a6ba67000000800000008004000080220603de55d1e1dac805e3f8a58c1fbf9b94c02f3dbaafe127fefca4995f26f82083bd10b4a6ba670000008000000080050000800000";
+    const VALID_UNKNOWN_KEYS: &str = "70736274ff01003f0200000001ffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffff0000000000ffffffff010000000000000000036a010000000000000a0f0102030405060708090f0102030405060708090a0b0c0d0e0f0000";
+
+    const PUBKEY: &str = "03d2e15674941bad4a996372cb87e1856d3652606d98562fe39c5e9e7e413f2105";
+    const SIGNATURE: &str = "304402202712be22e0270f394f568311dc7ca9a68970b8025fdd3b240229f07f8a5f3a240220018b38d7dcd314e734c9276bd6fb40f673325bc4baa144c800d2f2f02db2765c01";
+
+    fn psbt(hex_psbt: &str) -> Result<Psbt> {
+        Psbt::deserialize(&hex::decode(hex_psbt.trim()).unwrap())
+    }
+
+    #[test]
+    fn test_round_trip_vectors() {
+        for vector in [
+            VALID_NON_WITNESS_UTXO,
+            VALID_FINAL_AND_P2SH_P2WPKH,
+            VALID_BIP32_DERIVATIONS,
+            VALID_PARTIAL_SIG_P2WSH,
+            VALID_UNKNOWN_KEYS,
+        ] {
+            assert_eq!(hex::encode(psbt(vector).unwrap().serialize()), vector);
+        }
+
+        let parsed = psbt(VALID_PARTIAL_SIG_P2WSH).unwrap();
+        let input = &parsed.inputs[0];
+        assert_eq!(input.partial_sigs.len(), 1);
+        assert_eq!(input.sighash_type, None);
+        assert_eq!(
+            input.witness_utxo.as_ref().unwrap().value,
+            Amount::from_sat(199909013)
+        );
+        assert_eq!(input.redeem_script.as_ref().unwrap().len(), 34);
+        assert_eq!(input.witness_script.as_ref().unwrap().len(), 71);
+        let source = input.bip32_derivation.values().next().unwrap();
+        assert_eq!(source.fingerprint, [0xb4, 0xa6, 0xba, 0x67]);
+        assert_eq!(source.path, [0x80000000, 0x80000000, 0x80000004]);
+
+        let parsed = psbt(VALID_UNKNOWN_KEYS).unwrap();
+        assert_eq!(parsed.inputs[0].unknown.len(), 1);
+        assert_eq!(
+            parsed.inputs[0].unknown[&hex::decode("0f010203040506070809").unwrap()].len(),
+            15
+        );
+    }
+
+    #[test]
+    fn test_base64() {
+        let parsed: Psbt = VALID_NON_WITNESS_UTXO_BASE64.parse().unwrap();
+        assert_eq!(hex::encode(parsed.serialize()), VALID_NON_WITNESS_UTXO);
+        assert_eq!(parsed.to_string(), VALID_NON_WITNESS_UTXO_BASE64);
+        assert_eq!(
+            parsed.unsigned_tx.inputs[0].previous_output.hash,
+            parsed.inputs[0].non_witness_utxo.as_ref().unwrap().txid()
+        );
+        assert!(matches!(
+            Psbt::from_base64("not base64!"),
+            Err(BitcoinError::InvalidPsbt(_))
+        ));
+    }
+
+    #[test]
+    fn test_invalid_vectors() {
+        // A network transaction rather than a PSBT
+        assert!(matches!(
+            Psbt::from_base64("AgAAAAEmgXE3Ht/yhek3re6ks3t4AAwFZsuzrWRkFxPKQhcb9gAAAABqRzBEAiBwsiRRI+a/R01gxbUMBD1MaRpdJDXwmjSnZiqdwlF5CgIgATKcqdrPKAvfMHQOwDkEIkIsgctFg5RXrrdvwS7dlbMBIQJlfRGNM1e44PTCzUbbezn22cONmnCry5st5dyNv+TOMf7///8C09/1BQAAAAAZdqkU0MWZA8W6woaHYOkP1SGkZlqnZSCIrADh9QUAAAAAF6kUNUXm4zuDLEcFDyTT7rk8nAOUi8eHsy4TAA=="),
+            Err(BitcoinError::InvalidField { field: "psbt magic", offset: 0, .. })
+        ));
+        // Unsigned transaction with a scriptSig
+        assert!(matches!(
+            psbt("70736274ff0100fd0a010200000002ab0949a08c5af7c49b8212f417e2f15ab3f5c33dcf153821a8139f877a5b7be4000000006a47304402204759661797c01b036b25928948686218347d89864b719e1f7fcf57d1e511658702205309eabf56aa4d8891ffd111fdf1336f3a29da866d7f8486d75546ceedaf93190121035cdc61fc7ba971c0b501a646a2a83b102cb43881217ca682dc86e2d73fa88292feffffffab0949a08c5af7c49b8212f417e2f15ab3f5c33dcf153821a8139f877a5b7be40100000000feffffff02603bea0b000000001976a914768a40bbd740cbe81d988e71de2a4d5c71396b1d88ac8e240000000000001976a9146f4620b553fa095e721b9ee0efe9fa039cca459788ac00000000000001012000e1f5050000000017a9143545e6e33b832c47050f24d3eeb93c9c03948bc787010416001485d13537f2e265405a34dbafa9e3dda01fb82308000000"),
+            Err(BitcoinError::InvalidField { field: "unsigned transaction", offset: 5, .. })
+        ));
+        // No unsigned transaction
+        assert!(matches!(
+            psbt("70736274ff000100fda5010100000000010289a3c71eab4d20e0371bbba4cc698fa295c9463afa2e397f8533ccb62f9567e50100000017160014be18d152a9b012039daf3da7de4f53349eecb985ffffffff86f8aa43a71dff1448893a530a7237ef6b4608bbb2dd2d0171e63aec6a4890b40100000017160014fe3e9ef1a745e974d902c4355943abcb34bd5353ffffffff0200c2eb0b000000001976a91485cff1097fd9e008bb34af709c62197b38978a4888ac72fef84e2c00000017a914339725ba21efd62ac753a9bcd067d6c7a6a39d05870247304402202712be22e0270f394f568311dc7ca9a68970b8025fdd3b240229f07f8a5f3a240220018b38d7dcd314e734c9276bd6fb40f673325bc4baa144c800d2f2f02db2765c012103d2e15674941bad4a996372cb87e1856d3652606d98562fe39c5e9e7e413f210502483045022100d12b852d85dcd961d2f5f4ab660654df6eedcc794c0c33ce5cc309ffb5fce58d022067338a8e0e1725c197fb1a88af59f51e44e4255b20167c8684031c05d1f2592a01210223b72beef0965d10be0778efecd61fcac6f79a4ea169393380734464f84f2ab30000000000"),
+            Err(BitcoinError::InvalidPsbt(_))
+        ));
+        // Duplicate non-witness UTXO key in the input map
+        assert!(matches!(
+            psbt("70736274ff0100750200000001268171371edff285e937adeea4b37b78000c0566cbb3ad64641713ca42171bf60000000000feffffff02d3dff505000000001976a914d0c59903c5bac2868760e90fd521a4665aa7652088ac00e1f5050000000017a9143545e6e33b832c47050f24d3eeb93c9c03948bc787b32e1300000100fda5010100000000010289a3c71eab4d20e0371bbba4cc698fa295c9463afa2e397f8533ccb62f9567e50100000017160014be18d152a9b012039daf3da7de4f53349eecb985ffffffff86f8aa43a71dff1448893a530a7237ef6b4608bbb2dd2d0171e63aec6a4890b40100000017160014fe3e9ef1a745e974d902c4355943abcb34bd5353ffffffff0200c2eb0b000000001976a91485cff1097fd9e008bb34af709c62197b38978a4888ac72fef84e2c00000017a914339725ba21efd62ac753a9bcd067d6c7a6a39d05870247304402202712be22e0270f394f568311dc7ca9a68970b8025fdd3b240229f07f8a5f3a240220018b38d7dcd314e734c9276bd6fb40f673325bc4baa144c800d2f2f02db2765c012103d2e15674941bad4a996372cb87e1856d3652606d98562fe39c5e9e7e413f210502483045022100d12b852d85dcd961d2f5f4ab660654df6eedcc794c0c33ce5cc309ffb5fce58d022067338a8e0e1725c197fb1a88af59f51e44e4255b20167c8684031c05d1f2592a01210223b72beef0965d10be0778efecd61fcac6f79a4ea169393380734464f84f2ab30000000001003f0200000001ffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffff0000000000ffffffff010000000000000000036a010000000000000000"),
+            Err(BitcoinError::InvalidField { field: "psbt key", .. })
+        ));
+        // Missing output maps
+        assert!(psbt(&VALID_NON_WITNESS_UTXO[..VALID_NON_WITNESS_UTXO.len() - 4]).is_err());
+        // Trailing data
+        assert!(psbt(&format!("{}00", VALID_NON_WITNESS_UTXO)).is_err());
+    }
+
+    #[test]
+    fn test_combine() {
+        let mut combined = psbt(include_str!("../data/psbt/psbt1.hex")).unwrap();
+        let other = psbt(include_str!("../data/psbt/psbt2.hex")).unwrap();
+        // The vector lists partial signatures unsorted, serialization sorts them by key
+        let expected = psbt(include_str!("../data/psbt/psbt_combined.hex"))
+            .unwrap()
+            .serialize();
+
+        let mut reversed = other.clone();
+        reversed.combine(combined.clone()).unwrap();
+        combined.combine(other).unwrap();
+        assert_eq!(combined.serialize(), expected);
+        assert_eq!(reversed.serialize(), expected);
+
+        let unrelated = psbt(VALID_NON_WITNESS_UTXO).unwrap();
+        assert!(matches!(
+            combined.combine(unrelated),
+            Err(BitcoinError::InvalidPsbt(_))
+        ));
+    }
+
+    #[test]
+    fn test_round_trip_without_inputs() {
+        // A funding template with no inputs yet, whose output count would read as
+        // the BIP144 flag in the extended format
+        let mut unsigned_tx = psbt(VALID_UNKNOWN_KEYS).unwrap().unsigned_tx;
+        unsigned_tx.inputs.clear();
+        assert_eq!(unsigned_tx.outputs.len(), 1);
+        let parsed = Psbt::from_unsigned_tx(unsigned_tx).unwrap();
+        let payload = parsed.serialize();
+
+        let round_trip = Psbt::deserialize(&payload).unwrap();
+        assert!(round_trip.unsigned_tx.inputs.is_empty());
+        assert_eq!(round_trip.unsigned_tx.outputs.len(), 1);
+        assert_eq!(round_trip.unsigned_tx.txid(), parsed.unsigned_tx.txid());
+        assert_eq!(round_trip.serialize(), payload);
+    }
+
+    #[test]
+    fn test_finalize_p2pkh() {
+        let mut parsed = psbt(VALID_NON_WITNESS_UTXO).unwrap();
+        assert!(parsed.finalize().is_err());
+        assert!(parsed.extract_tx().is_err());
+
+        // The spent output pays to the hash of another key
+        let signature = hex::decode(SIGNATURE).unwrap();
+        parsed.inputs[0]
+            .partial_sigs
+            .insert(hex::decode(PUBKEY).unwrap(), signature.clone());
+        assert!(matches!(
+            parsed.finalize(),
+            Err(BitcoinError::InvalidPsbt(_))
+        ));
+        assert_eq!(parsed.inputs[0].partial_sigs.len(), 1);
+
+        let pubkey = hex::decode(PUBKEY).unwrap();
+        let vout = parsed.unsigned_tx.inputs[0].previous_output.index as usize;
+        let utxo = parsed.inputs[0].non_witness_utxo.as_mut().unwrap();
+        utxo.outputs[vout].script_pub_key[3..23].copy_from_slice(&hash160(&pubkey));
+        parsed.unsigned_tx.inputs[0].previous_output.hash = utxo.txid();
+        parsed.inputs[0].sighash_type = Some(2);
+        assert!(matches!(
+            parsed.finalize(),
+            Err(BitcoinError::InvalidPsbt(_))
+        ));
+        parsed.inputs[0].sighash_type = Some(1);
+        parsed.finalize().unwrap();
+
+        let input = &parsed.inputs[0];
+        let mut script_sig = vec![signature.len() as u8];
+        script_sig.extend_from_slice(&signature);
+        script_sig.push(pubkey.len() as u8);
+        script_sig.extend_from_slice(&pubkey);
+        assert_eq!(input.final_script_sig.as_ref(), Some(&script_sig));
+        assert!(input.final_script_witness.is_none());
+        assert!(input.partial_sigs.is_empty());
+        assert!(input.sighash_type.is_none());
+        assert!(input.non_witness_utxo.is_some());
+
+        let transaction = parsed.extract_tx().unwrap();
+        assert_eq!(transaction.inputs[0].script_sig, script_sig);
+        assert_eq!(
+            transaction.inputs[0].previous_output.hash,
+            parsed.unsigned_tx.inputs[0].previous_output.hash
+        );
+        assert!(!transaction.has_witness());
+
+        let round_trip = psbt(&hex::encode(parsed.serialize())).unwrap();
+        assert_eq!(round_trip.inputs[0].final_script_sig, Some(script_sig));
+    }
+
+    #[test]
+    fn test_finalize_p2sh_p2wpkh() {
+        let mut parsed = psbt(VALID_FINAL_AND_P2SH_P2WPKH).unwrap();
+        assert!(parsed.inputs[0].is_finalized());
+        let final_script_sig = parsed.inputs[0].final_script_sig.clone();
+        let redeem_script = parsed.inputs[1].redeem_script.clone().unwrap();
+
+        let pubkey = hex::decode(PUBKEY).unwrap();
+        let signature = hex::decode(SIGNATURE).unwrap();
+        parsed.inputs[1]
+            .partial_sigs
+            .insert(pubkey.clone(), signature.clone());
+        // The redeem script pays to the hash of another key
+        assert!(matches!(
+            parsed.finalize(),
+            Err(BitcoinError::InvalidPsbt(_))
+        ));
+        assert_eq!(
+            parsed.inputs[1].redeem_script.as_ref(),
+            Some(&redeem_script)
+        );
+
+        let mut redeem_script = vec![0x00, 0x14];
+        redeem_script.extend_from_slice(&hash160(&pubkey));
+        parsed.inputs[1].redeem_script = Some(redeem_script.clone());
+        assert!(matches!(
+            parsed.finalize(),
+            Err(BitcoinError::InvalidPsbt(_))
+        ));
+        let mut script_pub_key = vec![0xa9, 0x14];
+        script_pub_key.extend_from_slice(&hash160(&redeem_script));
+        script_pub_key.push(0x87);
+        parsed.inputs[1]
+            .witness_utxo
+            .as_mut()
+            .unwrap()
+            .script_pub_key = script_pub_key;
+        parsed.finalize().unwrap();
+
+        // The input that was already final is left alone
+        assert_eq!(parsed.inputs[0].final_script_sig, final_script_sig);
+        let input = &parsed.inputs[1];
+        let mut script_sig = vec![redeem_script.len() as u8];
+        script_sig.extend_from_slice(&redeem_script);
+        assert_eq!(input.final_script_sig.as_ref(), Some(&script_sig));
+        assert_eq!(
+            input.final_script_witness.as_ref(),
+            Some(&vec![signature, pubkey])
+        );
+        assert!(input.redeem_script.is_none());
+
+        let transaction = parsed.extract_tx().unwrap();
+        assert!(transaction.has_witness());
+        assert_eq!(transaction.inputs[1].witness.len(), 2);
+        assert!(Transaction::deserialize(&transaction.serialize()).is_ok());
+        assert!(psbt(&hex::encode(parsed.serialize())).is_ok());
+    }
+
+    #[test]
+    fn test_finalize_p2wpkh() {
+        let mut transaction = psbt(VALID_UNKNOWN_KEYS).unwrap().unsigned_tx;
+        transaction.inputs[0].previous_output.hash = Txid::all_zeros();
+        let mut parsed = Psbt::from_unsigned_tx(transaction).unwrap();
+        let pubkey = hex::decode(PUBKEY).unwrap();
+        let mut script_pub_key = vec![0x00, 0x14];
+        script_pub_key.extend_from_slice(&[0xab; 20]);
+        parsed.inputs[0].witness_utxo = Some(TransactionOutput {
+            value: Amount::from_sat(1000),
+            script_pub_key,
+        });
+        parsed.inputs[0]
+            .partial_sigs
+            .insert(pubkey.clone(), hex::decode(SIGNATURE).unwrap());
+        assert!(matches!(
+            parsed.finalize(),
+            Err(BitcoinError::InvalidPsbt(_))
+        ));
+
+        let mut script_pub_key = vec![0x00, 0x14];
+        script_pub_key.extend_from_slice(&hash160(&pubkey));
+        parsed.inputs[0]
+            .witness_utxo
+            .as_mut()
+            .unwrap()
+            .script_pub_key = script_pub_key;
+        parsed.inputs[0]
+            .partial_sigs
+            .insert(vec![0x02; 33], hex::decode(SIGNATURE).unwrap());
+        assert!(matches!(
+            parsed.finalize(),
+            Err(BitcoinError::InvalidPsbt(_))
+        ));
+
+        parsed.inputs[0].partial_sigs.remove(&vec![0x02; 33]);
+        parsed.finalize().unwrap();
+        assert!(parsed.inputs[0].final_script_sig.is_none());
+        assert_eq!(
+            parsed.inputs[0]
+                .final_script_witness
+                .as_ref()
+                .unwrap()
+                .len(),
+            2
+        );
+        assert_eq!(
+            psbt(&hex::encode(parsed.serialize())).unwrap().inputs[0].final_script_witness,
+            parsed.inputs[0].final_script_witness
+        );
+    }
+
+    #[test]
+    fn test_finalize_is_atomic() {
+        let mut transaction = psbt(VALID_UNKNOWN_KEYS).unwrap().unsigned_tx;
+        transaction.inputs[0].previous_output.hash = Txid::all_zeros();
+        transaction.inputs.push(transaction.inputs[0].clone());
+        transaction.inputs[1].previous_output.index = 1;
+        let mut parsed = Psbt::from_unsigned_tx(transaction).unwrap();
+        let pubkey = hex::decode(PUBKEY).unwrap();
+        let mut script_pub_key = vec![0x00, 0x14];
+        script_pub_key.extend_from_slice(&hash160(&pubkey));
+        let utxo = TransactionOutput {
+            value: Amount::from_sat(1000),
+            script_pub_key,
+        };
+        for input in &mut parsed.inputs {
+            input
+                .partial_sigs
+                .insert(pubkey.clone(), hex::decode(SIGNATURE).unwrap());
+        }
+        parsed.inputs[0].witness_utxo = Some(utxo.clone());
+
+        // Input 1 has no UTXO, so input 0 keeps its signing data too
+        assert!(matches!(
+            parsed.finalize(),
+            Err(BitcoinError::InvalidPsbt(_))
+        ));
+        assert!(!parsed.inputs[0].is_finalized());
+        assert_eq!(parsed.inputs[0].partial_sigs.len(), 1);
+
+        parsed.inputs[1].witness_utxo = Some(utxo);
+        parsed.finalize().unwrap();
+        assert!(parsed.inputs.iter().all(PsbtInput::is_finalized));
+        assert_eq!(parsed.extract_tx().unwrap().inputs[1].witness.len(), 2);
+    }
+}
@@ -0,0 +1,587 @@
+use std::fmt;
+
+use crate::{
+    opcodes::{
+        opcode_from_name, opcode_name, OP_0, OP_1, OP_16, OP_1NEGATE, OP_PUSHDATA1, OP_PUSHDATA2,
+        OP_PUSHDATA4,
+    },
+    secp256k1::is_valid_signature_encoding,
+    BitcoinError, Result,
+};
+
+/// One step of a script: a data push (`OP_0` and `OP_PUSHDATA*` included) or
+/// any other opcode.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Instruction<'a> {
+    PushBytes(&'a [u8]),
+    Op(u8),
+}
+
+impl<'a> Instruction<'a> {
+    pub fn push_bytes(&self) -> Option<&'a [u8]> {
+        match self {
+            Instruction::PushBytes(data) => Some(data),
+            Instruction::Op(_) => None,
+        }
+    }
+
+    pub fn opcode(&self) -> Option<u8> {
+        match self {
+            Instruction::PushBytes(_) => None,
+            Instruction::Op(opcode) => Some(*opcode),
+        }
+    }
+}
+
+/// Iterator over the instructions of a script. A push running past the end of
+/// the script yields an error, after which iteration stops.
+#[derive(Debug, Clone)]
+pub struct Instructions<'a> {
+    script: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Instructions<'a> {
+    /// Byte offset of the next instruction.
+    pub fn position(&self) -> usize {
+        self.position
+    }
+
+    fn take(&mut self, len: usize, field: &'static str) -> Result<&'a [u8]> {
+        match self
+            .script
+            .get(self.position..self.position.saturating_add(len))
+        {
+            Some(bytes) => {
+                self.position += len;
+                Ok(bytes)
+            }
+            None => {
+                let offset = self.position;
+                self.position = self.script.len();
+                Err(BitcoinError::UnexpectedEof { field, offset })
+            }
+        }
+    }
+}
+
+impl<'a> Iterator for Instructions<'a> {
+    type Item = Result<Instruction<'a>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let opcode = *self.script.get(self.position)?;
+        self.position += 1;
+        let len = match opcode {
+            OP_PUSHDATA1 => self.take(1, "push length").map(|len| len[0] as usize),
+            OP_PUSHDATA2 => self
+                .take(2, "push length")
+                .map(|len| u16::from_le_bytes([len[0], len[1]]) as usize),
+            OP_PUSHDATA4 => self
+                .take(4, "push length")
+                .map(|len| u32::from_le_bytes(len.try_into().unwrap()) as usize),
+            _ if opcode < OP_PUSHDATA1 => Ok(opcode as usize),
+            _ => return Some(Ok(Instruction::Op(opcode))),
+        };
+        Some(
+            len.and_then(|len| self.take(len, "push data"))
+                .map(Instruction::PushBytes),
+        )
+    }
+}
+
+/// Borrowed view of a serialized script, such as a `script_sig` or
+/// `script_pub_key`.
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Script<'a>(&'a [u8]);
+
+impl<'a> Script<'a> {
+    pub fn new(bytes: &'a [u8]) -> Self {
+        Self(bytes)
+    }
+
+    pub fn as_bytes(&self) -> &'a [u8] {
+        self.0
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn instructions(&self) -> Instructions<'a> {
+        Instructions {
+            script: self.0,
+            position: 0,
+        }
+    }
+
+    /// Whether the script only pushes data, as required of P2SH scriptSigs.
+    /// `OP_1NEGATE`, `OP_RESERVED` and `OP_1` to `OP_16` count as pushes.
+    pub fn is_push_only(&self) -> bool {
+        self.instructions().all(|instruction| match instruction {
+            Ok(Instruction::PushBytes(_)) => true,
+            Ok(Instruction::Op(opcode)) => opcode <= OP_16,
+            Err(_) => false,
+        })
+    }
+
+    /// Renders the script the way Bitcoin Core's `ScriptToAsmStr` does. Pushes
+    /// of up to four bytes are shown as numbers, longer ones as hex. With
+    /// `attempt_sighash_decode` DER signatures get their hash type spelled out.
+    pub fn to_asm(&self, attempt_sighash_decode: bool) -> String {
+        let mut parts: Vec<String> = Vec::new();
+        for instruction in self.instructions() {
+            let data = match instruction {
+                Ok(Instruction::PushBytes(data)) => data,
+                Ok(Instruction::Op(opcode)) => {
+                    parts.push(opcode_name(opcode).to_string());
+                    continue;
+                }
+                Err(_) => {
+                    parts.push("[error]".to_string());
+                    break;
+                }
+            };
+            if data.len() <= 4 {
+                parts.push(decode_script_num(data).to_string());
+                continue;
+            }
+            let mut part = hex::encode(data);
+            if attempt_sighash_decode && is_valid_signature_encoding(data) {
+                if let Some(name) = sighash_name(data[data.len() - 1]) {
+                    part = format!("{}[{}]", hex::encode(&data[..data.len() - 1]), name);
+                }
+            }
+            parts.push(part);
+        }
+        parts.join(" ")
+    }
+
+    /// Parses ASM back into script bytes. Accepted tokens are opcode names with
+    /// or without `OP_`, decimal numbers (pushed minimally), hex data with an
+    /// optional `[ALL]`-style hash type suffix, and Core's `ParseScript` forms:
+    /// `0x` followed by raw script bytes and `'text'` pushes. ASM is lossy, so
+    /// a non-minimal push comes back in its minimal form.
+    pub fn from_asm(asm: &str) -> Result<Vec<u8>> {
+        let mut builder = ScriptBuilder::new();
+        for token in asm.split_whitespace() {
+            let invalid = || BitcoinError::InvalidScript(format!("invalid ASM token {:?}", token));
+            builder = if let Some(number) = parse_asm_number(token) {
+                builder.push_int(number)
+            } else if let Some(raw) = token.strip_prefix("0x") {
+                builder.push_raw(&hex::decode(raw).map_err(|_| invalid())?)
+            } else if let Some(text) = token
+                .strip_prefix('\'')
+                .and_then(|text| text.strip_suffix('\''))
+            {
+                builder.push_slice(text.as_bytes())
+            } else if let Some(opcode) = opcode_from_name(token) {
+                builder.push_opcode(opcode)
+            } else if let Some((signature, name)) = token
+                .strip_suffix(']')
+                .and_then(|token| token.split_once('['))
+            {
+                let hash_type = (0..=u8::MAX)
+                    .find(|&hash_type| sighash_name(hash_type) == Some(name))
+                    .ok_or_else(invalid)?;
+                let mut data = hex::decode(signature).map_err(|_| invalid())?;
+                data.push(hash_type);
+                builder.push_slice(&data)
+            } else {
+                builder.push_slice(&hex::decode(token).map_err(|_| invalid())?)
+            };
+        }
+        Ok(builder.into_bytes())
+    }
+}
+
+/// Builds a script from opcodes and pushes, always choosing the smallest
+/// encoding so the result passes Core's `MINIMALDATA` checks.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ScriptBuilder(Vec<u8>);
+
+impl ScriptBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push_opcode(mut self, opcode: u8) -> Self {
+        self.0.push(opcode);
+        self
+    }
+
+    /// Pushes a CScriptNum, using `OP_1NEGATE`, `OP_0` and `OP_1` to `OP_16`
+    /// for the numbers they stand for.
+    pub fn push_int(self, value: i64) -> Self {
+        match value {
+            -1 => self.push_opcode(OP_1NEGATE),
+            0 => self.push_opcode(OP_0),
+            1..=16 => self.push_opcode(OP_1 + value as u8 - 1),
+            _ => self.push_slice(&encode_script_num(value)),
+        }
+    }
+
+    /// Pushes data. Single bytes that an opcode can push are replaced by it, and
+    /// `OP_PUSHDATA*` is only used when the length needs it.
+    pub fn push_slice(mut self, data: &[u8]) -> Self {
+        match data {
+            [] => return self.push_opcode(OP_0),
+            [n @ 1..=16] => return self.push_opcode(OP_1 + n - 1),
+            [0x81] => return self.push_opcode(OP_1NEGATE),
+            _ => {}
+        }
+        let len = data.len();
+        if len < OP_PUSHDATA1 as usize {
+            self.0.push(len as u8);
+        } else if len <= 0xff {
+            self.0.extend_from_slice(&[OP_PUSHDATA1, len as u8]);
+        } else if len <= 0xffff {
+            self.0.push(OP_PUSHDATA2);
+            self.0.extend_from_slice(&(len as u16).to_le_bytes());
+        } else {
+            self.0.push(OP_PUSHDATA4);
+            self.0.extend_from_slice(&(len as u32).to_le_bytes());
+        }
+        self.0.extend_from_slice(data);
+        self
+    }
+
+    /// Appends already serialized script bytes as they are.
+    pub fn push_raw(mut self, bytes: &[u8]) -> Self {
+        self.0.extend_from_slice(bytes);
+        self
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn as_script(&self) -> Script<'_> {
+        Script::new(&self.0)
+    }
+
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.0
+    }
+}
+
+impl<'a> From<&'a [u8]> for Script<'a> {
+    fn from(bytes: &'a [u8]) -> Self {
+        Self(bytes)
+    }
+}
+
+impl<'a> From<&'a Vec<u8>> for Script<'a> {
+    fn from(bytes: &'a Vec<u8>) -> Self {
+        Self(bytes)
+    }
+}
+
+impl AsRef<[u8]> for Script<'_> {
+    fn as_ref(&self) -> &[u8] {
+        self.0
+    }
+}
+
+/// ASM without sighash decoding.
+impl fmt::Display for Script<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.to_asm(false))
+    }
+}
+
+impl fmt::Debug for Script<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Script({})", self.to_asm(false))
+    }
+}
+
+fn sighash_name(hash_type: u8) -> Option<&'static str> {
+    match hash_type {
+        0x01 => Some("ALL"),
+        0x02 => Some("NONE"),
+        0x03 => Some("SINGLE"),
+        0x81 => Some("ALL|ANYONECANPAY"),
+        0x82 => Some("NONE|ANYONECANPAY"),
+        0x83 => Some("SINGLE|ANYONECANPAY"),
+        _ => None,
+    }
+}
+
+/// Minimally encoded little-endian sign-magnitude number, as used by CScriptNum.
+pub(crate) fn decode_script_num(bytes: &[u8]) -> i64 {
+    let Some((last, _)) = bytes.split_last() else {
+        return 0;
+    };
+    let mut value: i64 = 0;
+    for (i, byte) in bytes.iter().enumerate() {
+        value |= (*byte as i64) << (8 * i);
+    }
+    if last & 0x80 != 0 {
+        -(value & !(0x80i64 << (8 * (bytes.len() - 1))))
+    } else {
+        value
+    }
+}
+
+/// Decimal numbers in the range Core's `ParseScript` accepts. Longer digit
+/// strings are hex data.
+fn parse_asm_number(token: &str) -> Option<i64> {
+    let digits = token.strip_prefix('-').unwrap_or(token);
+    if digits.is_empty() || digits.len() > 10 || !digits.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    token
+        .parse::<i64>()
+        .ok()
+        .filter(|number| number.abs() <= 0xffffffff)
+}
+
+/// Minimal little-endian sign-magnitude encoding of a CScriptNum.
+pub(crate) fn encode_script_num(value: i64) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    let mut magnitude = value.unsigned_abs();
+    while magnitude > 0 {
+        bytes.push(magnitude as u8);
+        magnitude >>= 8;
+    }
+    if let Some(last) = bytes.last_mut() {
+        if *last & 0x80 != 0 {
+            bytes.push(if value < 0 { 0x80 } else { 0x00 });
+        } else if value < 0 {
+            *last |= 0x80;
+        }
+    }
+    bytes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_p2pk_asm() {
+        let script = hex::decode("410411db93e1dcdb8a016b49840f8c53bc1eb68a382e97b1482ecad7b148a6909a5cb2e0eaddfb84ccf9744464f82e160bfa9b8b64f9d4c03f999b8643f656b412a3ac").unwrap();
+        assert_eq!(
+            Script::new(&script).to_asm(false),
+            "0411db93e1dcdb8a016b49840f8c53bc1eb68a382e97b1482ecad7b148a6909a5cb2e0eaddfb84ccf9744464f82e160bfa9b8b64f9d4c03f999b8643f656b412a3 OP_CHECKSIG"
+        );
+    }
+
+    #[test]
+    fn test_signature_asm() {
+        let script = hex::decode("47304402204e45e16932b8af514961a1d3a1a25fdf3f4f7732e9d624c6c61548ab5fb8cd410220181522ec8eca07de4860a4acdd12909d831cc56cbbac4622082221a8768d1d0901").unwrap();
+        assert_eq!(
+            Script::new(&script).to_asm(true),
+            "304402204e45e16932b8af514961a1d3a1a25fdf3f4f7732e9d624c6c61548ab5fb8cd410220181522ec8eca07de4860a4acdd12909d831cc56cbbac4622082221a8768d1d09[ALL]"
+        );
+        assert_eq!(
+            Script::new(&script).to_asm(false),
+            hex::encode(&script[1..])
+        );
+    }
+
+    #[test]
+    fn test_small_pushes_and_errors() {
+        // Genesis-style coinbase: push of the bits, then a push of 4
+        assert_eq!(
+            Script::new(&hex::decode("04ffff001d0104").unwrap()).to_asm(true),
+            "486604799 4"
+        );
+        assert_eq!(
+            Script::new(&[0x00, 0x4f, 0x51, 0x60, 0x01, 0x81]).to_asm(false),
+            "0 -1 1 16 -1"
+        );
+        assert_eq!(Script::new(&[0x76, 0x4c]).to_asm(false), "OP_DUP [error]");
+        assert_eq!(Script::new(&[0x02, 0x01]).to_asm(false), "[error]");
+    }
+
+    #[test]
+    fn test_p2pk_instructions() {
+        let transaction =
+            crate::Transaction::deserialize(&hex::decode(crate::TRANSACTION_SERIALIZED).unwrap())
+                .unwrap();
+        let script = Script::new(&transaction.outputs[0].script_pub_key);
+        let instructions: Vec<_> = script.instructions().map(Result::unwrap).collect();
+        assert_eq!(instructions.len(), 2);
+        let pubkey = instructions[0].push_bytes().unwrap();
+        assert_eq!(pubkey.len(), 65);
+        assert_eq!(pubkey[0], 0x04);
+        assert_eq!(
+            instructions[1],
+            Instruction::Op(crate::opcodes::OP_CHECKSIG)
+        );
+        assert!(!script.is_push_only());
+    }
+
+    #[test]
+    fn test_coinbase_message() {
+        let script_sig = hex::decode("04ffff001d0104455468652054696d65732030332f4a616e2f32303039204368616e63656c6c6f72206f6e206272696e6b206f66207365636f6e64206261696c6f757420666f722062616e6b73").unwrap();
+        let script = Script::new(&script_sig);
+        let pushes: Vec<&[u8]> = script
+            .instructions()
+            .map(|instruction| instruction.unwrap().push_bytes().unwrap())
+            .collect();
+        assert_eq!(pushes[0], [0xff, 0xff, 0x00, 0x1d]);
+        assert_eq!(pushes[1], [0x04]);
+        assert_eq!(
+            pushes[2],
+            b"The Times 03/Jan/2009 Chancellor on brink of second bailout for banks"
+        );
+        assert!(script.is_push_only());
+    }
+
+    #[test]
+    fn test_pushdata_and_truncation() {
+        let script = [
+            0x4c, 0x02, 0xab, 0xcd, 0x4d, 0x01, 0x00, 0xee, 0x4e, 0x00, 0x00, 0x00, 0x00, 0x00,
+        ];
+        let instructions: Vec<_> = Script::new(&script)
+            .instructions()
+            .map(Result::unwrap)
+            .collect();
+        assert_eq!(
+            instructions,
+            [
+                Instruction::PushBytes(&[0xab, 0xcd]),
+                Instruction::PushBytes(&[0xee]),
+                Instruction::PushBytes(&[]),
+                Instruction::PushBytes(&[]),
+            ]
+        );
+
+        let mut instructions = Script::new(&[0x76, 0x4d, 0x01]).instructions();
+        assert_eq!(instructions.next().unwrap().unwrap(), Instruction::Op(0x76));
+        assert!(matches!(
+            instructions.next(),
+            Some(Err(BitcoinError::UnexpectedEof {
+                field: "push length",
+                offset: 2
+            }))
+        ));
+        assert!(instructions.next().is_none());
+
+        let mut instructions = Script::new(&[0x4c, 0x05, 0x01]).instructions();
+        assert!(matches!(
+            instructions.next(),
+            Some(Err(BitcoinError::UnexpectedEof {
+                field: "push data",
+                offset: 2
+            }))
+        ));
+        assert!(instructions.next().is_none());
+        assert!(!Script::new(&[0x4c, 0x05, 0x01]).is_push_only());
+    }
+
+    #[test]
+    fn test_asm_round_trip() {
+        for script_hex in [
+            "76a91489abcdefabbaabbaabbaabbaabbaabbaabbaabba88ac",
+            "5221022afc20bf379bc96a2f4e9e63ffceb8652b2b6a097f63fbee6ecec2a49a48010e2103a767c7221e9f15f870f1ad9311f5ab937d79fcaeee15bb2c722bca515581b4c052ae",
+            "47304402204e45e16932b8af514961a1d3a1a25fdf3f4f7732e9d624c6c61548ab5fb8cd410220181522ec8eca07de4860a4acdd12909d831cc56cbbac4622082221a8768d1d0901",
+            "6a4c50000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f202122232425262728292a2b2c2d2e2f303132333435363738393a3b3c3d3e3f404142434445464748494a4b4c4d4e4f",
+            "004f0051600281000380ff7f",
+        ] {
+            let script = hex::decode(script_hex).unwrap();
+            for attempt_sighash_decode in [false, true] {
+                let asm = Script::new(&script).to_asm(attempt_sighash_decode);
+                assert_eq!(hex::encode(Script::from_asm(&asm).unwrap()), script_hex, "{}", asm);
+            }
+        }
+
+        // Pushes of small numbers come back minimal
+        assert_eq!(
+            Script::from_asm(&Script::new(&[0x01, 0x05]).to_asm(false)).unwrap(),
+            [0x55]
+        );
+    }
+
+    #[test]
+    fn test_from_asm() {
+        assert_eq!(
+            Script::from_asm("DUP OP_HASH160 OP_NOP2 OP_TRUE OP_FALSE OP_1NEGATE").unwrap(),
+            [0x76, 0xa9, 0xb1, 0x51, 0x00, 0x4f]
+        );
+        assert_eq!(
+            hex::encode(Script::from_asm("-1 0 16 17 -129 1000 4294967295").unwrap()),
+            "4f0060011102818002e80305ffffffff00"
+        );
+        assert_eq!(Script::from_asm("0x4c01 0x05").unwrap(), [0x4c, 0x01, 0x05]);
+        assert_eq!(Script::from_asm("'abc'").unwrap(), [0x03, b'a', b'b', b'c']);
+        assert!(Script::from_asm("  ").unwrap().is_empty());
+        for invalid in ["OP_FOO", "abc", "0xzz", "3044[FOO]", "[error]"] {
+            assert!(
+                matches!(
+                    Script::from_asm(invalid),
+                    Err(BitcoinError::InvalidScript(_))
+                ),
+                "{}",
+                invalid
+            );
+        }
+    }
+
+    #[test]
+    fn test_builder_push_int() {
+        for (value, expected) in [
+            (-1, "4f"),
+            (0, "00"),
+            (16, "60"),
+            (17, "0111"),
+            (-2, "0182"),
+            (127, "017f"),
+            (128, "028000"),
+            (-128, "028080"),
+            (255, "02ff00"),
+            (256, "020001"),
+            (0x7fffffff, "04ffffff7f"),
+            (-0x80000000, "050000008080"),
+        ] {
+            let script = ScriptBuilder::new().push_int(value).into_bytes();
+            assert_eq!(hex::encode(&script), expected, "{}", value);
+            if script.len() > 1 && script.len() <= 5 {
+                assert_eq!(decode_script_num(&script[1..]), value);
+            }
+        }
+    }
+
+    #[test]
+    fn test_builder_push_slice() {
+        let push = |data: &[u8]| ScriptBuilder::new().push_slice(data).into_bytes();
+        assert_eq!(push(&[]), [OP_0]);
+        assert_eq!(push(&[0x05]), [0x55]);
+        assert_eq!(push(&[0x10]), [OP_16]);
+        assert_eq!(push(&[0x81]), [OP_1NEGATE]);
+        assert_eq!(push(&[0x11]), [0x01, 0x11]);
+        assert_eq!(push(&[0x00]), [0x01, 0x00]);
+        assert_eq!(push(&[0xaa; 75])[..1], [75]);
+        assert_eq!(push(&[0xaa; 76])[..2], [OP_PUSHDATA1, 76]);
+        assert_eq!(push(&[0xaa; 256])[..3], [OP_PUSHDATA2, 0x00, 0x01]);
+        assert_eq!(
+            push(&[0xaa; 0x10000])[..5],
+            [OP_PUSHDATA4, 0x00, 0x00, 0x01, 0x00]
+        );
+        for len in [0, 1, 75, 76, 255, 256, 0x10000] {
+            let script = push(&vec![0xaa; len]);
+            let mut instructions = Script::new(&script).instructions();
+            assert_eq!(
+                instructions.next().unwrap().unwrap(),
+                Instruction::PushBytes(&vec![0xaa; len])
+            );
+            assert!(instructions.next().is_none());
+        }
+
+        let builder = ScriptBuilder::new()
+            .push_opcode(crate::opcodes::OP_DUP)
+            .push_raw(&[0xa9]);
+        assert_eq!(builder.as_script().to_asm(false), "OP_DUP OP_HASH160");
+        assert_eq!(builder.len(), 2);
+    }
+}
@@ -0,0 +1,295 @@
+use crate::{
+    opcodes::{
+        OP_0, OP_1, OP_16, OP_CHECKMULTISIG, OP_CHECKSIG, OP_DUP, OP_EQUAL, OP_EQUALVERIFY,
+        OP_HASH160, OP_RETURN,
+    },
+    script::{Instruction, Script},
+};
+
+/// Largest null data output relayed by default, Core's `MAX_OP_RETURN_RELAY`.
+pub const MAX_OP_RETURN_RELAY: usize = 83;
+
+/// Output script template, the result of Core's `Solver`, with the data each
+/// template commits to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ScriptType<'a> {
+    NonStandard,
+    PubKey(&'a [u8]),
+    PubKeyHash(&'a [u8; 20]),
+    ScriptHash(&'a [u8; 20]),
+    Multisig {
+        required: u8,
+        pubkeys: Vec<&'a [u8]>,
+    },
+    NullData,
+    WitnessV0KeyHash(&'a [u8; 20]),
+    WitnessV0ScriptHash(&'a [u8; 32]),
+    WitnessV1Taproot(&'a [u8; 32]),
+    WitnessUnknown {
+        version: u8,
+        program: &'a [u8],
+    },
+}
+
+impl ScriptType<'_> {
+    /// Name used for the `type` field of Core's RPC output.
+    pub fn name(&self) -> &'static str {
+        match self {
+            ScriptType::NonStandard => "nonstandard",
+            ScriptType::PubKey(_) => "pubkey",
+            ScriptType::PubKeyHash(_) => "pubkeyhash",
+            ScriptType::ScriptHash(_) => "scripthash",
+            ScriptType::Multisig { .. } => "multisig",
+            ScriptType::NullData => "nulldata",
+            ScriptType::WitnessV0KeyHash(_) => "witness_v0_keyhash",
+            ScriptType::WitnessV0ScriptHash(_) => "witness_v0_scripthash",
+            ScriptType::WitnessV1Taproot(_) => "witness_v1_taproot",
+            ScriptType::WitnessUnknown { .. } => "witness_unknown",
+        }
+    }
+}
+
+impl<'a> Script<'a> {
+    /// Classifies the script as an output template.
+    pub fn script_type(&self) -> ScriptType<'a> {
+        let script = self.as_bytes();
+        if let [OP_HASH160, 20, hash @ .., OP_EQUAL] = script {
+            if let Ok(hash) = hash.try_into() {
+                return ScriptType::ScriptHash(hash);
+            }
+        }
+        if let Some((version, program)) = self.witness_program() {
+            return match (version, program.len()) {
+                (0, 20) => ScriptType::WitnessV0KeyHash(program.try_into().unwrap()),
+                (0, 32) => ScriptType::WitnessV0ScriptHash(program.try_into().unwrap()),
+                (0, _) => ScriptType::NonStandard,
+                (1, 32) => ScriptType::WitnessV1Taproot(program.try_into().unwrap()),
+                _ => ScriptType::WitnessUnknown { version, program },
+            };
+        }
+        if let [OP_RETURN, rest @ ..] = script {
+            if Script::new(rest).is_push_only() {
+                return ScriptType::NullData;
+            }
+        }
+        if let [len, pubkey @ .., OP_CHECKSIG] = script {
+            if *len as usize == pubkey.len() && is_valid_pubkey_size(pubkey) {
+                return ScriptType::PubKey(pubkey);
+            }
+        }
+        if let [OP_DUP, OP_HASH160, 20, hash @ .., OP_EQUALVERIFY, OP_CHECKSIG] = script {
+            if let Ok(hash) = hash.try_into() {
+                return ScriptType::PubKeyHash(hash);
+            }
+        }
+        self.multisig().unwrap_or(ScriptType::NonStandard)
+    }
+
+    /// Witness version and program of a BIP141 witness output: a version
+    /// opcode followed by a single push of 2 to 40 bytes.
+    pub fn witness_program(&self) -> Option<(u8, &'a [u8])> {
+        let script = self.as_bytes();
+        match script {
+            [version @ (OP_0 | OP_1..=OP_16), len, program @ ..]
+                if (4..=42).contains(&script.len()) && *len as usize == program.len() =>
+            {
+                let version = if *version == OP_0 {
+                    0
+                } else {
+                    version - OP_1 + 1
+                };
+                Some((version, program))
+            }
+            _ => None,
+        }
+    }
+
+    /// Core's `IsStandard` for output scripts: a known template, multisig of at
+    /// most 3 keys, and null data no larger than [`MAX_OP_RETURN_RELAY`].
+    pub fn is_standard(&self) -> bool {
+        match self.script_type() {
+            ScriptType::NonStandard => false,
+            ScriptType::Multisig { pubkeys, .. } => pubkeys.len() <= 3,
+            ScriptType::NullData => self.len() <= MAX_OP_RETURN_RELAY,
+            _ => true,
+        }
+    }
+
+    fn multisig(&self) -> Option<ScriptType<'a>> {
+        let small_int = |instruction: Option<Instruction>| match instruction? {
+            Instruction::Op(opcode @ OP_1..=OP_16) => Some(opcode - OP_1 + 1),
+            _ => None,
+        };
+        let mut instructions = self.instructions();
+        let required = small_int(instructions.next()?.ok())?;
+        let mut pubkeys = Vec::new();
+        let key_count = loop {
+            match instructions.next()?.ok()? {
+                Instruction::PushBytes(pubkey) if is_valid_pubkey_size(pubkey) => {
+                    pubkeys.push(pubkey)
+                }
+                instruction => break small_int(Some(instruction))?,
+            }
+        };
+        if key_count as usize != pubkeys.len() || required > key_count {
+            return None;
+        }
+        match (instructions.next()?.ok()?, instructions.next()) {
+            (Instruction::Op(OP_CHECKMULTISIG), None) => {
+                Some(ScriptType::Multisig { required, pubkeys })
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Core's `CPubKey::ValidSize`: the prefix byte has to match the length.
+fn is_valid_pubkey_size(pubkey: &[u8]) -> bool {
+    match pubkey.first() {
+        Some(0x02 | 0x03) => pubkey.len() == 33,
+        Some(0x04 | 0x06 | 0x07) => pubkey.len() == 65,
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ScriptBuilder, Transaction};
+
+    fn type_name(script_hex: &str) -> &'static str {
+        Script::new(&hex::decode(script_hex).unwrap())
+            .script_type()
+            .name()
+    }
+
+    #[test]
+    fn test_block_170_p2pk() {
+        let transaction =
+            Transaction::deserialize(&hex::decode(crate::TRANSACTION_SERIALIZED).unwrap()).unwrap();
+        for output in &transaction.outputs {
+            let script = Script::new(&output.script_pub_key);
+            match script.script_type() {
+                ScriptType::PubKey(pubkey) => {
+                    assert_eq!(pubkey.len(), 65);
+                    assert_eq!(pubkey, &output.script_pub_key[1..66]);
+                }
+                other => panic!("expected P2PK, got {:?}", other),
+            }
+            assert!(script.is_standard());
+        }
+    }
+
+    #[test]
+    fn test_templates() {
+        let hash20 = "89abcdefabbaabbaabbaabbaabbaabbaabbaabba";
+        let hash32 = "1863143c14c5166804bd19203356da136c985678cd4d27a1b8c6329604903262";
+        let hash = hex::decode(hash20).unwrap();
+        let script = hex::decode(format!("76a914{}88ac", hash20)).unwrap();
+        assert_eq!(
+            Script::new(&script).script_type(),
+            ScriptType::PubKeyHash(hash.as_slice().try_into().unwrap())
+        );
+        let script = hex::decode(format!("0020{}", hash32)).unwrap();
+        assert_eq!(
+            Script::new(&script).witness_program(),
+            Some((0, &script[2..]))
+        );
+        let program: Vec<u8> = (0..40).collect();
+        let script = hex::decode(format!("6028{}", hex::encode(&program))).unwrap();
+        assert_eq!(
+            Script::new(&script).script_type(),
+            ScriptType::WitnessUnknown {
+                version: 16,
+                program: &program
+            }
+        );
+
+        assert_eq!(type_name(&format!("a914{}87", hash20)), "scripthash");
+        assert_eq!(type_name(&format!("0014{}", hash20)), "witness_v0_keyhash");
+        assert_eq!(
+            type_name(&format!("0020{}", hash32)),
+            "witness_v0_scripthash"
+        );
+        assert_eq!(type_name(&format!("5120{}", hash32)), "witness_v1_taproot");
+        assert_eq!(type_name("51024e73"), "witness_unknown");
+        assert_eq!(
+            type_name("2102aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaac"),
+            "pubkey"
+        );
+        assert_eq!(type_name("6a"), "nulldata");
+        assert_eq!(type_name("6a0401020304"), "nulldata");
+        assert_eq!(type_name("6a4c0401020304"), "nulldata");
+
+        for nonstandard in [
+            // Version 0 with a program of the wrong size
+            format!("0015{}00", hash20),
+            // Push runs past the end
+            "6a05010203".to_string(),
+            // OP_RETURN followed by a non-push
+            "6a76".to_string(),
+            // Compressed key length with an uncompressed prefix
+            "2104aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaac".to_string(),
+            format!("76a914{}88ad", hash20),
+            "51".to_string(),
+            String::new(),
+        ] {
+            let script_bytes = hex::decode(&nonstandard).unwrap();
+            let script = Script::new(&script_bytes);
+            assert_eq!(
+                script.script_type(),
+                ScriptType::NonStandard,
+                "{}",
+                nonstandard
+            );
+            assert!(!script.is_standard());
+        }
+    }
+
+    #[test]
+    fn test_multisig() {
+        let key_a = "022afc20bf379bc96a2f4e9e63ffceb8652b2b6a097f63fbee6ecec2a49a48010e";
+        let key_b = "03a767c7221e9f15f870f1ad9311f5ab937d79fcaeee15bb2c722bca515581b4c0";
+        let script = hex::decode(format!("5221{}21{}52ae", key_a, key_b)).unwrap();
+        match Script::new(&script).script_type() {
+            ScriptType::Multisig { required, pubkeys } => {
+                assert_eq!(required, 2);
+                assert_eq!(pubkeys.len(), 2);
+                assert_eq!(hex::encode(pubkeys[1]), key_b);
+            }
+            other => panic!("expected multisig, got {:?}", other),
+        }
+
+        // Required above the key count, count mismatch, trailing opcode, zero required
+        for invalid in [
+            format!("5321{}21{}52ae", key_a, key_b),
+            format!("5121{}21{}53ae", key_a, key_b),
+            format!("5121{}21{}52ae51", key_a, key_b),
+            format!("0021{}51ae", key_a),
+        ] {
+            assert_eq!(type_name(&invalid), "nonstandard", "{}", invalid);
+        }
+
+        let four_keys = format!("51{}54ae", format!("21{}", key_a).repeat(4));
+        let script_bytes = hex::decode(&four_keys).unwrap();
+        let script = Script::new(&script_bytes);
+        assert_eq!(script.script_type().name(), "multisig");
+        assert!(!script.is_standard());
+    }
+
+    #[test]
+    fn test_null_data_standardness() {
+        let null_data = |len: usize| {
+            ScriptBuilder::new()
+                .push_opcode(OP_RETURN)
+                .push_slice(&vec![0xaa; len])
+                .into_bytes()
+        };
+        let script = null_data(80);
+        assert_eq!(script.len(), MAX_OP_RETURN_RELAY);
+        assert!(Script::new(&script).is_standard());
+        let script = null_data(81);
+        assert_eq!(Script::new(&script).script_type(), ScriptType::NullData);
+        assert!(!Script::new(&script).is_standard());
+    }
+}
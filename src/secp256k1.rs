@@ -0,0 +1,577 @@
+use crate::{error::Secp256k1Error, Result, Sighash, U256};
+
+/// Field prime, 2^256 - 2^32 - 977.
+const P: Modulus = Modulus(U256::from_limbs([
+    0xfffffffefffffc2f,
+    0xffffffffffffffff,
+    0xffffffffffffffff,
+    0xffffffffffffffff,
+]));
+
+/// Order of the group generated by [`G`].
+const N: Modulus = Modulus(U256::from_limbs([
+    0xbfd25e8cd0364141,
+    0xbaaedce6af48a03b,
+    0xfffffffffffffffe,
+    0xffffffffffffffff,
+]));
+
+const G: JacobianPoint = JacobianPoint {
+    x: U256::from_limbs([
+        0x59f2815b16f81798,
+        0x029bfcdb2dce28d9,
+        0x55a06295ce870b07,
+        0x79be667ef9dcbbac,
+    ]),
+    y: U256::from_limbs([
+        0x9c47d08ffb10d4b8,
+        0xfd17b448a6855419,
+        0x5da4fbfc0e1108a8,
+        0x483ada7726a3c465,
+    ]),
+    z: U256::ONE,
+};
+
+/// Arithmetic modulo a prime above 2^255, so any `U256` is less than twice it.
+struct Modulus(U256);
+
+impl Modulus {
+    fn reduce(&self, a: U256) -> U256 {
+        if a >= self.0 {
+            a - self.0
+        } else {
+            a
+        }
+    }
+
+    fn add(&self, a: U256, b: U256) -> U256 {
+        match a.overflowing_add(b) {
+            (sum, false) => self.reduce(sum),
+            (sum, true) => sum.overflowing_sub(self.0).0,
+        }
+    }
+
+    fn sub(&self, a: U256, b: U256) -> U256 {
+        match a.overflowing_sub(b) {
+            (difference, false) => difference,
+            (difference, true) => difference.overflowing_add(self.0).0,
+        }
+    }
+
+    fn mul(&self, a: U256, b: U256) -> U256 {
+        // 2^256 is congruent to 2^256 - m, fold the high half in until it is
+        // gone. It shrinks by at least 127 bits each round.
+        let fold = U256::ZERO.overflowing_sub(self.0).0;
+        let (mut low, mut high) = a.widening_mul(b);
+        while !high.is_zero() {
+            let (product_low, product_high) = high.widening_mul(fold);
+            let (sum, carry) = low.overflowing_add(product_low);
+            low = sum;
+            high = product_high + U256::from(carry as u64);
+        }
+        self.reduce(low)
+    }
+
+    fn pow(&self, base: U256, exponent: U256) -> U256 {
+        let mut result = U256::ONE;
+        for i in (0..exponent.bits()).rev() {
+            result = self.mul(result, result);
+            if exponent.bit(i) {
+                result = self.mul(result, base);
+            }
+        }
+        result
+    }
+
+    /// Inverse by Fermat's little theorem, zero for zero.
+    fn inv(&self, a: U256) -> U256 {
+        self.pow(a, self.0 - U256::from(2))
+    }
+}
+
+/// Curve point `(x / z^2, y / z^3)`, the point at infinity when `z` is zero.
+#[derive(Debug, Clone, Copy)]
+struct JacobianPoint {
+    x: U256,
+    y: U256,
+    z: U256,
+}
+
+impl JacobianPoint {
+    const INFINITY: Self = Self {
+        x: U256::ZERO,
+        y: U256::ONE,
+        z: U256::ZERO,
+    };
+
+    fn is_infinity(&self) -> bool {
+        self.z.is_zero()
+    }
+
+    fn double(self) -> Self {
+        if self.is_infinity() || self.y.is_zero() {
+            return Self::INFINITY;
+        }
+        let Self { x, y, z } = self;
+        let y2 = P.mul(y, y);
+        let s = P.mul(P.mul(x, y2), U256::from(4));
+        let m = P.mul(P.mul(x, x), U256::from(3));
+        let x3 = P.sub(P.mul(m, m), P.add(s, s));
+        let y3 = P.sub(P.mul(m, P.sub(s, x3)), P.mul(P.mul(y2, y2), U256::from(8)));
+        Self {
+            x: x3,
+            y: y3,
+            z: P.mul(P.add(y, y), z),
+        }
+    }
+
+    fn add(self, other: Self) -> Self {
+        if self.is_infinity() {
+            return other;
+        }
+        if other.is_infinity() {
+            return self;
+        }
+        let z1z1 = P.mul(self.z, self.z);
+        let z2z2 = P.mul(other.z, other.z);
+        let u1 = P.mul(self.x, z2z2);
+        let u2 = P.mul(other.x, z1z1);
+        let s1 = P.mul(self.y, P.mul(other.z, z2z2));
+        let s2 = P.mul(other.y, P.mul(self.z, z1z1));
+        if u1 == u2 {
+            return if s1 == s2 {
+                self.double()
+            } else {
+                Self::INFINITY
+            };
+        }
+        let h = P.sub(u2, u1);
+        let r = P.sub(s2, s1);
+        let hh = P.mul(h, h);
+        let hhh = P.mul(h, hh);
+        let v = P.mul(u1, hh);
+        let x3 = P.sub(P.sub(P.mul(r, r), hhh), P.add(v, v));
+        let y3 = P.sub(P.mul(r, P.sub(v, x3)), P.mul(s1, hhh));
+        Self {
+            x: x3,
+            y: y3,
+            z: P.mul(h, P.mul(self.z, other.z)),
+        }
+    }
+
+    fn affine_x(&self) -> U256 {
+        let z_inv = P.inv(self.z);
+        P.mul(self.x, P.mul(z_inv, z_inv))
+    }
+}
+
+/// `x^3 + 7`, the square of `y` for points on the curve.
+fn curve_y2(x: U256) -> U256 {
+    P.add(P.mul(P.mul(x, x), x), U256::from(7))
+}
+
+/// secp256k1 public key, a point on the curve other than infinity.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PublicKey {
+    x: U256,
+    y: U256,
+}
+
+impl PublicKey {
+    /// Parses a compressed (33 bytes), uncompressed or hybrid (65 bytes) key
+    /// as libsecp256k1 does, which is what consensus accepts.
+    pub fn from_slice(bytes: &[u8]) -> Result<Self> {
+        let coordinate = |bytes: &[u8]| {
+            let value = U256::from_be_bytes(bytes.try_into().unwrap());
+            (value < P.0).then_some(value)
+        };
+        let key = match bytes {
+            [prefix @ (0x02 | 0x03), x @ ..] if x.len() == 32 => coordinate(x).and_then(|x| {
+                let y2 = curve_y2(x);
+                // P is 3 mod 4, so this is a square root when there is one
+                let y = P.pow(y2, (P.0 + U256::ONE) >> 2);
+                let y = if y.bit(0) == (*prefix == 0x03) {
+                    y
+                } else {
+                    P.sub(U256::ZERO, y)
+                };
+                (P.mul(y, y) == y2).then_some(Self { x, y })
+            }),
+            [prefix @ (0x04 | 0x06 | 0x07), xy @ ..] if xy.len() == 64 => {
+                match (coordinate(&xy[..32]), coordinate(&xy[32..])) {
+                    // Hybrid keys also give the parity of y in their prefix
+                    (Some(x), Some(y))
+                        if P.mul(y, y) == curve_y2(x)
+                            && (*prefix == 0x04 || y.bit(0) == (*prefix == 0x07)) =>
+                    {
+                        Some(Self { x, y })
+                    }
+                    _ => None,
+                }
+            }
+            _ => None,
+        };
+        Ok(key.ok_or(Secp256k1Error::InvalidPublicKey)?)
+    }
+
+    pub fn serialize(&self) -> [u8; 33] {
+        let mut bytes = [0; 33];
+        bytes[0] = if self.y.bit(0) { 0x03 } else { 0x02 };
+        bytes[1..].copy_from_slice(&self.x.to_be_bytes());
+        bytes
+    }
+
+    pub fn serialize_uncompressed(&self) -> [u8; 65] {
+        let mut bytes = [0; 65];
+        bytes[0] = 0x04;
+        bytes[1..33].copy_from_slice(&self.x.to_be_bytes());
+        bytes[33..].copy_from_slice(&self.y.to_be_bytes());
+        bytes
+    }
+
+    /// Checks an ECDSA `signature` of `message`. Both `s` and `n - s` are
+    /// accepted, as they are by consensus.
+    pub fn verify(&self, message: &Sighash, signature: &EcdsaSignature) -> bool {
+        let EcdsaSignature { r, s } = *signature;
+        if r.is_zero() || s.is_zero() {
+            return false;
+        }
+        let e = N.reduce(U256::from_be_bytes(message.to_byte_array()));
+        let s_inv = N.inv(s);
+        let u1 = N.mul(e, s_inv);
+        let u2 = N.mul(r, s_inv);
+
+        // u1 * G + u2 * Q, doubling once for both
+        let q = JacobianPoint {
+            x: self.x,
+            y: self.y,
+            z: U256::ONE,
+        };
+        let g_plus_q = G.add(q);
+        let mut point = JacobianPoint::INFINITY;
+        for i in (0..256).rev() {
+            point = point.double();
+            match (u1.bit(i), u2.bit(i)) {
+                (true, true) => point = point.add(g_plus_q),
+                (true, false) => point = point.add(G),
+                (false, true) => point = point.add(q),
+                (false, false) => {}
+            }
+        }
+        !point.is_infinity() && N.reduce(point.affine_x()) == r
+    }
+}
+
+/// ECDSA signature, `r` and `s` below the group order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EcdsaSignature {
+    r: U256,
+    s: U256,
+}
+
+impl EcdsaSignature {
+    /// `r` then `s`, both big endian.
+    pub fn from_compact(bytes: &[u8; 64]) -> Result<Self> {
+        let r = U256::from_be_bytes(bytes[..32].try_into().unwrap());
+        let s = U256::from_be_bytes(bytes[32..].try_into().unwrap());
+        if r >= N.0 || s >= N.0 {
+            return Err(Secp256k1Error::InvalidSignature.into());
+        }
+        Ok(Self { r, s })
+    }
+
+    /// Core's `ecdsa_signature_parse_der_lax`, the parser consensus uses
+    /// without BIP66: lengths are trusted only as far as the input goes and
+    /// anything after `s` is ignored. Values too large to be scalars give a
+    /// signature that never verifies rather than an error.
+    pub fn from_der_lax(der: &[u8]) -> Result<Self> {
+        let invalid = || Secp256k1Error::InvalidSignature;
+        let mut pos = 0;
+        if der.first() != Some(&0x30) {
+            return Err(invalid().into());
+        }
+        pos += 1;
+        // The sequence length is skipped, long form included
+        let length_byte = *der.get(pos).ok_or_else(invalid)?;
+        pos += 1;
+        if length_byte & 0x80 != 0 {
+            let length_bytes = (length_byte - 0x80) as usize;
+            if length_bytes > der.len() - pos {
+                return Err(invalid().into());
+            }
+            pos += length_bytes;
+        }
+        let (r, pos) = lax_der_integer(der, pos).ok_or_else(invalid)?;
+        let (s, _) = lax_der_integer(der, pos).ok_or_else(invalid)?;
+
+        let scalar = |bytes: &[u8]| {
+            let start = bytes
+                .iter()
+                .position(|&byte| byte != 0)
+                .unwrap_or(bytes.len());
+            let bytes = &bytes[start..];
+            if bytes.len() > 32 {
+                return None;
+            }
+            let mut padded = [0; 32];
+            padded[32 - bytes.len()..].copy_from_slice(bytes);
+            let value = U256::from_be_bytes(padded);
+            (value < N.0).then_some(value)
+        };
+        Ok(match (scalar(r), scalar(s)) {
+            (Some(r), Some(s)) => Self { r, s },
+            _ => Self {
+                r: U256::ZERO,
+                s: U256::ZERO,
+            },
+        })
+    }
+
+    /// Whether `s` is at most half the group order, the only form relay
+    /// policy accepts since either `s` or `n - s` verifies.
+    pub fn is_low_s(&self) -> bool {
+        self.s <= N.0 >> 1
+    }
+
+    /// Replaces a high `s` with `n - s`, returning whether it did.
+    pub fn normalize_s(&mut self) -> bool {
+        if self.is_low_s() {
+            return false;
+        }
+        self.s = N.sub(U256::ZERO, self.s);
+        true
+    }
+
+    pub fn r(&self) -> U256 {
+        self.r
+    }
+
+    pub fn s(&self) -> U256 {
+        self.s
+    }
+}
+
+/// BIP66 strict DER encoding check, hash type byte included. Unlike
+/// [`EcdsaSignature::from_der_lax`] it does not look at the values.
+pub fn is_valid_signature_encoding(sig: &[u8]) -> bool {
+    if sig.len() < 9 || sig.len() > 73 {
+        return false;
+    }
+    if sig[0] != 0x30 || sig[1] as usize != sig.len() - 3 {
+        return false;
+    }
+    let len_r = sig[3] as usize;
+    if 5 + len_r >= sig.len() {
+        return false;
+    }
+    let len_s = sig[5 + len_r] as usize;
+    if len_r + len_s + 7 != sig.len() {
+        return false;
+    }
+    if sig[2] != 0x02 || len_r == 0 || sig[4] & 0x80 != 0 {
+        return false;
+    }
+    if len_r > 1 && sig[4] == 0x00 && sig[5] & 0x80 == 0 {
+        return false;
+    }
+    if sig[len_r + 4] != 0x02 || len_s == 0 || sig[len_r + 6] & 0x80 != 0 {
+        return false;
+    }
+    if len_s > 1 && sig[len_r + 6] == 0x00 && sig[len_r + 7] & 0x80 == 0 {
+        return false;
+    }
+    true
+}
+
+/// One DER integer at `pos`: its content bytes and the position after it.
+fn lax_der_integer(der: &[u8], mut pos: usize) -> Option<(&[u8], usize)> {
+    if der.get(pos) != Some(&0x02) {
+        return None;
+    }
+    pos += 1;
+    let length_byte = *der.get(pos)? as usize;
+    pos += 1;
+    let length = if length_byte & 0x80 != 0 {
+        let mut length_bytes = length_byte - 0x80;
+        if length_bytes > der.len() - pos {
+            return None;
+        }
+        while length_bytes > 0 && der[pos] == 0 {
+            pos += 1;
+            length_bytes -= 1;
+        }
+        if length_bytes >= 4 {
+            return None;
+        }
+        let mut length = 0;
+        for &byte in &der[pos..pos + length_bytes] {
+            length = (length << 8) + byte as usize;
+        }
+        pos += length_bytes;
+        length
+    } else {
+        length_byte
+    };
+    if length > der.len() - pos {
+        return None;
+    }
+    Some((&der[pos..pos + length], pos + length))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The signature and key of the block 170 spend, and the hash it signs.
+    fn block_170_spend() -> (Vec<u8>, PublicKey, Sighash) {
+        let signature = hex::decode("304402204e45e16932b8af514961a1d3a1a25fdf3f4f7732e9d624c6c61548ab5fb8cd410220181522ec8eca07de4860a4acdd12909d831cc56cbbac4622082221a8768d1d09").unwrap();
+        let pubkey = PublicKey::from_slice(&hex::decode("0411db93e1dcdb8a016b49840f8c53bc1eb68a382e97b1482ecad7b148a6909a5cb2e0eaddfb84ccf9744464f82e160bfa9b8b64f9d4c03f999b8643f656b412a3").unwrap()).unwrap();
+        let message =
+            hex::decode("7a05c6145f10101e9d6325494245adf1297d80f8f38d4d576d57cdba220bcb19")
+                .unwrap();
+        (
+            signature,
+            pubkey,
+            Sighash::from_byte_array(message.try_into().unwrap()),
+        )
+    }
+
+    #[test]
+    fn test_modular_arithmetic() {
+        let a = U256::MAX;
+        assert_eq!(P.mul(a, P.inv(a)), U256::ONE);
+        assert_eq!(
+            N.mul(N.sub(U256::ZERO, U256::ONE), N.sub(U256::ZERO, U256::ONE)),
+            U256::ONE
+        );
+        assert_eq!(P.add(P.0 - U256::ONE, U256::from(2)), U256::ONE);
+        assert_eq!(P.inv(U256::ZERO), U256::ZERO);
+        assert_eq!(P.mul(G.y, G.y), curve_y2(G.x));
+    }
+
+    #[test]
+    fn test_public_key_encodings() {
+        let (_, pubkey, _) = block_170_spend();
+        let compressed = pubkey.serialize();
+        assert_eq!(PublicKey::from_slice(&compressed).unwrap(), pubkey);
+        let uncompressed = pubkey.serialize_uncompressed();
+        assert_eq!(PublicKey::from_slice(&uncompressed).unwrap(), pubkey);
+
+        let mut hybrid = uncompressed;
+        hybrid[0] = 0x06 | compressed[0] & 1;
+        assert_eq!(PublicKey::from_slice(&hybrid).unwrap(), pubkey);
+        hybrid[0] ^= 1;
+        assert!(PublicKey::from_slice(&hybrid).is_err());
+
+        let mut off_curve = uncompressed;
+        off_curve[64] ^= 1;
+        assert!(PublicKey::from_slice(&off_curve).is_err());
+        // x = 5 has no y, x = p is out of range
+        let mut no_y = [0; 33];
+        no_y[0] = 0x02;
+        no_y[32] = 5;
+        assert!(PublicKey::from_slice(&no_y).is_err());
+        let mut too_large = [0x02; 33];
+        too_large[1..].copy_from_slice(&P.0.to_be_bytes());
+        assert!(PublicKey::from_slice(&too_large).is_err());
+        assert!(PublicKey::from_slice(&compressed[..32]).is_err());
+        assert!(PublicKey::from_slice(&[]).is_err());
+    }
+
+    #[test]
+    fn test_verify_block_170_signature() {
+        let (der, pubkey, message) = block_170_spend();
+        let signature = EcdsaSignature::from_der_lax(&der).unwrap();
+        assert!(pubkey.verify(&message, &signature));
+        let compressed = PublicKey::from_slice(&pubkey.serialize()).unwrap();
+        assert!(compressed.verify(&message, &signature));
+
+        // The other s is just as valid
+        let high_s = EcdsaSignature {
+            r: signature.r,
+            s: N.sub(U256::ZERO, signature.s),
+        };
+        assert!(pubkey.verify(&message, &high_s));
+
+        let mut other_message = message.to_byte_array();
+        other_message[0] ^= 1;
+        assert!(!pubkey.verify(&Sighash::from_byte_array(other_message), &signature));
+        let other_key = PublicKey { x: G.x, y: G.y };
+        assert!(!other_key.verify(&message, &signature));
+        let zero = EcdsaSignature::from_compact(&[0; 64]).unwrap();
+        assert!(!pubkey.verify(&message, &zero));
+    }
+
+    #[test]
+    fn test_lax_der() {
+        let (der, pubkey, message) = block_170_spend();
+        let signature = EcdsaSignature::from_der_lax(&der).unwrap();
+
+        // Wrong sequence length, long form lengths, padding and trailing bytes
+        let mut lax = vec![0x30, 0x84, 0, 0, 0, 0, 0x02, 0x81, 0x21, 0x00];
+        lax.extend_from_slice(&der[4..36]);
+        lax.extend_from_slice(&[0x02, 0x82, 0x00, 0x20]);
+        lax.extend_from_slice(&der[38..]);
+        lax.push(0x01);
+        assert_eq!(EcdsaSignature::from_der_lax(&lax).unwrap(), signature);
+        assert!(pubkey.verify(&message, &EcdsaSignature::from_der_lax(&lax).unwrap()));
+
+        // r of 33 significant bytes parses but never verifies
+        let mut overflow = vec![0x30, 0x45, 0x02, 0x21, 0x01];
+        overflow.extend_from_slice(&der[4..]);
+        let overflowed = EcdsaSignature::from_der_lax(&overflow).unwrap();
+        assert_eq!(overflowed.r(), U256::ZERO);
+        assert!(!pubkey.verify(&message, &overflowed));
+
+        for malformed in [
+            &[][..],
+            &der[1..],
+            &der[..der.len() - 1],
+            &[0x30, 0x00, 0x02, 0x01, 0x01],
+            &[0x30, 0x00, 0x02, 0x84, 1, 0, 0, 0],
+        ] {
+            assert!(
+                EcdsaSignature::from_der_lax(malformed).is_err(),
+                "{:02x?}",
+                malformed
+            );
+        }
+        let mut compact = [0xff; 64];
+        assert!(EcdsaSignature::from_compact(&compact).is_err());
+        compact[..32].copy_from_slice(&signature.r.to_be_bytes());
+        compact[32..].copy_from_slice(&signature.s.to_be_bytes());
+        assert_eq!(EcdsaSignature::from_compact(&compact).unwrap(), signature);
+    }
+
+    #[test]
+    fn test_strict_der_and_low_s() {
+        let (der, pubkey, message) = block_170_spend();
+        let mut with_hash_type = der.clone();
+        with_hash_type.push(0x01);
+        assert!(is_valid_signature_encoding(&with_hash_type));
+        // The hash type byte is part of the checked length
+        assert!(!is_valid_signature_encoding(&der));
+        let mut padded_r = vec![0x30, 0x45, 0x02, 0x21, 0x00];
+        padded_r.extend_from_slice(&with_hash_type[4..]);
+        assert!(!is_valid_signature_encoding(&padded_r));
+        let mut negative_s = with_hash_type.clone();
+        negative_s[38] = 0x80;
+        assert!(!is_valid_signature_encoding(&negative_s));
+
+        let mut signature = EcdsaSignature::from_der_lax(&der).unwrap();
+        assert!(signature.is_low_s());
+        assert!(!signature.normalize_s());
+        let mut high_s = EcdsaSignature {
+            r: signature.r,
+            s: N.sub(U256::ZERO, signature.s),
+        };
+        assert!(!high_s.is_low_s());
+        assert!(high_s.normalize_s());
+        assert_eq!(high_s, signature);
+        assert!(pubkey.verify(&message, &high_s));
+        signature.s = N.0 >> 1;
+        assert!(signature.is_low_s());
+        signature.s = signature.s + U256::ONE;
+        assert!(!signature.is_low_s());
+    }
+}
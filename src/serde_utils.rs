@@ -0,0 +1,205 @@
+//! Serde helpers: hex strings in human-readable formats, raw bytes otherwise.
+
+use std::fmt;
+
+use serde::{
+    de::{self, SeqAccess, Visitor},
+    Deserializer, Serializer,
+};
+
+struct BytesVisitor;
+
+impl<'de> Visitor<'de> for BytesVisitor {
+    type Value = Vec<u8>;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a hex string or a byte array")
+    }
+
+    fn visit_str<E: de::Error>(self, value: &str) -> Result<Self::Value, E> {
+        hex::decode(value).map_err(E::custom)
+    }
+
+    fn visit_bytes<E: de::Error>(self, value: &[u8]) -> Result<Self::Value, E> {
+        Ok(value.to_vec())
+    }
+
+    fn visit_byte_buf<E: de::Error>(self, value: Vec<u8>) -> Result<Self::Value, E> {
+        Ok(value)
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+        let mut bytes = Vec::with_capacity(seq.size_hint().unwrap_or(0).min(4096));
+        while let Some(byte) = seq.next_element()? {
+            bytes.push(byte);
+        }
+        Ok(bytes)
+    }
+}
+
+fn deserialize_bytes<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+    if deserializer.is_human_readable() {
+        deserializer.deserialize_str(BytesVisitor)
+    } else {
+        deserializer.deserialize_byte_buf(BytesVisitor)
+    }
+}
+
+/// Scripts and other variable length byte strings.
+pub mod hex_bytes {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(bytes: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+        if serializer.is_human_readable() {
+            serializer.serialize_str(&hex::encode(bytes))
+        } else {
+            serializer.serialize_bytes(bytes)
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+        deserialize_bytes(deserializer)
+    }
+}
+
+/// Witness stacks, a list of byte strings.
+pub mod hex_bytes_list {
+    use serde::{ser::SerializeSeq, Deserialize, Serialize};
+
+    use super::*;
+
+    struct Item<'a>(&'a [u8]);
+
+    impl Serialize for Item<'_> {
+        fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            hex_bytes::serialize(self.0, serializer)
+        }
+    }
+
+    struct OwnedItem(Vec<u8>);
+
+    impl<'de> Deserialize<'de> for OwnedItem {
+        fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+            hex_bytes::deserialize(deserializer).map(OwnedItem)
+        }
+    }
+
+    pub fn serialize<S: Serializer>(items: &[Vec<u8>], serializer: S) -> Result<S::Ok, S::Error> {
+        let mut seq = serializer.serialize_seq(Some(items.len()))?;
+        for item in items {
+            seq.serialize_element(&Item(item))?;
+        }
+        seq.end()
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Vec<Vec<u8>>, D::Error> {
+        let items = Vec::<OwnedItem>::deserialize(deserializer)?;
+        Ok(items.into_iter().map(|item| item.0).collect())
+    }
+}
+
+/// 32-byte hashes. Human-readable formats use the byte-reversed RPC order,
+/// binary formats the internal order.
+pub mod hex_hash {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(hash: &[u8; 32], serializer: S) -> Result<S::Ok, S::Error> {
+        if serializer.is_human_readable() {
+            let mut reversed = *hash;
+            reversed.reverse();
+            serializer.serialize_str(&hex::encode(reversed))
+        } else {
+            serializer.serialize_bytes(hash)
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<[u8; 32], D::Error> {
+        let human_readable = deserializer.is_human_readable();
+        let bytes = deserialize_bytes(deserializer)?;
+        let mut hash: [u8; 32] = bytes
+            .try_into()
+            .map_err(|bytes: Vec<u8>| de::Error::invalid_length(bytes.len(), &"32 bytes"))?;
+        if human_readable {
+            hash.reverse();
+        }
+        Ok(hash)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{Block, Transaction};
+
+    const BLOCK_170: &str = "0100000055bd840a78798ad0da853f68974f3d183e2bd1db6a842c1feecf222a00000000ff104ccb05421ab93e63f8c3ce5c2c2e9dbb37de2764b3a3175c8166562cac7d51b96a49ffff001d283e9e700201000000010000000000000000000000000000000000000000000000000000000000000000ffffffff0704ffff001d0102ffffffff0100f2052a01000000434104d46c4968bde02899d2aa0963367c7a6ce34eec332b32e42e5f3407e052d64ac625da6f0718e7b302140434bd725706957c092db53805b821a85b23a7ac61725bac000000000100000001c997a5e56e104102fa209c6a852dd90660a20b2d9c352423edce25857fcd3704000000004847304402204e45e16932b8af514961a1d3a1a25fdf3f4f7732e9d624c6c61548ab5fb8cd410220181522ec8eca07de4860a4acdd12909d831cc56cbbac4622082221a8768d1d0901ffffffff0200ca9a3b00000000434104ae1a62fe09c5f51b13905f07f06b99a2f7159b2225f374cd378d71302fa28414e7aab37397f554a7df5f142c21c1b7303b8a0626f1baded5c72a704f7e6cd84cac00286bee0000000043410411db93e1dcdb8a016b49840f8c53bc1eb68a382e97b1482ecad7b148a6909a5cb2e0eaddfb84ccf9744464f82e160bfa9b8b64f9d4c03f999b8643f656b412a3ac00000000";
+    const SEGWIT_TRANSACTION: &str = "02000000000101595895ea20179de87052b4046dfe6fd515860505d6511a9004cf12a1f93cac7c0100000000ffffffff01deb807000000000017a9140f3444e271620c736808aa7b33e370bd87cb5a078702483045022100fb60dad8df4af2841adc0346638c16d0b8035f5e3f3753b88db122e70c79f9370220756e6633b17fd2710e626347d28d60b0a2d6cbb41de51740644b9fb3ba7751040121028fa937ca8cba2197a37c007176ed8941055d3bcb8627d085e94553e62f057dcc00000000";
+
+    #[test]
+    fn test_block_json_round_trip() {
+        let payload = hex::decode(BLOCK_170).unwrap();
+        let block = Block::deserialize(&payload).unwrap();
+
+        let json = serde_json::to_value(&block).unwrap();
+        assert_eq!(
+            json["block_header"]["previous_block_hash"],
+            "000000002a22cfee1f2c846adbd12b3e183d4f97683f85dad08a79780a84bd55"
+        );
+        assert_eq!(
+            json["transactions"][1]["inputs"][0]["previous_output"]["hash"],
+            "0437cd7f8525ceed2324359c2d0ba26006d92d856a9c20fa0241106ee5a597c9"
+        );
+        assert_eq!(
+            json["transactions"][0]["inputs"][0]["script_sig"],
+            "04ffff001d0102"
+        );
+        assert_eq!(json["transactions"][1]["outputs"][0]["value"], 1000000000);
+
+        let decoded: Block = serde_json::from_value(json).unwrap();
+        assert_eq!(decoded.serialize(), payload);
+    }
+
+    #[test]
+    fn test_witness_json_round_trip() {
+        let payload = hex::decode(SEGWIT_TRANSACTION).unwrap();
+        let transaction = Transaction::deserialize(&payload).unwrap();
+
+        let json = serde_json::to_string(&transaction).unwrap();
+        assert!(json.contains(
+            "\"witness\":[\"3045022100fb60dad8df4af2841adc0346638c16d0b8035f5e3f3753b88db122e70c79f9370220756e6633b17fd2710e626347d28d60b0a2d6cbb41de51740644b9fb3ba77510401\",\"028fa937ca8cba2197a37c007176ed8941055d3bcb8627d085e94553e62f057dcc\"]"
+        ));
+
+        let decoded: Transaction = serde_json::from_str(&json).unwrap();
+        assert_eq!(decoded.serialize(), payload);
+    }
+
+    #[test]
+    fn test_binary_round_trip() {
+        let payload = hex::decode(BLOCK_170).unwrap();
+        let block = Block::deserialize(&payload).unwrap();
+
+        let encoded = bincode::serialize(&block).unwrap();
+        // Raw bytes, not hex: the encoding is not much larger than the block itself
+        assert!(encoded.len() < payload.len() * 2);
+        let decoded: Block = bincode::deserialize(&encoded).unwrap();
+        assert_eq!(decoded.serialize(), payload);
+        assert_eq!(
+            decoded.block_header.previous_block_hash,
+            block.block_header.previous_block_hash
+        );
+
+        let transaction =
+            Transaction::deserialize(&hex::decode(SEGWIT_TRANSACTION).unwrap()).unwrap();
+        let decoded: Transaction =
+            bincode::deserialize(&bincode::serialize(&transaction).unwrap()).unwrap();
+        assert_eq!(decoded.wtxid(), transaction.wtxid());
+    }
+
+    #[test]
+    fn test_invalid_hex_is_rejected() {
+        let json = r#"{"hash":"zz","index":0}"#;
+        assert!(serde_json::from_str::<crate::OutPoint>(json).is_err());
+        let json = r#"{"hash":"00","index":0}"#;
+        assert!(serde_json::from_str::<crate::OutPoint>(json).is_err());
+    }
+}
@@ -0,0 +1,920 @@
+use std::{cell::OnceCell, io::Write};
+
+use sha2::{Digest, Sha256};
+
+use crate::{
+    encode::{encode_var_bytes, CompactSize, Encodable},
+    opcodes::{OP_CHECKSIG, OP_CODESEPARATOR, OP_DUP, OP_EQUALVERIFY, OP_HASH160},
+    Amount, Instruction, Result, Script, ScriptType, Sighash, SighashError, Transaction,
+    TransactionInput, TransactionOutput,
+};
+
+/// Taproot only, signs like `SIGHASH_ALL` but is left out of the signature.
+pub const SIGHASH_DEFAULT: u32 = 0x00;
+pub const SIGHASH_ALL: u32 = 0x01;
+pub const SIGHASH_NONE: u32 = 0x02;
+pub const SIGHASH_SINGLE: u32 = 0x03;
+pub const SIGHASH_ANYONECANPAY: u32 = 0x80;
+
+/// Leaf version of BIP342 tapscript.
+pub const TAPROOT_LEAF_TAPSCRIPT: u8 = 0xc0;
+
+/// First byte of a taproot annex, which no script or key can start with.
+const TAPROOT_ANNEX_TAG: u8 = 0x50;
+
+/// What legacy signing returns instead of failing, the number one. A signature
+/// over it can be reused by anyone, Core keeps it for compatibility.
+const SIGHASH_ONE: Sighash = {
+    let mut one = [0; 32];
+    one[0] = 1;
+    Sighash::from_byte_array(one)
+};
+
+impl Transaction {
+    /// Core's `SignatureHash` for legacy (non-segwit) inputs. `script_code` is
+    /// the script being executed from its last `OP_CODESEPARATOR` on, and
+    /// `hash_type` is the full 32 bit value that is committed to, not just the
+    /// byte at the end of the signature.
+    ///
+    /// An `input_index` out of range, or `SIGHASH_SINGLE` without a matching
+    /// output, gives the hash `1` as Core does.
+    pub fn legacy_sighash(
+        &self,
+        input_index: usize,
+        script_code: &[u8],
+        hash_type: u32,
+    ) -> Sighash {
+        let base_type = hash_type & 0x1f;
+        if input_index >= self.inputs.len()
+            || (base_type == SIGHASH_SINGLE && input_index >= self.outputs.len())
+        {
+            return SIGHASH_ONE;
+        }
+        let mut hasher = Sha256::new();
+        self.encode_legacy_sighash(&mut hasher, input_index, script_code, hash_type)
+            .expect("writing to a hasher never fails");
+        Sighash::from_byte_array(Sha256::digest(hasher.finalize()).into())
+    }
+
+    /// Core's `CTransactionSignatureSerializer`: the transaction without
+    /// witness, with the scriptSig of the signed input replaced by the script
+    /// code, the others emptied, and inputs and outputs left out according to
+    /// `hash_type`.
+    fn encode_legacy_sighash<W: Write>(
+        &self,
+        writer: &mut W,
+        input_index: usize,
+        script_code: &[u8],
+        hash_type: u32,
+    ) -> std::io::Result<()> {
+        let anyone_can_pay = hash_type & SIGHASH_ANYONECANPAY != 0;
+        let base_type = hash_type & 0x1f;
+        let is_none_or_single = base_type == SIGHASH_NONE || base_type == SIGHASH_SINGLE;
+
+        writer.write_all(&self.version.to_le_bytes())?;
+        let inputs: Vec<usize> = if anyone_can_pay {
+            vec![input_index]
+        } else {
+            (0..self.inputs.len()).collect()
+        };
+        CompactSize(inputs.len() as u64).consensus_encode(writer)?;
+        for index in inputs {
+            let input = &self.inputs[index];
+            input.previous_output.consensus_encode(writer)?;
+            if index == input_index {
+                encode_var_bytes(&remove_code_separators(script_code), writer)?;
+            } else {
+                CompactSize(0).consensus_encode(writer)?;
+            }
+            // The other inputs are free to be replaced when not all outputs
+            // are signed
+            let sequence = if index != input_index && is_none_or_single {
+                0
+            } else {
+                input.sequence
+            };
+            writer.write_all(&sequence.to_le_bytes())?;
+        }
+
+        let output_count = match base_type {
+            SIGHASH_NONE => 0,
+            SIGHASH_SINGLE => input_index + 1,
+            _ => self.outputs.len(),
+        };
+        CompactSize(output_count as u64).consensus_encode(writer)?;
+        for (index, output) in self.outputs[..output_count].iter().enumerate() {
+            if base_type == SIGHASH_SINGLE && index != input_index {
+                // Outputs before the signed one are blanked: value -1, empty script
+                writer.write_all(&u64::MAX.to_le_bytes())?;
+                CompactSize(0).consensus_encode(writer)?;
+            } else {
+                output.consensus_encode(writer)?;
+            }
+        }
+
+        writer.write_all(&self.locktime.to_le_bytes())?;
+        writer.write_all(&hash_type.to_le_bytes())
+    }
+}
+
+/// Computes the signature hashes of one transaction, keeping the parts that
+/// are shared between its inputs so they are hashed only once.
+///
+/// Taproot hashes also commit to the outputs every input spends. They are
+/// cached too, so all calls on one cache must be given the same prevouts.
+#[derive(Debug)]
+pub struct SighashCache<'a> {
+    transaction: &'a Transaction,
+    shared: OnceCell<SharedCache>,
+    segwit_v0: OnceCell<SegwitV0Cache>,
+    taproot: OnceCell<TaprootCache>,
+}
+
+/// Single SHA256 of all outpoints, sequences and outputs, used as they are by
+/// BIP341 and hashed once more by BIP143.
+#[derive(Debug)]
+struct SharedCache {
+    prevouts: [u8; 32],
+    sequences: [u8; 32],
+    outputs: [u8; 32],
+}
+
+/// BIP143 `hashPrevouts`, `hashSequence` and `hashOutputs` over all inputs
+/// and outputs.
+#[derive(Debug)]
+struct SegwitV0Cache {
+    prevouts: [u8; 32],
+    sequences: [u8; 32],
+    outputs: [u8; 32],
+}
+
+/// BIP341 `sha_amounts` and `sha_scriptpubkeys` of the spent outputs.
+#[derive(Debug)]
+struct TaprootCache {
+    amounts: [u8; 32],
+    script_pub_keys: [u8; 32],
+}
+
+/// Outputs spent by the inputs of a transaction, which taproot signatures
+/// commit to.
+pub trait PrevoutProvider {
+    /// Output spent by input `input_index`, `None` if it is not known.
+    fn prevout(&self, input_index: usize) -> Option<&TransactionOutput>;
+}
+
+/// The spent outputs in input order.
+impl PrevoutProvider for [TransactionOutput] {
+    fn prevout(&self, input_index: usize) -> Option<&TransactionOutput> {
+        self.get(input_index)
+    }
+}
+
+impl PrevoutProvider for Vec<TransactionOutput> {
+    fn prevout(&self, input_index: usize) -> Option<&TransactionOutput> {
+        self.get(input_index)
+    }
+}
+
+/// BIP342 extension of the taproot signature message for script path spends.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TapscriptExtension {
+    pub leaf_hash: [u8; 32],
+    /// Opcode position of the last executed `OP_CODESEPARATOR`, `u32::MAX`
+    /// when there is none.
+    pub code_separator_position: u32,
+}
+
+impl TapscriptExtension {
+    /// For a leaf of version [`TAPROOT_LEAF_TAPSCRIPT`] before any
+    /// `OP_CODESEPARATOR` is executed.
+    pub fn new(script: &[u8]) -> Self {
+        Self {
+            leaf_hash: tap_leaf_hash(TAPROOT_LEAF_TAPSCRIPT, script),
+            code_separator_position: u32::MAX,
+        }
+    }
+}
+
+/// BIP341 `TapLeaf` tagged hash of a script and its leaf version.
+pub fn tap_leaf_hash(leaf_version: u8, script: &[u8]) -> [u8; 32] {
+    let mut hasher = tagged_hasher(b"TapLeaf");
+    hasher.update([leaf_version]);
+    encode_var_bytes(script, &mut hasher).expect("writing to a hasher never fails");
+    hasher.finalize().into()
+}
+
+impl<'a> SighashCache<'a> {
+    pub fn new(transaction: &'a Transaction) -> Self {
+        Self {
+            transaction,
+            shared: OnceCell::new(),
+            segwit_v0: OnceCell::new(),
+            taproot: OnceCell::new(),
+        }
+    }
+
+    pub fn transaction(&self) -> &'a Transaction {
+        self.transaction
+    }
+
+    /// See [`Transaction::legacy_sighash`], nothing is shared between inputs.
+    pub fn legacy_sighash(
+        &self,
+        input_index: usize,
+        script_code: &[u8],
+        hash_type: u32,
+    ) -> Sighash {
+        self.transaction
+            .legacy_sighash(input_index, script_code, hash_type)
+    }
+
+    /// BIP143 hash for a segwit v0 input spending `amount`. `script_code` is
+    /// the witness script from its last executed `OP_CODESEPARATOR` on for
+    /// P2WSH, or [`Script::p2wpkh_script_code`] for P2WPKH.
+    ///
+    /// # Panics
+    ///
+    /// If `input_index` is out of range.
+    pub fn segwit_v0_sighash(
+        &self,
+        input_index: usize,
+        script_code: &[u8],
+        amount: Amount,
+        hash_type: u32,
+    ) -> Sighash {
+        let mut hasher = Sha256::new();
+        self.encode_segwit_v0_sighash(&mut hasher, input_index, script_code, amount, hash_type)
+            .expect("writing to a hasher never fails");
+        Sighash::from_byte_array(Sha256::digest(hasher.finalize()).into())
+    }
+
+    fn encode_segwit_v0_sighash<W: Write>(
+        &self,
+        writer: &mut W,
+        input_index: usize,
+        script_code: &[u8],
+        amount: Amount,
+        hash_type: u32,
+    ) -> std::io::Result<()> {
+        let transaction = self.transaction;
+        let input = &transaction.inputs[input_index];
+        let anyone_can_pay = hash_type & SIGHASH_ANYONECANPAY != 0;
+        let base_type = hash_type & 0x1f;
+        let zero = [0; 32];
+
+        writer.write_all(&transaction.version.to_le_bytes())?;
+        if anyone_can_pay {
+            writer.write_all(&zero)?;
+        } else {
+            writer.write_all(&self.segwit_v0_cache().prevouts)?;
+        }
+        if anyone_can_pay || base_type == SIGHASH_SINGLE || base_type == SIGHASH_NONE {
+            writer.write_all(&zero)?;
+        } else {
+            writer.write_all(&self.segwit_v0_cache().sequences)?;
+        }
+        input.previous_output.consensus_encode(writer)?;
+        encode_var_bytes(script_code, writer)?;
+        writer.write_all(&amount.to_sat().to_le_bytes())?;
+        writer.write_all(&input.sequence.to_le_bytes())?;
+        match transaction.outputs.get(input_index) {
+            _ if base_type != SIGHASH_SINGLE && base_type != SIGHASH_NONE => {
+                writer.write_all(&self.segwit_v0_cache().outputs)?
+            }
+            // Unlike legacy signing, SINGLE without a matching output commits
+            // to no outputs instead of signing the number one
+            Some(output) if base_type == SIGHASH_SINGLE => {
+                writer.write_all(&Sha256::digest(sha256(|hasher| {
+                    output.consensus_encode(hasher).map(drop)
+                })))?
+            }
+            _ => writer.write_all(&zero)?,
+        }
+        writer.write_all(&transaction.locktime.to_le_bytes())?;
+        writer.write_all(&hash_type.to_le_bytes())
+    }
+
+    /// BIP341 `TapSighash` of input `input_index`. `annex` is the input's
+    /// [`TransactionInput::taproot_annex`] and `tapscript` is set for script
+    /// path spends. Hash types are the byte at the end of the signature, with
+    /// [`SIGHASH_DEFAULT`] for a 64 byte signature.
+    pub fn taproot_sighash<P: PrevoutProvider + ?Sized>(
+        &self,
+        input_index: usize,
+        prevouts: &P,
+        annex: Option<&[u8]>,
+        tapscript: Option<TapscriptExtension>,
+        hash_type: u32,
+    ) -> Result<Sighash> {
+        let mut hasher = tagged_hasher(b"TapSighash");
+        self.encode_taproot_sighash(
+            &mut hasher,
+            input_index,
+            prevouts,
+            annex,
+            tapscript,
+            hash_type,
+        )?;
+        Ok(Sighash::from_byte_array(hasher.finalize().into()))
+    }
+
+    /// BIP341 `SigMsg` with the leading epoch byte, or why it cannot be built.
+    fn encode_taproot_sighash<W: Write, P: PrevoutProvider + ?Sized>(
+        &self,
+        writer: &mut W,
+        input_index: usize,
+        prevouts: &P,
+        annex: Option<&[u8]>,
+        tapscript: Option<TapscriptExtension>,
+        hash_type: u32,
+    ) -> Result<()> {
+        let transaction = self.transaction;
+        let input = transaction
+            .inputs
+            .get(input_index)
+            .ok_or(SighashError::InputIndex {
+                index: input_index,
+                inputs: transaction.inputs.len(),
+            })?;
+        if !matches!(hash_type, SIGHASH_DEFAULT..=SIGHASH_SINGLE | 0x81..=0x83) {
+            return Err(SighashError::InvalidHashType(hash_type).into());
+        }
+        let anyone_can_pay = hash_type & SIGHASH_ANYONECANPAY != 0;
+        let base_type = hash_type & 0x03;
+
+        // Epoch
+        writer.write_all(&[0])?;
+        writer.write_all(&[hash_type as u8])?;
+        writer.write_all(&transaction.version.to_le_bytes())?;
+        writer.write_all(&transaction.locktime.to_le_bytes())?;
+        if !anyone_can_pay {
+            let taproot = self.taproot_cache(prevouts)?;
+            writer.write_all(&self.shared_cache().prevouts)?;
+            writer.write_all(&taproot.amounts)?;
+            writer.write_all(&taproot.script_pub_keys)?;
+            writer.write_all(&self.shared_cache().sequences)?;
+        }
+        if base_type != SIGHASH_NONE && base_type != SIGHASH_SINGLE {
+            writer.write_all(&self.shared_cache().outputs)?;
+        }
+
+        let spend_type = (tapscript.is_some() as u8) << 1 | annex.is_some() as u8;
+        writer.write_all(&[spend_type])?;
+        if anyone_can_pay {
+            let prevout = prevouts
+                .prevout(input_index)
+                .ok_or(SighashError::MissingPrevout(input_index))?;
+            input.previous_output.consensus_encode(writer)?;
+            prevout.consensus_encode(writer)?;
+            writer.write_all(&input.sequence.to_le_bytes())?;
+        } else {
+            writer.write_all(&(input_index as u32).to_le_bytes())?;
+        }
+        if let Some(annex) = annex {
+            if annex.first() != Some(&TAPROOT_ANNEX_TAG) {
+                return Err(SighashError::InvalidAnnex.into());
+            }
+            writer.write_all(&sha256(|hasher| encode_var_bytes(annex, hasher).map(drop)))?;
+        }
+
+        if base_type == SIGHASH_SINGLE {
+            let output = transaction
+                .outputs
+                .get(input_index)
+                .ok_or(SighashError::SingleWithoutOutput(input_index))?;
+            writer.write_all(&sha256(|hasher| output.consensus_encode(hasher).map(drop)))?;
+        }
+
+        if let Some(tapscript) = tapscript {
+            writer.write_all(&tapscript.leaf_hash)?;
+            // Key version
+            writer.write_all(&[0])?;
+            writer.write_all(&tapscript.code_separator_position.to_le_bytes())?;
+        }
+        Ok(())
+    }
+
+    fn shared_cache(&self) -> &SharedCache {
+        self.shared.get_or_init(|| {
+            let transaction = self.transaction;
+            SharedCache {
+                prevouts: sha256(|hasher| {
+                    transaction.inputs.iter().try_for_each(|input| {
+                        input.previous_output.consensus_encode(hasher).map(drop)
+                    })
+                }),
+                sequences: sha256(|hasher| {
+                    transaction
+                        .inputs
+                        .iter()
+                        .try_for_each(|input| hasher.write_all(&input.sequence.to_le_bytes()))
+                }),
+                outputs: sha256(|hasher| {
+                    transaction
+                        .outputs
+                        .iter()
+                        .try_for_each(|output| output.consensus_encode(hasher).map(drop))
+                }),
+            }
+        })
+    }
+
+    fn segwit_v0_cache(&self) -> &SegwitV0Cache {
+        self.segwit_v0.get_or_init(|| {
+            let shared = self.shared_cache();
+            SegwitV0Cache {
+                prevouts: Sha256::digest(shared.prevouts).into(),
+                sequences: Sha256::digest(shared.sequences).into(),
+                outputs: Sha256::digest(shared.outputs).into(),
+            }
+        })
+    }
+
+    fn taproot_cache<P: PrevoutProvider + ?Sized>(&self, prevouts: &P) -> Result<&TaprootCache> {
+        if let Some(cache) = self.taproot.get() {
+            return Ok(cache);
+        }
+        let spent = (0..self.transaction.inputs.len())
+            .map(|index| {
+                prevouts
+                    .prevout(index)
+                    .ok_or(SighashError::MissingPrevout(index))
+            })
+            .collect::<std::result::Result<Vec<_>, _>>()?;
+        Ok(self.taproot.get_or_init(|| TaprootCache {
+            amounts: sha256(|hasher| {
+                spent
+                    .iter()
+                    .try_for_each(|output| hasher.write_all(&output.value.to_sat().to_le_bytes()))
+            }),
+            script_pub_keys: sha256(|hasher| {
+                spent.iter().try_for_each(|output| {
+                    encode_var_bytes(&output.script_pub_key, hasher).map(drop)
+                })
+            }),
+        }))
+    }
+}
+
+impl TransactionInput {
+    /// BIP341 annex: the last witness element if it starts with `0x50` and is
+    /// not the only one.
+    pub fn taproot_annex(&self) -> Option<&[u8]> {
+        match self.witness.as_slice() {
+            [_, .., annex] if annex.first() == Some(&TAPROOT_ANNEX_TAG) => Some(annex),
+            _ => None,
+        }
+    }
+}
+
+impl Script<'_> {
+    /// BIP143 script code of a P2WPKH output: the P2PKH script of its key hash.
+    pub fn p2wpkh_script_code(&self) -> Option<Vec<u8>> {
+        match self.script_type() {
+            ScriptType::WitnessV0KeyHash(hash) => {
+                let mut script_code = vec![OP_DUP, OP_HASH160, 20];
+                script_code.extend_from_slice(hash);
+                script_code.extend_from_slice(&[OP_EQUALVERIFY, OP_CHECKSIG]);
+                Some(script_code)
+            }
+            _ => None,
+        }
+    }
+}
+
+fn sha256<F: FnOnce(&mut Sha256) -> std::io::Result<()>>(encode: F) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    encode(&mut hasher).expect("writing to a hasher never fails");
+    hasher.finalize().into()
+}
+
+/// BIP340 tagged hash engine: SHA256 primed with the hash of `tag` twice.
+fn tagged_hasher(tag: &[u8]) -> Sha256 {
+    let tag_hash = Sha256::digest(tag);
+    let mut hasher = Sha256::new();
+    hasher.update(tag_hash);
+    hasher.update(tag_hash);
+    hasher
+}
+
+/// The script code as signed: every `OP_CODESEPARATOR` is dropped. Bytes after
+/// a malformed push are kept as they are.
+fn remove_code_separators(script_code: &[u8]) -> Vec<u8> {
+    let mut stripped = Vec::with_capacity(script_code.len());
+    let mut instructions = Script::new(script_code).instructions();
+    let mut start = 0;
+    while let Some(Ok(instruction)) = instructions.next() {
+        let end = instructions.position();
+        if instruction != Instruction::Op(OP_CODESEPARATOR) {
+            stripped.extend_from_slice(&script_code[start..end]);
+        }
+        start = end;
+    }
+    stripped.extend_from_slice(&script_code[start..]);
+    stripped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{BitcoinError, Decodable, Decoder, OutPoint, Txid};
+
+    #[test]
+    fn test_core_sighash_vectors() {
+        let vectors: Vec<serde_json::Value> =
+            serde_json::from_str(include_str!("../data/sighash.json")).unwrap();
+        // The first row names the columns
+        for vector in &vectors[1..] {
+            let transaction =
+                Transaction::deserialize(&hex::decode(vector[0].as_str().unwrap()).unwrap())
+                    .unwrap();
+            let script_code = hex::decode(vector[1].as_str().unwrap()).unwrap();
+            let input_index = vector[2].as_u64().unwrap() as usize;
+            // Stored as a signed 32 bit integer
+            let hash_type = vector[3].as_i64().unwrap() as i32 as u32;
+            assert_eq!(
+                transaction
+                    .legacy_sighash(input_index, &script_code, hash_type)
+                    .to_string(),
+                vector[4].as_str().unwrap(),
+                "{}",
+                vector
+            );
+        }
+    }
+
+    #[test]
+    fn test_sighash_single_bug() {
+        let input = TransactionInput {
+            previous_output: OutPoint {
+                hash: Txid::all_zeros(),
+                index: 0,
+            },
+            script_sig: Vec::new(),
+            sequence: u32::MAX,
+            witness: Vec::new(),
+        };
+        let transaction = Transaction {
+            version: 1,
+            inputs: vec![input.clone(), input],
+            outputs: vec![TransactionOutput {
+                value: Amount::ZERO,
+                script_pub_key: Vec::new(),
+            }],
+            locktime: 0,
+        };
+        assert_eq!(
+            transaction.legacy_sighash(1, &[], SIGHASH_SINGLE),
+            SIGHASH_ONE
+        );
+        assert_eq!(
+            transaction.legacy_sighash(2, &[], SIGHASH_ALL).to_string(),
+            format!("{:064x}", 1)
+        );
+        assert_ne!(
+            transaction.legacy_sighash(0, &[], SIGHASH_SINGLE),
+            SIGHASH_ONE
+        );
+    }
+
+    #[test]
+    fn test_block_170_and_code_separators() {
+        let transaction =
+            Transaction::deserialize(&hex::decode(crate::TRANSACTION_SERIALIZED).unwrap()).unwrap();
+        let script_code = &transaction.outputs[1].script_pub_key;
+        // The message of the block 170 signature, the spent block 9 coinbase
+        // pays to the same key as the change output
+        let sighash = transaction.legacy_sighash(0, script_code, SIGHASH_ALL);
+        assert_eq!(
+            hex::encode(sighash.as_byte_array()),
+            "7a05c6145f10101e9d6325494245adf1297d80f8f38d4d576d57cdba220bcb19"
+        );
+        let mut with_separator = vec![OP_CODESEPARATOR];
+        with_separator.extend_from_slice(script_code);
+        with_separator.push(OP_CODESEPARATOR);
+        assert_eq!(
+            transaction.legacy_sighash(0, &with_separator, SIGHASH_ALL),
+            transaction.legacy_sighash(0, script_code, SIGHASH_ALL)
+        );
+        // 0xab inside a push is data, and a truncated push is kept verbatim
+        assert_eq!(remove_code_separators(&[0x01, 0xab, 0xab]), [0x01, 0xab]);
+        assert_eq!(remove_code_separators(&[0xab, 0x02, 0xab]), [0x02, 0xab]);
+    }
+
+    fn bip143_transaction(hex: &str) -> Transaction {
+        Transaction::deserialize(&hex::decode(hex).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn test_bip143_p2wpkh() {
+        let transaction = bip143_transaction(
+            "0100000002fff7f7881a8099afa6940d42d1e7f6362bec38171ea3edf433541db4e4ad969f0000000000eeffffffef51e1b804cc89d182d279655c3aa89e815b1b309fe287d9b2b55d57b90ec68a0100000000ffffffff02202cb206000000001976a9148280b37df378db99f66f85c95a783a76ac7a6d5988ac9093510d000000001976a9143bde42dbee7e4dbe6a21b2d50ce2f0167faa815988ac11000000",
+        );
+        let script_pub_key = hex::decode("00141d0f172a0ecb48aee1be1f2687d2963ae33f71a1").unwrap();
+        let script_code = Script::new(&script_pub_key).p2wpkh_script_code().unwrap();
+        assert_eq!(
+            hex::encode(&script_code),
+            "76a9141d0f172a0ecb48aee1be1f2687d2963ae33f71a188ac"
+        );
+        let cache = SighashCache::new(&transaction);
+        let sighash =
+            cache.segwit_v0_sighash(1, &script_code, Amount::from_sat(600_000_000), SIGHASH_ALL);
+        assert_eq!(
+            hex::encode(sighash.as_byte_array()),
+            "c37af31116d1b27caf68aae9e3ac82f1477929014d5b917657d0eb49478cb670"
+        );
+        let midstates = cache.segwit_v0.get().unwrap();
+        assert_eq!(
+            hex::encode(midstates.prevouts),
+            "96b827c8483d4e9b96712b6713a7b68d6e8003a781feba36c31143470b4efd37"
+        );
+        assert_eq!(
+            hex::encode(midstates.sequences),
+            "52b0a642eea2fb7ae638c36f6252b6750293dbe574a806984b8e4d8548339a3b"
+        );
+        assert_eq!(
+            hex::encode(midstates.outputs),
+            "863ef3e1a92afbfdb97f31ad0fc7683ee943e9abcf2501590ff8f6551f47e5e5"
+        );
+        assert_eq!(Script::new(&script_code).p2wpkh_script_code(), None);
+    }
+
+    #[test]
+    fn test_bip143_p2wpkh_nested_in_p2sh() {
+        let transaction = bip143_transaction(
+            "0100000001db6b1b20aa0fd7b23880be2ecbd4a98130974cf4748fb66092ac4d3ceb1a54770100000000feffffff02b8b4eb0b000000001976a914a457b684d7f0d539a46a45bbc043f35b59d0d96388ac0008af2f000000001976a914fd270b1ee6abcaea97fea7ad0402e8bd8ad6d77c88ac92040000",
+        );
+        let redeem_script = hex::decode("001479091972186c449eb1ded22b78e40d009bdf0089").unwrap();
+        let script_code = Script::new(&redeem_script).p2wpkh_script_code().unwrap();
+        let sighash = SighashCache::new(&transaction).segwit_v0_sighash(
+            0,
+            &script_code,
+            Amount::from_sat(1_000_000_000),
+            SIGHASH_ALL,
+        );
+        assert_eq!(
+            hex::encode(sighash.as_byte_array()),
+            "64f3b0f4dd2bb3aa1ce8566d220cc74dda9df97d8490cc81d89d735c92e59fb6"
+        );
+    }
+
+    #[test]
+    fn test_bip143_p2wsh_nested_in_p2sh() {
+        let transaction = bip143_transaction(
+            "010000000136641869ca081e70f394c6948e8af409e18b619df2ed74aa106c1ca29787b96e0100000000ffffffff0200e9a435000000001976a914389ffce9cd9ae88dcc0631e88a821ffdbe9bfe2688acc0832f05000000001976a9147480a33f950689af511e6e84c138dbbd3c3ee41588ac00000000",
+        );
+        let witness_script = hex::decode(concat!(
+            "56210307b8ae49ac90a048e9b53357a2354b3334e9c8bee813ecb98e99a7e07e8c3ba32103b28f0c28",
+            "bfab54554ae8c658ac5c3e0ce6e79ad336331f78c428dd43eea8449b21034b8113d703413d57761b8b",
+            "9781957b8c0ac1dfe69f492580ca4195f50376ba4a21033400f6afecb833092a9a21cfdf1ed1376e58",
+            "c5d1f47de74683123987e967a8f42103a6d48b1131e94ba04d9737d61acdaa1322008af9602b3b1486",
+            "2c07a1789aac162102d8b661b0b3302ee2f162b09e07a55ad5dfbe673a9f01d9f0c19617681024306b",
+            "56ae"
+        ))
+        .unwrap();
+        let amount = Amount::from_sat(987_654_321);
+        let cache = SighashCache::new(&transaction);
+        for (hash_type, expected) in [
+            (
+                SIGHASH_ALL,
+                "185c0be5263dce5b4bb50a047973c1b6272bfbd0103a89444597dc40b248ee7c",
+            ),
+            (
+                SIGHASH_NONE,
+                "e9733bc60ea13c95c6527066bb975a2ff29a925e80aa14c213f686cbae5d2f36",
+            ),
+            (
+                SIGHASH_SINGLE,
+                "1e1f1c303dc025bd664acb72e583e933fae4cff9148bf78c157d1e8f78530aea",
+            ),
+            (
+                SIGHASH_ALL | SIGHASH_ANYONECANPAY,
+                "2a67f03e63a6a422125878b40b82da593be8d4efaafe88ee528af6e5a9955c6e",
+            ),
+            (
+                SIGHASH_NONE | SIGHASH_ANYONECANPAY,
+                "781ba15f3779d5542ce8ecb5c18716733a5ee42a6f51488ec96154934e2c890a",
+            ),
+            (
+                SIGHASH_SINGLE | SIGHASH_ANYONECANPAY,
+                "511e8e52ed574121fc1b654970395502128263f62662e076dc6baf05c2e6a99b",
+            ),
+        ] {
+            let sighash = cache.segwit_v0_sighash(0, &witness_script, amount, hash_type);
+            assert_eq!(
+                hex::encode(sighash.as_byte_array()),
+                expected,
+                "{:#x}",
+                hash_type
+            );
+        }
+    }
+
+    #[test]
+    fn test_bip341_wallet_vectors() {
+        let vectors: serde_json::Value =
+            serde_json::from_str(include_str!("../data/bip341_tests.json")).unwrap();
+        for vector in vectors["keyPathSpending"].as_array().unwrap() {
+            let given = &vector["given"];
+            let transaction = bip143_transaction(given["rawUnsignedTx"].as_str().unwrap());
+            let prevouts: Vec<TransactionOutput> = given["utxosSpent"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|utxo| TransactionOutput {
+                    value: Amount::from_sat(utxo["amountSats"].as_u64().unwrap()),
+                    script_pub_key: hex::decode(utxo["scriptPubKey"].as_str().unwrap()).unwrap(),
+                })
+                .collect();
+            let cache = SighashCache::new(&transaction);
+            for input in vector["inputSpending"].as_array().unwrap() {
+                let input_index = input["given"]["txinIndex"].as_u64().unwrap() as usize;
+                let hash_type = input["given"]["hashType"].as_u64().unwrap() as u32;
+                let mut sig_msg = Vec::new();
+                cache
+                    .encode_taproot_sighash(
+                        &mut sig_msg,
+                        input_index,
+                        &prevouts,
+                        None,
+                        None,
+                        hash_type,
+                    )
+                    .unwrap();
+                assert_eq!(
+                    hex::encode(sig_msg),
+                    input["intermediary"]["sigMsg"].as_str().unwrap()
+                );
+                let sighash = cache
+                    .taproot_sighash(input_index, &prevouts, None, None, hash_type)
+                    .unwrap();
+                assert_eq!(
+                    hex::encode(sighash.as_byte_array()),
+                    input["intermediary"]["sigHash"].as_str().unwrap(),
+                    "input {}",
+                    input_index
+                );
+            }
+            let intermediary = &vector["intermediary"];
+            let shared = cache.shared.get().unwrap();
+            let taproot = cache.taproot.get().unwrap();
+            for (hash, name) in [
+                (shared.prevouts, "hashPrevouts"),
+                (shared.sequences, "hashSequences"),
+                (shared.outputs, "hashOutputs"),
+                (taproot.amounts, "hashAmounts"),
+                (taproot.script_pub_keys, "hashScriptPubkeys"),
+            ] {
+                assert_eq!(
+                    hex::encode(hash),
+                    intermediary[name].as_str().unwrap(),
+                    "{}",
+                    name
+                );
+            }
+        }
+    }
+
+    /// Checks a case from Core's functional tests, `prevouts` being the
+    /// serialized list of spent outputs.
+    fn check_taproot_sighash(
+        transaction: &str,
+        prevouts: &str,
+        hash_type: u32,
+        annex: Option<&str>,
+        tapscript: Option<&str>,
+        expected: &str,
+    ) {
+        let transaction = bip143_transaction(transaction);
+        let prevouts = hex::decode(prevouts).unwrap();
+        let mut decoder = Decoder::from_slice(&prevouts);
+        let count = CompactSize::consensus_decode(&mut decoder).unwrap().0;
+        let prevouts: Vec<TransactionOutput> = (0..count)
+            .map(|_| TransactionOutput::consensus_decode(&mut decoder).unwrap())
+            .collect();
+        let annex = annex.map(|annex| hex::decode(annex).unwrap());
+        let tapscript =
+            tapscript.map(|script| TapscriptExtension::new(&hex::decode(script).unwrap()));
+        let sighash = SighashCache::new(&transaction)
+            .taproot_sighash(0, &prevouts, annex.as_deref(), tapscript, hash_type)
+            .unwrap();
+        assert_eq!(hex::encode(sighash.as_byte_array()), expected);
+    }
+
+    #[test]
+    fn test_taproot_sighash_types() {
+        check_taproot_sighash(
+            "020000000164eb050a5e3da0c2a65e4786f26d753b7bc69691fabccafb11f7acef36641f1846010000003101b2b404392a22000000000017a9147f2bde86fe78bf68a0544a4f290e12f0b7e0a08c87580200000000000017a91425d11723074ecfb96a0a83c3956bfaf362ae0c908758020000000000001600147e20f938993641de67bb0cdd71682aa34c4d29ad5802000000000000160014c64984dc8761acfa99418bd6bedc79b9287d652d72000000",
+            "01365724000000000023542156b39dab4f8f3508e0432cfb41fab110170acaa2d4c42539cb90a4dc7c093bc500",
+            SIGHASH_DEFAULT,
+            None,
+            None,
+            "33ca0ebfb4a945eeee9569fc0f5040221275f88690b7f8592ada88ce3bdf6703",
+        );
+        check_taproot_sighash(
+            "eb93dbb901028c8515589dac980b6e7f8e4088b77ed866ca0d6d210a7218b6fd0f6b22dd6d7300000000eb4740a9047efc0e0000000000160014913da2128d8fcf292b3691db0e187414aa1783825802000000000000160014913da2128d8fcf292b3691db0e187414aa178382580200000000000017a9143dd27f01c6f7ef9bb9159937b17f17065ed01a0c875802000000000000160014d7630e19df70ada9905ede1722b800c0005f246641000000",
+            "013fed110000000000225120eb536ae8c33580290630fc495046e998086a64f8f33b93b07967d9029b265c55",
+            SIGHASH_NONE | SIGHASH_ANYONECANPAY,
+            None,
+            None,
+            "2441e8b0e063a2083ee790f14f2045022f07258ddde5ee01de543c9e789d80ae",
+        );
+        check_taproot_sighash(
+            "0100000001aa6deae89d5e0aaca58714fc76ef6f3c8284224888089232d4e663843ed3ab3eae010000008b6657a60450cb4c0000000000160014a3d42b5413ef0c0701c4702f3cd7d4df222c147058020000000000001976a91430b4ed8723a4ee8992aa2c8814cfe5c3ad0ab9d988ac5802000000000000160014365b1166a6ed0a5e8e9dff17a6d00bbb43454bc758020000000000001976a914bc98c51a84fe7fad5dc380eb8b39586eff47241688ac4f313247",
+            "0107af4e00000000002251202c36d243dfc06cb56a248e62df27ecba7417307511a81ae61aa41c597a929c69",
+            SIGHASH_SINGLE | SIGHASH_ANYONECANPAY,
+            None,
+            None,
+            "bf9c83f26c6dd16449e4921f813f551c4218e86f2ec906ca8611175b41b566df",
+        );
+    }
+
+    #[test]
+    fn test_taproot_sighash_annex_and_script_path() {
+        check_taproot_sighash(
+            "0200000001df8123752e8f37d132c4e9f1ff7e4f9b986ade9211267e9ebd5fd22a5e718dec6d01000000ce4023b903cb7b23000000000017a914a18b36ea7a094db2f4940fc09edf154e86de7bd787580200000000000017a914afd0d512a2c5c2b40e25669e9cc460303c325b8b87580200000000000017a914a18b36ea7a094db2f4940fc09edf154e86de7bd787f6020000",
+            "01ea49260000000000225120ab5e9800806bf18cb246edcf5fe63441208fe955a4b5a35bbff65f5db622a010",
+            SIGHASH_SINGLE | SIGHASH_ANYONECANPAY,
+            Some("507b979802e62d397acb29f56743a791894b99372872fc5af06a4f6e8d242d0615cda53062bb20e6ec79756fe39183f0c128adfe85559a8fa042b042c018aa8010143799e44f0893c40e1e"),
+            None,
+            "3b003000add359a364a156e73e02846782a59d0d95ca8c4638aaad99f2ef915c",
+        );
+        check_taproot_sighash(
+            "020000000189fc651483f9296b906455dd939813bf086b1bbe7c77635e157c8e14ae29062195010000004445b5c7044561320000000000160014331414dbdada7fb578f700f38fb69995fc9b5ab958020000000000001976a914268db0a8104cc6d8afd91233cc8b3d1ace8ac3ef88ac580200000000000017a914ec00dcb368d6a693e11986d265f659d2f59e8be2875802000000000000160014c715799a49a0bae3956df9c17cb4440a673ac0df6f010000",
+            "011bec34000000000022512028055142ea437db73382e991861446040b61dd2185c4891d7daf6893d79f7182",
+            SIGHASH_ALL,
+            None,
+            Some("20cc4e1107aea1d170c5ff5b6817e1303010049724fb3caa7941792ea9d29b3e2bacab"),
+            "d66de5274a60400c7b08c86ba6b7f198f40660079edf53aca89d2a9501317f2e",
+        );
+        assert_eq!(
+            hex::encode(tap_leaf_hash(
+                TAPROOT_LEAF_TAPSCRIPT,
+                &hex::decode(
+                    "20cc4e1107aea1d170c5ff5b6817e1303010049724fb3caa7941792ea9d29b3e2bacab"
+                )
+                .unwrap()
+            )),
+            "15a2530514e399f8b5cf0b3d3112cf5b289eaa3e308ba2071b58392fdc6da68a"
+        );
+        check_taproot_sighash(
+            "020000000132fb72cb8fba496755f027a9743e2d698c831fdb8304e4d1a346ac92cbf51acba50100000026bdc7df044aad34000000000017a9144fa2554ed6174586854fa3bc01de58dcf33567d0875802000000000000160014950367e1e62cdf240b35b883fc2f5e39f0eb9ab95802000000000000160014950367e1e62cdf240b35b883fc2f5e39f0eb9ab958020000000000001600141b31217d48ccc8760dcc0710fade5866d628e733a02d5122",
+            "011458360000000000225120a7baec3fb9f84614e3899fcc010c638f80f13539344120e1f4d8b68a9a011a13",
+            SIGHASH_ALL,
+            Some("50a6272b470e1460e3332ade7bb14b81671c564fb6245761bd5bd531394b28860e0b3808ab229fb51791fb6ae6fa82d915b2efb8f6df83ae1f5ab3db13e30928875e2a22b749d89358de481f19286cd4caa792ce27f9559082d227a731c5486882cc707f83da361c51b7aadd9a0cf68fe7480c410fa137b454482d9a1ebf0f96d760b4d61426fc109c6e8e99a508372c45caa7b000a41f8251305da3f206c1849985ba03f3d9592832b4053afbd23ab25d0465df0bc25a36c223aacf8e04ec736a418c72dc319e4da3e972e349713ca600965e7c665f2090d5a70e241ac164115a1f5639f28b1773327715ca307ace64a2de7f0e3df70a2ffee3857689f909c0dad46d8a20fa373a4cc6eed6d4c9806bf146f0d76baae1"),
+            Some("7520ab9160dd8299dc1367659be3e8f66781fe440d52940c7f8d314a89b9f2698d406ead6ead6ead6ead6ead6ead6ead6ead6ead6ead6ead6ead6ead6ead6ead6ead6ead6ead6ead6ead6ead6ead6ead6ead6ead6ead6ead6ead6ead6ead6ead6ead6ead6ead6ead6ead6ead6ead6ead6ead6ead6ead6ead6ead6ead6ead6ead6ead6ead6ead6ead6ead6ead6ead6ead6ead6ead6ead6ead6eadac"),
+            "a0042aa434f9a75904b64043f2a283f8b4c143c7f4f7f49a6cbe5b9f745f4c15",
+        );
+    }
+
+    #[test]
+    fn test_taproot_sighash_errors() {
+        let transaction = bip143_transaction(crate::TRANSACTION_SERIALIZED);
+        let cache = SighashCache::new(&transaction);
+        let prevout = TransactionOutput {
+            value: Amount::from_sat(5_000_000_000),
+            script_pub_key: Vec::new(),
+        };
+        let sighash = |index, prevouts: &[TransactionOutput], annex, hash_type| match cache
+            .taproot_sighash(index, prevouts, annex, None, hash_type)
+        {
+            Err(BitcoinError::InvalidSighash(error)) => Some(error),
+            Err(error) => panic!("{}", error),
+            Ok(_) => None,
+        };
+        let prevouts = [prevout];
+        assert_eq!(
+            sighash(1, &prevouts, None, SIGHASH_ALL),
+            Some(SighashError::InputIndex {
+                index: 1,
+                inputs: 1
+            })
+        );
+        assert_eq!(
+            sighash(0, &[], None, SIGHASH_ALL),
+            Some(SighashError::MissingPrevout(0))
+        );
+        assert_eq!(
+            sighash(0, &prevouts, None, 0x04),
+            Some(SighashError::InvalidHashType(0x04))
+        );
+        assert_eq!(
+            sighash(0, &prevouts, None, 0x80),
+            Some(SighashError::InvalidHashType(0x80))
+        );
+        assert_eq!(
+            sighash(0, &prevouts, Some(&[0x51]), SIGHASH_ALL),
+            Some(SighashError::InvalidAnnex)
+        );
+        assert_eq!(sighash(0, &prevouts, Some(&[0x50]), SIGHASH_ALL), None);
+        // Block 170 pays two outputs
+        assert_eq!(sighash(0, &prevouts, None, SIGHASH_SINGLE), None);
+
+        let mut input = transaction.inputs[0].clone();
+        assert_eq!(input.taproot_annex(), None);
+        input.witness = vec![vec![0x50]];
+        assert_eq!(input.taproot_annex(), None);
+        input.witness.push(vec![0x50, 1]);
+        assert_eq!(input.taproot_annex(), Some(&[0x50, 1][..]));
+    }
+}
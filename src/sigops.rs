@@ -0,0 +1,386 @@
+use crate::{
+    opcodes::{
+        OP_1, OP_16, OP_CHECKMULTISIG, OP_CHECKMULTISIGVERIFY, OP_CHECKSIG, OP_CHECKSIGVERIFY,
+    },
+    BitcoinError, Block, Instruction, PrevoutProvider, Result, Script, ScriptType, Transaction,
+    VerifyFlags, MAX_BLOCK_SIGOPS_COST, MAX_PUBKEYS_PER_MULTISIG, WITNESS_SCALE_FACTOR,
+};
+
+impl Script<'_> {
+    /// Core's `GetSigOpCount`. Legacy counting charges every
+    /// `OP_CHECKMULTISIG` the maximum of 20 keys, `accurate` uses the key
+    /// count when an `OP_1` to `OP_16` precedes it. Counting stops at a
+    /// malformed push.
+    pub fn sigop_count(&self, accurate: bool) -> usize {
+        let mut count = 0;
+        let mut last = None;
+        for instruction in self.instructions() {
+            let Ok(instruction) = instruction else {
+                break;
+            };
+            match instruction {
+                Instruction::Op(OP_CHECKSIG | OP_CHECKSIGVERIFY) => count += 1,
+                Instruction::Op(OP_CHECKMULTISIG | OP_CHECKMULTISIGVERIFY) => {
+                    count += match last {
+                        Some(Instruction::Op(keys @ OP_1..=OP_16)) if accurate => {
+                            (keys - OP_1 + 1) as usize
+                        }
+                        _ => MAX_PUBKEYS_PER_MULTISIG,
+                    }
+                }
+                _ => {}
+            }
+            last = Some(instruction);
+        }
+        count
+    }
+
+    /// Sigops of the redeem script when this is a P2SH output spent by
+    /// `script_sig`, zero if `script_sig` is not push only. Other scripts are
+    /// counted accurately.
+    pub fn p2sh_sigop_count(&self, script_sig: &[u8]) -> usize {
+        if !matches!(self.script_type(), ScriptType::ScriptHash(_)) {
+            return self.sigop_count(true);
+        }
+        match last_push(script_sig) {
+            Some(redeem_script) => Script::new(redeem_script).sigop_count(true),
+            None => 0,
+        }
+    }
+}
+
+/// Data of the last push of a push only script, empty if that is a small
+/// number opcode.
+fn last_push(script: &[u8]) -> Option<&[u8]> {
+    let mut data: &[u8] = &[];
+    for instruction in Script::new(script).instructions() {
+        data = match instruction.ok()? {
+            Instruction::PushBytes(data) => data,
+            Instruction::Op(opcode) if opcode <= OP_16 => &[],
+            Instruction::Op(_) => return None,
+        };
+    }
+    Some(data)
+}
+
+/// Core's `CountWitnessSigOps`, for native and P2SH wrapped witness programs.
+fn witness_sigop_count(
+    script_sig: &[u8],
+    script_pub_key: &[u8],
+    witness: &[Vec<u8>],
+    flags: VerifyFlags,
+) -> usize {
+    if !flags.contains(VerifyFlags::WITNESS) {
+        return 0;
+    }
+    let script_pub_key = Script::new(script_pub_key);
+    let program = match script_pub_key.script_type() {
+        ScriptType::ScriptHash(_) => last_push(script_sig)
+            .and_then(|redeem_script| Script::new(redeem_script).witness_program()),
+        _ => script_pub_key.witness_program(),
+    };
+    match program {
+        Some((0, program)) if program.len() == 20 => 1,
+        Some((0, program)) if program.len() == 32 => witness.last().map_or(0, |witness_script| {
+            Script::new(witness_script).sigop_count(true)
+        }),
+        _ => 0,
+    }
+}
+
+impl Transaction {
+    /// Sigops of all scriptSigs and output scripts, counted the legacy way.
+    pub fn legacy_sigop_count(&self) -> usize {
+        let inputs = self.inputs.iter().map(|input| &input.script_sig);
+        let outputs = self.outputs.iter().map(|output| &output.script_pub_key);
+        inputs
+            .chain(outputs)
+            .map(|script| Script::new(script).sigop_count(false))
+            .sum()
+    }
+
+    /// Sigops of the redeem scripts of the P2SH outputs spent, zero for a
+    /// coinbase.
+    pub fn p2sh_sigop_count<P: PrevoutProvider + ?Sized>(&self, prevouts: &P) -> Result<usize> {
+        if self.is_coinbase() {
+            return Ok(0);
+        }
+        let mut count = 0;
+        for (index, input) in self.inputs.iter().enumerate() {
+            let prevout = prevouts
+                .prevout(index)
+                .ok_or(BitcoinError::MissingPrevout(index))?;
+            let script_pub_key = Script::new(&prevout.script_pub_key);
+            if matches!(script_pub_key.script_type(), ScriptType::ScriptHash(_)) {
+                count += script_pub_key.p2sh_sigop_count(&input.script_sig);
+            }
+        }
+        Ok(count)
+    }
+
+    /// Core's `GetTransactionSigOpCost`: legacy and P2SH sigops weigh
+    /// [`WITNESS_SCALE_FACTOR`] each, witness sigops one. P2SH and witness
+    /// sigops are only counted with their flag set.
+    pub fn sigop_cost<P: PrevoutProvider + ?Sized>(
+        &self,
+        prevouts: &P,
+        flags: VerifyFlags,
+    ) -> Result<usize> {
+        let mut cost = self.legacy_sigop_count() * WITNESS_SCALE_FACTOR;
+        if self.is_coinbase() {
+            return Ok(cost);
+        }
+        if flags.contains(VerifyFlags::P2SH) {
+            cost += self.p2sh_sigop_count(prevouts)? * WITNESS_SCALE_FACTOR;
+        }
+        for (index, input) in self.inputs.iter().enumerate() {
+            let prevout = prevouts
+                .prevout(index)
+                .ok_or(BitcoinError::MissingPrevout(index))?;
+            cost += witness_sigop_count(
+                &input.script_sig,
+                &prevout.script_pub_key,
+                &input.witness,
+                flags,
+            );
+        }
+        Ok(cost)
+    }
+}
+
+impl Block {
+    pub fn legacy_sigop_count(&self) -> usize {
+        self.transactions
+            .iter()
+            .map(Transaction::legacy_sigop_count)
+            .sum()
+    }
+
+    /// Sigop cost of all transactions. `prevouts` has the spent outputs of
+    /// each transaction, the entry of the coinbase is not used.
+    pub fn sigop_cost<P: PrevoutProvider>(
+        &self,
+        prevouts: &[P],
+        flags: VerifyFlags,
+    ) -> Result<usize> {
+        let mut cost = 0;
+        for (i, transaction) in self.transactions.iter().enumerate() {
+            let prevouts = prevouts.get(i).ok_or_else(|| {
+                BitcoinError::InvalidBlock(format!("transaction {}: no spent outputs given", i))
+            })?;
+            cost += transaction
+                .sigop_cost(prevouts, flags)
+                .map_err(|e| BitcoinError::InvalidBlock(format!("transaction {}: {}", i, e)))?;
+        }
+        Ok(cost)
+    }
+
+    /// Checks the sigop cost against [`MAX_BLOCK_SIGOPS_COST`].
+    pub fn check_sigop_cost<P: PrevoutProvider>(
+        &self,
+        prevouts: &[P],
+        flags: VerifyFlags,
+    ) -> Result<()> {
+        let cost = self.sigop_cost(prevouts, flags)?;
+        if cost > MAX_BLOCK_SIGOPS_COST {
+            return Err(BitcoinError::InvalidBlock(format!(
+                "Block sigop cost {} exceeds {}",
+                cost, MAX_BLOCK_SIGOPS_COST
+            )));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        Amount, BlockHeader, OutPoint, ScriptBuilder, TransactionInput, TransactionOutput, Txid,
+    };
+
+    fn script(asm: &str) -> Vec<u8> {
+        Script::from_asm(asm).unwrap()
+    }
+
+    fn spend(script_sig: Vec<u8>, witness: Vec<Vec<u8>>) -> Transaction {
+        Transaction {
+            version: 2,
+            inputs: vec![TransactionInput {
+                previous_output: OutPoint {
+                    hash: Txid::all_zeros(),
+                    index: 1,
+                },
+                script_sig,
+                sequence: u32::MAX,
+                witness,
+            }],
+            outputs: vec![TransactionOutput {
+                value: Amount::ZERO,
+                script_pub_key: script("OP_DUP OP_HASH160 0x14 0x0000000000000000000000000000000000000000 OP_EQUALVERIFY OP_CHECKSIG"),
+            }],
+            locktime: 0,
+        }
+    }
+
+    fn output(script_pub_key: Vec<u8>) -> Vec<TransactionOutput> {
+        vec![TransactionOutput {
+            value: Amount::ZERO,
+            script_pub_key,
+        }]
+    }
+
+    #[test]
+    fn test_script_sigop_count() {
+        let key = "0x21 0x020000000000000000000000000000000000000000000000000000000000000001";
+        let multisig = script(&format!("2 {} {} {} 3 OP_CHECKMULTISIG", key, key, key));
+        assert_eq!(Script::new(&multisig).sigop_count(true), 3);
+        assert_eq!(Script::new(&multisig).sigop_count(false), 20);
+        let checks = script("OP_CHECKSIG OP_CHECKSIGVERIFY OP_CHECKMULTISIGVERIFY");
+        assert_eq!(Script::new(&checks).sigop_count(true), 22);
+        // A key count that is a push rather than OP_N is not used
+        let pushed_count = script("0x01 0x03 OP_CHECKMULTISIG");
+        assert_eq!(Script::new(&pushed_count).sigop_count(true), 20);
+        // Counting stops at the truncated push
+        assert_eq!(
+            Script::new(&[OP_CHECKSIG, 0x02, OP_CHECKSIG]).sigop_count(false),
+            1
+        );
+
+        let redeem_script = multisig;
+        let p2sh = script("OP_HASH160 0x14 0x0000000000000000000000000000000000000000 OP_EQUAL");
+        let script_sig = ScriptBuilder::new()
+            .push_int(0)
+            .push_slice(&redeem_script)
+            .into_bytes();
+        assert_eq!(Script::new(&p2sh).p2sh_sigop_count(&script_sig), 3);
+        let mut not_push_only = script_sig.clone();
+        not_push_only.push(OP_CHECKSIG);
+        assert_eq!(Script::new(&p2sh).p2sh_sigop_count(&not_push_only), 0);
+        assert_eq!(Script::new(&redeem_script).p2sh_sigop_count(&script_sig), 3);
+    }
+
+    #[test]
+    fn test_transaction_sigop_cost() {
+        let key = "0x21 0x020000000000000000000000000000000000000000000000000000000000000001";
+        let witness_script = script(&format!("1 {} {} 2 OP_CHECKMULTISIG", key, key));
+        let p2wsh =
+            script("0 0x20 0x0000000000000000000000000000000000000000000000000000000000000000");
+        let p2wpkh = script("0 0x14 0x0000000000000000000000000000000000000000");
+        let p2sh = script("OP_HASH160 0x14 0x0000000000000000000000000000000000000000 OP_EQUAL");
+        let all = VerifyFlags::P2SH | VerifyFlags::WITNESS;
+
+        // The P2PKH output costs 4 in every case
+        let transaction = spend(Vec::new(), vec![Vec::new(), witness_script.clone()]);
+        assert_eq!(transaction.legacy_sigop_count(), 1);
+        assert_eq!(
+            transaction.sigop_cost(&output(p2wsh.clone()), all).unwrap(),
+            4 + 2
+        );
+        assert_eq!(
+            transaction
+                .sigop_cost(&output(p2wsh), VerifyFlags::P2SH)
+                .unwrap(),
+            4
+        );
+        assert_eq!(
+            transaction
+                .sigop_cost(&output(p2wpkh.clone()), all)
+                .unwrap(),
+            4 + 1
+        );
+
+        // P2SH wrapped P2WPKH, and a P2SH multisig
+        let nested = spend(
+            ScriptBuilder::new().push_slice(&p2wpkh).into_bytes(),
+            vec![Vec::new()],
+        );
+        assert_eq!(
+            nested.sigop_cost(&output(p2sh.clone()), all).unwrap(),
+            4 + 1
+        );
+        let multisig = spend(
+            ScriptBuilder::new()
+                .push_int(0)
+                .push_slice(&witness_script)
+                .into_bytes(),
+            Vec::new(),
+        );
+        assert_eq!(multisig.p2sh_sigop_count(&output(p2sh.clone())).unwrap(), 2);
+        assert_eq!(
+            multisig.sigop_cost(&output(p2sh.clone()), all).unwrap(),
+            4 + 8
+        );
+        assert_eq!(
+            multisig
+                .sigop_cost(&output(p2sh), VerifyFlags::NONE)
+                .unwrap(),
+            4
+        );
+
+        assert!(matches!(
+            multisig.sigop_cost(&Vec::new(), all),
+            Err(BitcoinError::MissingPrevout(0))
+        ));
+        // A coinbase spends nothing
+        let coinbase = Transaction::coinbase(1, 0, b"", output(script("OP_CHECKSIG"))).unwrap();
+        assert_eq!(coinbase.sigop_cost(&Vec::new(), all).unwrap(), 4);
+    }
+
+    #[test]
+    fn test_block_sigop_limit() {
+        let transaction =
+            Transaction::deserialize(&hex::decode(crate::TRANSACTION_SERIALIZED).unwrap()).unwrap();
+        let coinbase =
+            Transaction::deserialize(&hex::decode(crate::BLOCK_9_COINBASE_SERIALIZED).unwrap())
+                .unwrap();
+        let mut block = Block {
+            block_header: BlockHeader {
+                version: 1,
+                previous_block_hash: Default::default(),
+                merkle_root_hash: Default::default(),
+                timestamp: 0,
+                bits: 0,
+                nonce: 0,
+            },
+            transactions: vec![coinbase.clone(), transaction],
+        };
+        block.block_header.merkle_root_hash = block.merkle_root();
+        // Every P2PK output counts one
+        assert_eq!(block.legacy_sigop_count(), 3);
+        let prevouts = vec![Vec::new(), coinbase.outputs.clone()];
+        assert_eq!(
+            block.sigop_cost(&prevouts, VerifyFlags::MANDATORY).unwrap(),
+            12
+        );
+        block
+            .check_sigop_cost(&prevouts, VerifyFlags::MANDATORY)
+            .unwrap();
+        assert!(block
+            .sigop_cost(&prevouts[..1], VerifyFlags::MANDATORY)
+            .is_err());
+        block.validate().unwrap();
+
+        // 1000 legacy multisigs cost 80,000, one more sigop is too many
+        block.transactions[0].outputs[0].script_pub_key = vec![OP_CHECKMULTISIG; 1000];
+        block.block_header.merkle_root_hash = block.merkle_root();
+        assert_eq!(
+            block.sigop_cost(&prevouts, VerifyFlags::MANDATORY).unwrap(),
+            80_008
+        );
+        assert!(block
+            .check_sigop_cost(&prevouts, VerifyFlags::MANDATORY)
+            .is_err());
+        assert!(matches!(
+            block.validate(),
+            Err(BitcoinError::InvalidBlock(_))
+        ));
+        block.transactions[1].outputs.truncate(0);
+        block.block_header.merkle_root_hash = block.merkle_root();
+        assert_eq!(
+            block.sigop_cost(&prevouts, VerifyFlags::MANDATORY).unwrap(),
+            MAX_BLOCK_SIGOPS_COST
+        );
+        block.validate().unwrap();
+    }
+}
@@ -1,11 +1,28 @@
+use std::io::{self, Read, Write};
+
 use sha2::{Digest, Sha256};
 
 use crate::{
-    utils::{decode_varint, encode_varint},
-    BitcoinError, Result,
+    decoder::Decoder,
+    encode::{encode_var_bytes, CompactSize, Decodable, Encodable},
+    opcodes::{OP_1, OP_16},
+    script::{decode_script_num, Instruction, Script, ScriptBuilder},
+    utils::varint_size,
+    Amount, BitcoinError, Result, Txid, Wtxid, WITNESS_SCALE_FACTOR,
 };
 
+/// Outpoint, empty scriptSig and sequence.
+pub(crate) const MIN_INPUT_SIZE: usize = 41;
+/// Value and empty scriptPubKey.
+pub(crate) const MIN_OUTPUT_SIZE: usize = 9;
+/// Version, two empty counts and locktime.
+pub(crate) const MIN_TRANSACTION_SIZE: usize = 10;
+/// Coinbase scriptSig size bounds enforced by `CheckTransaction`.
+const MIN_COINBASE_SCRIPT_SIG_SIZE: usize = 2;
+const MAX_COINBASE_SCRIPT_SIG_SIZE: usize = 100;
+
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Transaction {
     pub version: u32,
     pub inputs: Vec<TransactionInput>,
@@ -15,154 +32,395 @@ pub struct Transaction {
 
 impl Transaction {
     pub fn deserialize(payload: &[u8]) -> Result<Self> {
-        if payload.len() < 4 {
-            return Err(BitcoinError::InvalidPayload(
-                "Insufficient bytes for transaction".to_string(),
-            ));
+        Self::consensus_decode(&mut Decoder::from_slice(payload))
+    }
+
+    /// Builds a version 1 coinbase whose scriptSig pushes the BIP34 `height`,
+    /// then `extra_nonce`, then `tag` unless it is empty. Fails if the
+    /// scriptSig ends up outside the 2 to 100 bytes consensus allows.
+    pub fn coinbase(
+        height: u32,
+        extra_nonce: u32,
+        tag: &[u8],
+        outputs: Vec<TransactionOutput>,
+    ) -> Result<Self> {
+        let mut script_sig = ScriptBuilder::new()
+            .push_int(height as i64)
+            .push_int(extra_nonce as i64);
+        if !tag.is_empty() {
+            script_sig = script_sig.push_slice(tag);
         }
-        let version = u32::from_le_bytes(payload[0..4].try_into().unwrap());
-        let (num_inputs, offset) = decode_varint(&payload[4..])?;
-        let mut offset = offset + 4;
-        let mut inputs = Vec::with_capacity(num_inputs as usize);
-        for _ in 0..num_inputs {
-            let input = TransactionInput::deserialize(&payload[offset..])?;
-            offset += input.size();
-            inputs.push(input);
+        let script_sig = script_sig.into_bytes();
+        if !(MIN_COINBASE_SCRIPT_SIG_SIZE..=MAX_COINBASE_SCRIPT_SIG_SIZE)
+            .contains(&script_sig.len())
+        {
+            return Err(BitcoinError::InvalidScript(format!(
+                "coinbase scriptSig is {} bytes, must be between {} and {}",
+                script_sig.len(),
+                MIN_COINBASE_SCRIPT_SIG_SIZE,
+                MAX_COINBASE_SCRIPT_SIG_SIZE
+            )));
         }
-        let (num_outputs, offset_outputs) = decode_varint(&payload[offset..])?;
-        offset += offset_outputs;
-        let mut outputs = Vec::with_capacity(num_outputs as usize);
-        for _ in 0..num_outputs {
-            let output = TransactionOutput::deserialize(&payload[offset..])?;
-            offset += output.size();
-            outputs.push(output);
-        }
-        let locktime = u32::from_le_bytes(payload[offset..offset + 4].try_into().unwrap());
         Ok(Self {
-            version,
-            inputs,
+            version: 1,
+            inputs: vec![TransactionInput {
+                previous_output: OutPoint {
+                    hash: Txid::all_zeros(),
+                    index: u32::MAX,
+                },
+                script_sig,
+                sequence: u32::MAX,
+                witness: Vec::new(),
+            }],
             outputs,
-            locktime,
+            locktime: 0,
         })
     }
 
-    pub fn txid(&self) -> [u8; 32] {
-        let txid: [u8; 32] = Sha256::digest(Sha256::digest(&self.serialize())).into();
-        txid
+    pub fn is_coinbase(&self) -> bool {
+        matches!(self.inputs.as_slice(), [input] if input.previous_output.hash == Txid::all_zeros()
+            && input.previous_output.index == u32::MAX)
+    }
+
+    /// Height committed to by a BIP34 coinbase. The scriptSig has to start with
+    /// exactly the push `CScript() << height` produces, as Core checks it.
+    pub fn bip34_height(&self) -> Option<u32> {
+        if !self.is_coinbase() {
+            return None;
+        }
+        let script_sig = &self.inputs[0].script_sig;
+        let height = match Script::new(script_sig).instructions().next()?.ok()? {
+            Instruction::Op(opcode @ OP_1..=OP_16) => (opcode - OP_1 + 1) as i64,
+            Instruction::PushBytes(data) if data.len() <= 5 => decode_script_num(data),
+            _ => return None,
+        };
+        let height = u32::try_from(height).ok()?;
+        let expected = ScriptBuilder::new().push_int(height as i64).into_bytes();
+        script_sig.starts_with(&expected).then_some(height)
+    }
+
+    pub fn txid(&self) -> Txid {
+        let mut hasher = Sha256::new();
+        self.encode_with_witness(&mut hasher, false)
+            .expect("writing to a hasher never fails");
+        Txid::from_byte_array(Sha256::digest(hasher.finalize()).into())
+    }
+
+    pub fn wtxid(&self) -> Wtxid {
+        let mut hasher = Sha256::new();
+        self.consensus_encode(&mut hasher)
+            .expect("writing to a hasher never fails");
+        Wtxid::from_byte_array(Sha256::digest(hasher.finalize()).into())
+    }
+
+    pub fn has_witness(&self) -> bool {
+        self.inputs.iter().any(|input| !input.witness.is_empty())
     }
 
     pub fn serialize(&self) -> Vec<u8> {
-        let mut payload = Vec::new();
-        payload.extend_from_slice(&self.version.to_le_bytes());
-        payload.extend_from_slice(&encode_varint(self.inputs.len() as u64));
+        crate::encode::serialize(self)
+    }
+
+    pub fn serialize_without_witness(&self) -> Vec<u8> {
+        let mut payload = Vec::with_capacity(self.stripped_size());
+        self.encode_with_witness(&mut payload, false)
+            .expect("writing to a Vec never fails");
+        payload
+    }
+
+    fn encode_with_witness<W: Write + ?Sized>(
+        &self,
+        writer: &mut W,
+        include_witness: bool,
+    ) -> io::Result<usize> {
+        let mut len = 0;
+        writer.write_all(&self.version.to_le_bytes())?;
+        len += 4;
+        if include_witness {
+            // BIP144 marker and flag
+            writer.write_all(&[0x00, 0x01])?;
+            len += 2;
+        }
+        len += CompactSize(self.inputs.len() as u64).consensus_encode(writer)?;
         for input in &self.inputs {
-            payload.extend_from_slice(&input.previous_output.hash);
-            payload.extend_from_slice(&input.previous_output.index.to_le_bytes());
-            payload.extend_from_slice(&encode_varint(input.script_sig.len() as u64));
-            payload.extend_from_slice(&input.script_sig);
-            payload.extend_from_slice(&input.sequence.to_le_bytes());
+            len += input.consensus_encode(writer)?;
         }
-        payload.extend_from_slice(&encode_varint(self.outputs.len() as u64));
+        len += CompactSize(self.outputs.len() as u64).consensus_encode(writer)?;
         for output in &self.outputs {
-            payload.extend_from_slice(&output.value.to_le_bytes());
-            payload.extend_from_slice(&encode_varint(output.script_pub_key.len() as u64));
-            payload.extend_from_slice(&output.script_pub_key);
+            len += output.consensus_encode(writer)?;
         }
-        payload.extend_from_slice(&self.locktime.to_le_bytes());
-        payload
+        if include_witness {
+            for input in &self.inputs {
+                len += CompactSize(input.witness.len() as u64).consensus_encode(writer)?;
+                for item in &input.witness {
+                    len += encode_var_bytes(item, writer)?;
+                }
+            }
+        }
+        writer.write_all(&self.locktime.to_le_bytes())?;
+        Ok(len + 4)
     }
 
     pub fn size(&self) -> usize {
-        self.serialize().len()
+        self.total_size()
+    }
+
+    /// Size of the legacy serialization, without marker, flag and witnesses.
+    pub fn stripped_size(&self) -> usize {
+        4 + varint_size(self.inputs.len() as u64)
+            + self.inputs.iter().map(|input| input.size()).sum::<usize>()
+            + varint_size(self.outputs.len() as u64)
+            + self
+                .outputs
+                .iter()
+                .map(|output| output.size())
+                .sum::<usize>()
+            + 4
+    }
+
+    pub fn total_size(&self) -> usize {
+        if !self.has_witness() {
+            return self.stripped_size();
+        }
+        // Marker and flag plus one witness stack per input
+        self.stripped_size()
+            + 2
+            + self
+                .inputs
+                .iter()
+                .map(|input| input.witness_size())
+                .sum::<usize>()
+    }
+
+    pub fn weight(&self) -> usize {
+        self.stripped_size() * (WITNESS_SCALE_FACTOR - 1) + self.total_size()
+    }
+
+    pub fn vsize(&self) -> usize {
+        self.weight().div_ceil(WITNESS_SCALE_FACTOR)
+    }
+
+    /// Sum of the output values, checked the way Core's `CheckTransaction` does:
+    /// every value and every partial sum must be within `MAX_MONEY`.
+    pub fn total_output_value(&self) -> Result<Amount> {
+        let mut total = Amount::ZERO;
+        for (n, output) in self.outputs.iter().enumerate() {
+            if !output.value.is_money_range() {
+                return Err(BitcoinError::InvalidAmount(format!(
+                    "output {} value {} out of range",
+                    n, output.value
+                )));
+            }
+            total = total
+                .checked_add(output.value)
+                .filter(|total| total.is_money_range())
+                .ok_or_else(|| {
+                    BitcoinError::InvalidAmount("total output value out of range".to_string())
+                })?;
+        }
+        Ok(total)
     }
 }
+
+impl Encodable for Transaction {
+    fn consensus_encode<W: Write + ?Sized>(&self, writer: &mut W) -> io::Result<usize> {
+        self.encode_with_witness(writer, self.has_witness())
+    }
+
+    fn encoded_size(&self) -> usize {
+        self.total_size()
+    }
+}
+
+impl Decodable for Transaction {
+    fn consensus_decode<R: Read>(decoder: &mut Decoder<R>) -> Result<Self> {
+        Self::decode_with_witness(decoder, true)
+    }
+}
+
+impl Transaction {
+    /// Without `allow_witness` a zero input count is taken literally instead of
+    /// as the BIP144 marker, like Core's `TX_NO_WITNESS`.
+    pub(crate) fn decode_with_witness<R: Read>(
+        decoder: &mut Decoder<R>,
+        allow_witness: bool,
+    ) -> Result<Self> {
+        let version = decoder.read_u32("transaction version")?;
+        let mut num_inputs = decoder.read_count("input count", MIN_INPUT_SIZE)?;
+        // BIP144: a zero input count followed by a non-zero flag means this is the
+        // extended format and witness data follows the outputs
+        let mut has_witness = false;
+        let mut num_outputs = None;
+        if num_inputs == 0 && allow_witness {
+            match decoder.read_u8("segwit flag")? {
+                0x00 => num_outputs = Some(0),
+                0x01 => {
+                    has_witness = true;
+                    num_inputs = decoder.read_count("input count", MIN_INPUT_SIZE)?;
+                }
+                _ => return Err(decoder.invalid("segwit flag", "unknown serialization flag")),
+            }
+        }
+        let mut inputs = Vec::new();
+        for _ in 0..num_inputs {
+            inputs.push(TransactionInput::consensus_decode(decoder)?);
+        }
+        let num_outputs = match num_outputs {
+            Some(num_outputs) => num_outputs,
+            None => decoder.read_count("output count", MIN_OUTPUT_SIZE)?,
+        };
+        let mut outputs = Vec::new();
+        for _ in 0..num_outputs {
+            outputs.push(TransactionOutput::consensus_decode(decoder)?);
+        }
+        if has_witness {
+            for input in inputs.iter_mut() {
+                let num_items = decoder.read_count("witness item count", 1)?;
+                for _ in 0..num_items {
+                    input.witness.push(decoder.read_var_bytes("witness item")?);
+                }
+            }
+            if inputs.iter().all(|input| input.witness.is_empty()) {
+                return Err(decoder.invalid("witness", "superfluous witness record"));
+            }
+        }
+        let locktime = decoder.read_u32("locktime")?;
+        Ok(Self {
+            version,
+            inputs,
+            outputs,
+            locktime,
+        })
+    }
+}
+
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct TransactionInput {
     pub previous_output: OutPoint,
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_utils::hex_bytes"))]
     pub script_sig: Vec<u8>,
     pub sequence: u32,
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_utils::hex_bytes_list"))]
+    pub witness: Vec<Vec<u8>>,
 }
 
 impl TransactionInput {
     pub fn deserialize(payload: &[u8]) -> Result<Self> {
-        if payload.len() < 36 {
-            return Err(BitcoinError::InvalidPayload(
-                "Insufficient bytes for transaction input".to_string(),
-            ));
-        }
-        let previous_output = OutPoint::deserialize(&payload[0..36])?;
-        let (script_sig_len, offset) = decode_varint(&payload[36..])?;
-        let offset = 36 + offset;
-        let script_sig = payload[offset..offset + script_sig_len as usize].to_vec();
-        let offset = offset + script_sig_len as usize;
-        let sequence = &payload[offset..offset + 4];
-        let sequence = u32::from_le_bytes(sequence.try_into().map_err(|_| {
-            BitcoinError::InvalidPayload("Invalid sequence in transaction input".to_string())
-        })?);
-        let sequence = sequence as u32;
-        Ok(Self {
-            previous_output,
-            script_sig,
-            sequence,
-        })
+        Self::consensus_decode(&mut Decoder::from_slice(payload))
     }
 
+    /// Size of the input in the legacy serialization, witness excluded.
     pub fn size(&self) -> usize {
-        36 + encode_varint(self.script_sig.len() as u64).len()
+        36 + varint_size(self.script_sig.len() as u64)
             + self.script_sig.len()
             + self.sequence.to_le_bytes().len()
     }
+
+    pub fn witness_size(&self) -> usize {
+        varint_size(self.witness.len() as u64)
+            + self
+                .witness
+                .iter()
+                .map(|item| varint_size(item.len() as u64) + item.len())
+                .sum::<usize>()
+    }
+}
+
+/// Encodes the input as it appears in the input list; the witness is written
+/// separately by the transaction.
+impl Encodable for TransactionInput {
+    fn consensus_encode<W: Write + ?Sized>(&self, writer: &mut W) -> io::Result<usize> {
+        let mut len = self.previous_output.consensus_encode(writer)?;
+        len += encode_var_bytes(&self.script_sig, writer)?;
+        writer.write_all(&self.sequence.to_le_bytes())?;
+        Ok(len + 4)
+    }
+
+    fn encoded_size(&self) -> usize {
+        self.size()
+    }
 }
+
+impl Decodable for TransactionInput {
+    fn consensus_decode<R: Read>(decoder: &mut Decoder<R>) -> Result<Self> {
+        let previous_output = OutPoint::consensus_decode(decoder)?;
+        let script_sig = decoder.read_var_bytes("script_sig")?;
+        let sequence = decoder.read_u32("sequence")?;
+        Ok(Self {
+            previous_output,
+            script_sig,
+            sequence,
+            witness: Vec::new(),
+        })
+    }
+}
+
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct TransactionOutput {
-    pub value: u64,
+    pub value: Amount,
+    #[cfg_attr(feature = "serde", serde(with = "crate::serde_utils::hex_bytes"))]
     pub script_pub_key: Vec<u8>,
 }
 
 impl TransactionOutput {
     pub fn deserialize(payload: &[u8]) -> Result<Self> {
-        if payload.len() < 8 {
-            return Err(BitcoinError::InvalidPayload(
-                "Insufficient bytes for transaction output".to_string(),
-            ));
-        }
-        let value = u64::from_le_bytes(payload[0..8].try_into().unwrap());
-        let (script_pub_key_len, offset) = decode_varint(&payload[8..])?;
-        let offset = offset + 8;
-        let script_pub_key = payload[offset..offset + script_pub_key_len as usize].to_vec();
+        Self::consensus_decode(&mut Decoder::from_slice(payload))
+    }
+
+    pub fn size(&self) -> usize {
+        8 + varint_size(self.script_pub_key.len() as u64) + self.script_pub_key.len()
+    }
+}
+
+impl Encodable for TransactionOutput {
+    fn consensus_encode<W: Write + ?Sized>(&self, writer: &mut W) -> io::Result<usize> {
+        writer.write_all(&self.value.to_sat().to_le_bytes())?;
+        Ok(8 + encode_var_bytes(&self.script_pub_key, writer)?)
+    }
+
+    fn encoded_size(&self) -> usize {
+        self.size()
+    }
+}
+
+impl Decodable for TransactionOutput {
+    fn consensus_decode<R: Read>(decoder: &mut Decoder<R>) -> Result<Self> {
+        let value = Amount::from_sat(decoder.read_u64("output value")?);
+        let script_pub_key = decoder.read_var_bytes("script_pub_key")?;
         Ok(Self {
             value,
             script_pub_key,
         })
     }
-
-    pub fn size(&self) -> usize {
-        self.value.to_le_bytes().len()
-            + encode_varint(self.script_pub_key.len() as u64).len()
-            + self.script_pub_key.len()
-    }
 }
+
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct OutPoint {
-    pub hash: [u8; 32],
+    pub hash: Txid,
     pub index: u32,
 }
 
 impl OutPoint {
     pub fn deserialize(payload: &[u8]) -> Result<Self> {
-        if payload.len() < 36 {
-            return Err(BitcoinError::InvalidPayload(
-                "Insufficient bytes for outpoint".to_string(),
-            ));
-        }
-        let hash = payload[0..32]
-            .try_into()
-            .map_err(|_| BitcoinError::InvalidPayload("Invalid outpoint hash".to_string()))?;
-        let index = u32::from_le_bytes(
-            payload[32..36]
-                .try_into()
-                .map_err(|_| BitcoinError::InvalidPayload("Invalid outpoint index".to_string()))?,
-        );
+        Self::consensus_decode(&mut Decoder::from_slice(payload))
+    }
+}
+
+impl Encodable for OutPoint {
+    fn consensus_encode<W: Write + ?Sized>(&self, writer: &mut W) -> io::Result<usize> {
+        writer.write_all(self.hash.as_byte_array())?;
+        writer.write_all(&self.index.to_le_bytes())?;
+        Ok(36)
+    }
+}
+
+impl Decodable for OutPoint {
+    fn consensus_decode<R: Read>(decoder: &mut Decoder<R>) -> Result<Self> {
+        let hash = Txid::from_byte_array(decoder.read_array("outpoint hash")?);
+        let index = decoder.read_u32("outpoint index")?;
         Ok(Self { hash, index })
     }
 }
@@ -184,14 +442,14 @@ mod tests {
 
         assert_eq!(transaction.inputs.len(), 1);
         let input = &transaction.inputs[0];
-        assert_eq!(input.previous_output.hash, [0; 32]);
+        assert_eq!(input.previous_output.hash, Txid::all_zeros());
         assert_eq!(input.previous_output.index, 0xFFFFFFFF);
         assert_eq!(input.script_sig, hex::decode("04ffff001d0102").unwrap());
         assert_eq!(input.sequence, 0xFFFFFFFF);
 
         assert_eq!(transaction.outputs.len(), 1);
         let output = &transaction.outputs[0];
-        assert_eq!(output.value, 5000000000);
+        assert_eq!(output.value, Amount::from_int_btc(50));
         assert_eq!(
              output.script_pub_key,
              hex::decode("4104d46c4968bde02899d2aa0963367c7a6ce34eec332b32e42e5f3407e052d64ac625da6f0718e7b302140434bd725706957c092db53805b821a85b23a7ac61725bac").unwrap()
@@ -210,4 +468,154 @@ mod tests {
 
         assert_eq!(transaction_hex_test, transaction_hex);
     }
+
+    #[test]
+    fn test_segwit_transaction() {
+        let transaction_hex_test = "02000000000101595895ea20179de87052b4046dfe6fd515860505d6511a9004cf12a1f93cac7c0100000000ffffffff01deb807000000000017a9140f3444e271620c736808aa7b33e370bd87cb5a078702483045022100fb60dad8df4af2841adc0346638c16d0b8035f5e3f3753b88db122e70c79f9370220756e6633b17fd2710e626347d28d60b0a2d6cbb41de51740644b9fb3ba7751040121028fa937ca8cba2197a37c007176ed8941055d3bcb8627d085e94553e62f057dcc00000000";
+        let payload_transaction = hex::decode(transaction_hex_test).unwrap();
+        let transaction = Transaction::deserialize(&payload_transaction).unwrap();
+
+        assert_eq!(transaction.version, 2);
+        assert_eq!(transaction.inputs.len(), 1);
+        assert_eq!(transaction.outputs.len(), 1);
+        assert!(transaction.has_witness());
+
+        let witness = &transaction.inputs[0].witness;
+        assert_eq!(witness.len(), 2);
+        assert_eq!(witness[0].len(), 72);
+        assert_eq!(
+            witness[1],
+            hex::decode("028fa937ca8cba2197a37c007176ed8941055d3bcb8627d085e94553e62f057dcc")
+                .unwrap()
+        );
+
+        assert_eq!(hex::encode(transaction.serialize()), transaction_hex_test);
+        assert_eq!(transaction.size(), payload_transaction.len());
+
+        assert_eq!(
+            transaction.txid().to_string(),
+            "f5864806e3565c34d1b41e716f72609d00b55ea5eac5b924c9719a842ef42206"
+        );
+        assert_eq!(
+            transaction.wtxid().to_string(),
+            "80b7d8a82d5d5bf92905b06f2014dd699e03837ca172e3a59d51426ebbe3e7f5"
+        );
+
+        assert_eq!(transaction.total_size(), payload_transaction.len());
+        assert_eq!(
+            transaction.stripped_size(),
+            transaction.serialize_without_witness().len()
+        );
+        assert_eq!(transaction.weight(), 442);
+        assert_eq!(transaction.vsize(), 111);
+
+        let mut stripped = transaction.clone();
+        stripped.inputs[0].witness.clear();
+        assert_eq!(
+            stripped.serialize(),
+            transaction.serialize_without_witness()
+        );
+        assert_eq!(
+            stripped.wtxid().to_byte_array(),
+            transaction.txid().to_byte_array()
+        );
+        assert_eq!(stripped.weight(), transaction.stripped_size() * 4);
+    }
+
+    #[test]
+    fn test_legacy_transaction_wtxid_equals_txid() {
+        let payload_transaction = hex::decode(crate::TRANSACTION_SERIALIZED).unwrap();
+        let transaction = Transaction::deserialize(&payload_transaction).unwrap();
+
+        assert!(!transaction.has_witness());
+        assert_eq!(
+            transaction.txid().to_byte_array(),
+            transaction.wtxid().to_byte_array()
+        );
+        assert_eq!(transaction.serialize(), payload_transaction);
+        assert_eq!(transaction.stripped_size(), payload_transaction.len());
+        assert_eq!(transaction.total_size(), payload_transaction.len());
+        assert_eq!(transaction.weight(), payload_transaction.len() * 4);
+        assert_eq!(transaction.vsize(), payload_transaction.len());
+    }
+
+    #[test]
+    fn test_total_output_value() {
+        let payload_transaction = hex::decode(crate::TRANSACTION_SERIALIZED).unwrap();
+        let mut transaction = Transaction::deserialize(&payload_transaction).unwrap();
+        assert_eq!(
+            transaction.total_output_value().unwrap(),
+            Amount::from_int_btc(50)
+        );
+
+        // Each output is in range but the sum is not
+        transaction.outputs[0].value = Amount::MAX_MONEY;
+        assert!(matches!(
+            transaction.total_output_value(),
+            Err(BitcoinError::InvalidAmount(_))
+        ));
+
+        // The 2010 value overflow: huge outputs whose u64 sum wraps around
+        transaction.outputs[0].value = Amount::from_sat(0x8000_0000_0000_0000);
+        transaction.outputs[1].value = Amount::from_sat(0x8000_0000_0000_0000);
+        assert!(transaction.total_output_value().is_err());
+    }
+
+    #[test]
+    fn test_superfluous_witness_record() {
+        // Marker and flag present but every witness stack is empty
+        let payload_transaction = hex::decode("0100000000010100000000000000000000000000000000000000000000000000000000000000000000000000ffffffff0100f2052a0100000001510000000000").unwrap();
+        assert!(Transaction::deserialize(&payload_transaction).is_err());
+    }
+
+    #[test]
+    fn test_coinbase() {
+        let outputs = vec![TransactionOutput {
+            value: Amount::from_int_btc(50),
+            script_pub_key: vec![0x51],
+        }];
+        let coinbase = Transaction::coinbase(170, 0, b"erickcestari", outputs.clone()).unwrap();
+        assert!(coinbase.is_coinbase());
+        assert_eq!(
+            hex::encode(&coinbase.inputs[0].script_sig),
+            format!("02aa00000c{}", hex::encode("erickcestari"))
+        );
+        assert_eq!(coinbase.bip34_height(), Some(170));
+        assert_eq!(coinbase.version, 1);
+        assert_eq!(coinbase.inputs[0].sequence, u32::MAX);
+
+        for height in [0, 1, 16, 17, 128, 227931, 840000, u32::MAX] {
+            let coinbase = Transaction::coinbase(height, 7, b"", outputs.clone()).unwrap();
+            assert_eq!(coinbase.bip34_height(), Some(height), "{}", height);
+        }
+
+        assert!(Transaction::coinbase(170, 0, &[0xaa; 94], outputs.clone()).is_ok());
+        assert!(matches!(
+            Transaction::coinbase(170, 0, &[0xaa; 95], outputs.clone()),
+            Err(BitcoinError::InvalidScript(_))
+        ));
+        let coinbase = Transaction::coinbase(0, 0, &[], outputs).unwrap();
+        assert_eq!(coinbase.inputs[0].script_sig, [0x00, 0x00]);
+    }
+
+    #[test]
+    fn test_bip34_height() {
+        // Height 227931 as the first BIP34 block has it
+        let mut coinbase = Transaction::deserialize(&hex::decode("01000000010000000000000000000000000000000000000000000000000000000000000000ffffffff0704ffff001d0102ffffffff0100f2052a01000000434104d46c4968bde02899d2aa0963367c7a6ce34eec332b32e42e5f3407e052d64ac625da6f0718e7b302140434bd725706957c092db53805b821a85b23a7ac61725bac00000000").unwrap()).unwrap();
+        coinbase.inputs[0].script_sig = hex::decode("035b7a03").unwrap();
+        assert_eq!(coinbase.bip34_height(), Some(227931));
+
+        // Not minimally encoded
+        coinbase.inputs[0].script_sig = hex::decode("045b7a0300").unwrap();
+        assert_eq!(coinbase.bip34_height(), None);
+        coinbase.inputs[0].script_sig = vec![0x01, 0x05];
+        assert_eq!(coinbase.bip34_height(), None);
+        coinbase.inputs[0].script_sig = vec![0x4c];
+        assert_eq!(coinbase.bip34_height(), None);
+
+        let transaction =
+            Transaction::deserialize(&hex::decode(crate::TRANSACTION_SERIALIZED).unwrap()).unwrap();
+        assert!(!transaction.is_coinbase());
+        assert_eq!(transaction.bip34_height(), None);
+    }
 }
@@ -0,0 +1,326 @@
+use std::{
+    cmp::Ordering,
+    fmt,
+    ops::{Add, Not, Shl, Shr, Sub},
+};
+
+/// Unsigned 256-bit integer, stored as four little-endian 64-bit limbs.
+/// Arithmetic operators panic on overflow like the primitive integers in debug
+/// builds; use the `overflowing_*` methods when wrapping is expected.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct U256([u64; 4]);
+
+impl U256 {
+    pub const ZERO: Self = Self([0; 4]);
+    pub const ONE: Self = Self([1, 0, 0, 0]);
+    pub const MAX: Self = Self([u64::MAX; 4]);
+
+    pub const fn from_u64(value: u64) -> Self {
+        Self([value, 0, 0, 0])
+    }
+
+    /// Least significant limb first.
+    pub const fn from_limbs(limbs: [u64; 4]) -> Self {
+        Self(limbs)
+    }
+
+    pub fn from_be_bytes(bytes: [u8; 32]) -> Self {
+        let mut limbs = [0u64; 4];
+        for (i, chunk) in bytes.chunks_exact(8).enumerate() {
+            limbs[3 - i] = u64::from_be_bytes(chunk.try_into().unwrap());
+        }
+        Self(limbs)
+    }
+
+    /// Hashes are little-endian numbers, so this is how they compare to a target.
+    pub fn from_le_bytes(mut bytes: [u8; 32]) -> Self {
+        bytes.reverse();
+        Self::from_be_bytes(bytes)
+    }
+
+    pub fn to_be_bytes(self) -> [u8; 32] {
+        let mut bytes = [0u8; 32];
+        for (i, chunk) in bytes.chunks_exact_mut(8).enumerate() {
+            chunk.copy_from_slice(&self.0[3 - i].to_be_bytes());
+        }
+        bytes
+    }
+
+    pub fn to_le_bytes(self) -> [u8; 32] {
+        let mut bytes = self.to_be_bytes();
+        bytes.reverse();
+        bytes
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.0 == [0; 4]
+    }
+
+    pub fn low_u64(&self) -> u64 {
+        self.0[0]
+    }
+
+    /// Number of significant bits, zero for zero.
+    pub fn bits(&self) -> u32 {
+        for i in (0..4).rev() {
+            if self.0[i] != 0 {
+                return 64 * i as u32 + 64 - self.0[i].leading_zeros();
+            }
+        }
+        0
+    }
+
+    pub fn bit(&self, index: u32) -> bool {
+        index < 256 && (self.0[index as usize / 64] >> (index % 64)) & 1 == 1
+    }
+
+    pub fn overflowing_add(self, rhs: Self) -> (Self, bool) {
+        let mut limbs = [0u64; 4];
+        let mut carry = false;
+        for (i, limb) in limbs.iter_mut().enumerate() {
+            let (sum, carry_a) = self.0[i].overflowing_add(rhs.0[i]);
+            let (sum, carry_b) = sum.overflowing_add(carry as u64);
+            *limb = sum;
+            carry = carry_a || carry_b;
+        }
+        (Self(limbs), carry)
+    }
+
+    pub fn overflowing_sub(self, rhs: Self) -> (Self, bool) {
+        let mut limbs = [0u64; 4];
+        let mut borrow = false;
+        for (i, limb) in limbs.iter_mut().enumerate() {
+            let (diff, borrow_a) = self.0[i].overflowing_sub(rhs.0[i]);
+            let (diff, borrow_b) = diff.overflowing_sub(borrow as u64);
+            *limb = diff;
+            borrow = borrow_a || borrow_b;
+        }
+        (Self(limbs), borrow)
+    }
+
+    pub fn checked_add(self, rhs: Self) -> Option<Self> {
+        match self.overflowing_add(rhs) {
+            (sum, false) => Some(sum),
+            _ => None,
+        }
+    }
+
+    pub fn checked_sub(self, rhs: Self) -> Option<Self> {
+        match self.overflowing_sub(rhs) {
+            (diff, false) => Some(diff),
+            _ => None,
+        }
+    }
+
+    /// Full 512-bit product as `(low, high)` halves.
+    pub fn widening_mul(self, rhs: Self) -> (Self, Self) {
+        let mut product = [0u64; 8];
+        for i in 0..4 {
+            let mut carry = 0u128;
+            for j in 0..4 {
+                let value = self.0[i] as u128 * rhs.0[j] as u128 + product[i + j] as u128 + carry;
+                product[i + j] = value as u64;
+                carry = value >> 64;
+            }
+            product[i + 4] = carry as u64;
+        }
+        (
+            Self(product[..4].try_into().unwrap()),
+            Self(product[4..].try_into().unwrap()),
+        )
+    }
+
+    /// Quotient and remainder. Panics when dividing by zero.
+    pub fn div_rem(self, divisor: Self) -> (Self, Self) {
+        assert!(!divisor.is_zero(), "attempt to divide by zero");
+        if self < divisor {
+            return (Self::ZERO, self);
+        }
+        let mut quotient = Self::ZERO;
+        let mut remainder = Self::ZERO;
+        for i in (0..self.bits()).rev() {
+            remainder = remainder << 1;
+            if self.bit(i) {
+                remainder.0[0] |= 1;
+            }
+            if remainder >= divisor {
+                remainder = remainder - divisor;
+                quotient.0[i as usize / 64] |= 1 << (i % 64);
+            }
+        }
+        (quotient, remainder)
+    }
+}
+
+impl From<u64> for U256 {
+    fn from(value: u64) -> Self {
+        Self::from_u64(value)
+    }
+}
+
+impl Ord for U256 {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.0.iter().rev().cmp(other.0.iter().rev())
+    }
+}
+
+impl PartialOrd for U256 {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Add for U256 {
+    type Output = Self;
+
+    fn add(self, rhs: Self) -> Self {
+        self.checked_add(rhs).expect("attempt to add with overflow")
+    }
+}
+
+impl Sub for U256 {
+    type Output = Self;
+
+    fn sub(self, rhs: Self) -> Self {
+        self.checked_sub(rhs)
+            .expect("attempt to subtract with overflow")
+    }
+}
+
+impl Not for U256 {
+    type Output = Self;
+
+    fn not(self) -> Self {
+        Self(self.0.map(|limb| !limb))
+    }
+}
+
+/// Bits shifted past either end are dropped; shifting by 256 or more gives zero.
+impl Shl<u32> for U256 {
+    type Output = Self;
+
+    fn shl(self, shift: u32) -> Self {
+        let mut limbs = [0u64; 4];
+        let (limb_shift, bit_shift) = ((shift / 64) as usize, shift % 64);
+        for (i, limb) in limbs.iter_mut().enumerate().skip(limb_shift) {
+            *limb = self.0[i - limb_shift] << bit_shift;
+            if bit_shift > 0 && i > limb_shift {
+                *limb |= self.0[i - limb_shift - 1] >> (64 - bit_shift);
+            }
+        }
+        Self(limbs)
+    }
+}
+
+impl Shr<u32> for U256 {
+    type Output = Self;
+
+    fn shr(self, shift: u32) -> Self {
+        let mut limbs = [0u64; 4];
+        let (limb_shift, bit_shift) = ((shift / 64) as usize, shift % 64);
+        for (i, limb) in limbs
+            .iter_mut()
+            .enumerate()
+            .take(4usize.saturating_sub(limb_shift))
+        {
+            *limb = self.0[i + limb_shift] >> bit_shift;
+            if bit_shift > 0 && i + limb_shift + 1 < 4 {
+                *limb |= self.0[i + limb_shift + 1] << (64 - bit_shift);
+            }
+        }
+        Self(limbs)
+    }
+}
+
+impl fmt::LowerHex for U256 {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let hex = hex::encode(self.to_be_bytes());
+        let digits = hex.trim_start_matches('0');
+        let digits = if digits.is_empty() { "0" } else { digits };
+        f.pad_integral(true, "0x", digits)
+    }
+}
+
+impl fmt::Debug for U256 {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "U256({:#066x})", self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn from_hex(s: &str) -> U256 {
+        let mut bytes = [0u8; 32];
+        let decoded = hex::decode(format!("{:0>64}", s)).unwrap();
+        bytes.copy_from_slice(&decoded);
+        U256::from_be_bytes(bytes)
+    }
+
+    #[test]
+    fn test_byte_order() {
+        let value = from_hex("0102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f20");
+        assert_eq!(value.to_be_bytes()[0], 0x01);
+        assert_eq!(value.to_le_bytes()[0], 0x20);
+        assert_eq!(U256::from_le_bytes(value.to_le_bytes()), value);
+        assert_eq!(value.low_u64(), 0x191a1b1c1d1e1f20);
+        assert_eq!(
+            format!("{:x}", value),
+            "102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f20"
+        );
+        assert_eq!(format!("{:#x}", U256::ZERO), "0x0");
+        assert_eq!(U256::from(0x1234u64).bits(), 13);
+        assert_eq!(U256::MAX.bits(), 256);
+        assert!(U256::from_u64(1) < from_hex("100000000000000000000000000000000"));
+    }
+
+    #[test]
+    fn test_add_sub_overflow() {
+        let a = from_hex("ffffffffffffffffffffffffffffffff");
+        assert_eq!(a + U256::ONE, from_hex("100000000000000000000000000000000"));
+        assert_eq!(from_hex("100000000000000000000000000000000") - U256::ONE, a);
+        assert_eq!(U256::MAX.overflowing_add(U256::ONE), (U256::ZERO, true));
+        assert_eq!(U256::ZERO.overflowing_sub(U256::ONE), (U256::MAX, true));
+        assert_eq!(U256::ZERO.checked_sub(U256::ONE), None);
+        assert_eq!(!U256::ZERO, U256::MAX);
+    }
+
+    #[test]
+    fn test_shifts() {
+        let one = U256::ONE;
+        assert_eq!(
+            one << 255,
+            from_hex("8000000000000000000000000000000000000000000000000000000000000000")
+        );
+        assert_eq!((one << 255) >> 255, one);
+        assert_eq!(one << 256, U256::ZERO);
+        assert_eq!(U256::MAX >> 300, U256::ZERO);
+        assert_eq!(from_hex("ffff") << 60, from_hex("ffff000000000000000"));
+        assert_eq!(from_hex("ffff000000000000000") >> 60, from_hex("ffff"));
+        assert_eq!(U256::MAX << 64 >> 64, U256::MAX >> 64);
+    }
+
+    #[test]
+    fn test_mul_div() {
+        let a = from_hex("fedcba9876543210fedcba9876543210");
+        let b = from_hex("123456789abcdef0123456789");
+        let (low, high) = a.widening_mul(b);
+        assert!(high.is_zero());
+        assert_eq!(low.div_rem(b), (a, U256::ZERO));
+        assert_eq!((low + U256::from(5)).div_rem(a), (b, U256::from(5)));
+
+        let (low, high) = U256::MAX.widening_mul(U256::MAX);
+        assert_eq!(low, U256::ONE);
+        assert_eq!(high, U256::MAX - U256::ONE);
+
+        assert_eq!(
+            U256::from(7).div_rem(U256::from(9)),
+            (U256::ZERO, U256::from(7))
+        );
+        assert_eq!(
+            U256::MAX.div_rem(U256::from(0x100000000)),
+            (U256::MAX >> 32, U256::from(0xffffffff))
+        );
+    }
+}
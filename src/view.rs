@@ -0,0 +1,511 @@
+use sha2::{Digest, Sha256};
+
+use crate::transaction::{MIN_INPUT_SIZE, MIN_OUTPUT_SIZE, MIN_TRANSACTION_SIZE};
+use crate::{
+    decoder::Decoder, encode::Decodable, Amount, BitcoinError, Block, BlockHeader, OutPoint,
+    Result, Transaction, TransactionInput, TransactionOutput, Txid, Wtxid,
+};
+
+/// Slice cursor handing out borrowed sub-slices of the payload.
+#[derive(Debug, Clone)]
+struct Cursor<'a> {
+    payload: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> Cursor<'a> {
+    fn new(payload: &'a [u8], offset: usize) -> Self {
+        Self { payload, offset }
+    }
+
+    fn read_bytes(&mut self, len: usize, field: &'static str) -> Result<&'a [u8]> {
+        let bytes = self
+            .offset
+            .checked_add(len)
+            .and_then(|end| self.payload.get(self.offset..end))
+            .ok_or(BitcoinError::UnexpectedEof {
+                field,
+                offset: self.offset,
+            })?;
+        self.offset += len;
+        Ok(bytes)
+    }
+
+    fn read_u32(&mut self, field: &'static str) -> Result<u32> {
+        Ok(u32::from_le_bytes(
+            self.read_bytes(4, field)?.try_into().unwrap(),
+        ))
+    }
+
+    fn read_u64(&mut self, field: &'static str) -> Result<u64> {
+        Ok(u64::from_le_bytes(
+            self.read_bytes(8, field)?.try_into().unwrap(),
+        ))
+    }
+
+    fn decoder(&self) -> Decoder<&'a [u8]> {
+        let rest = &self.payload[self.offset..];
+        Decoder::with_offset(rest, self.offset).with_limit(rest.len())
+    }
+
+    fn read_varint(&mut self, field: &'static str) -> Result<u64> {
+        let mut decoder = self.decoder();
+        let value = decoder.read_varint(field)?;
+        self.offset = decoder.offset();
+        Ok(value)
+    }
+
+    fn read_count(&mut self, field: &'static str, min_item_size: usize) -> Result<usize> {
+        let mut decoder = self.decoder();
+        let count = decoder.read_count(field, min_item_size)?;
+        self.offset = decoder.offset();
+        Ok(count)
+    }
+
+    fn read_var_bytes(&mut self, field: &'static str) -> Result<&'a [u8]> {
+        let len = self.read_varint(field)?;
+        let len = usize::try_from(len).map_err(|_| BitcoinError::InvalidField {
+            field,
+            offset: self.offset,
+            reason: format!("length {} does not fit in memory", len),
+        })?;
+        self.read_bytes(len, field)
+    }
+}
+
+/// Borrowed view over a serialized block. The framing of every transaction is
+/// checked once on construction; afterwards inputs, outputs and scripts are
+/// handed out as slices of the original buffer.
+#[derive(Debug, Clone)]
+pub struct BlockView<'a> {
+    payload: &'a [u8],
+    transactions: Vec<TransactionView<'a>>,
+}
+
+impl<'a> BlockView<'a> {
+    pub fn new(payload: &'a [u8]) -> Result<Self> {
+        let mut cursor = Cursor::new(payload, 0);
+        cursor.read_bytes(80, "block header")?;
+        let transaction_count = cursor.read_count("transaction count", MIN_TRANSACTION_SIZE)?;
+        let mut transactions = Vec::new();
+        for _ in 0..transaction_count {
+            transactions.push(TransactionView::parse(&mut cursor)?);
+        }
+        Ok(Self {
+            payload: &payload[..cursor.offset],
+            transactions,
+        })
+    }
+
+    pub fn as_bytes(&self) -> &'a [u8] {
+        self.payload
+    }
+
+    pub fn header_bytes(&self) -> &'a [u8] {
+        &self.payload[..80]
+    }
+
+    pub fn header(&self) -> BlockHeader {
+        BlockHeader::consensus_decode(&mut Decoder::from_slice(self.header_bytes()))
+            .expect("header length checked on construction")
+    }
+
+    pub fn transaction_count(&self) -> usize {
+        self.transactions.len()
+    }
+
+    pub fn transactions(&self) -> std::slice::Iter<'_, TransactionView<'a>> {
+        self.transactions.iter()
+    }
+
+    pub fn to_owned(&self) -> Block {
+        Block {
+            block_header: self.header(),
+            transactions: self.transactions.iter().map(|tx| tx.to_owned()).collect(),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct TransactionView<'a> {
+    payload: &'a [u8],
+    version: u32,
+    locktime: u32,
+    input_count: usize,
+    output_count: usize,
+    // Offsets into `payload` where each section starts
+    inputs_start: usize,
+    outputs_start: usize,
+    witness_start: Option<usize>,
+}
+
+impl<'a> TransactionView<'a> {
+    pub fn new(payload: &'a [u8]) -> Result<Self> {
+        Self::parse(&mut Cursor::new(payload, 0))
+    }
+
+    fn parse(cursor: &mut Cursor<'a>) -> Result<Self> {
+        let start = cursor.offset;
+        let version = cursor.read_u32("transaction version")?;
+        let mut input_count = cursor.read_count("input count", MIN_INPUT_SIZE)?;
+        let mut inputs_start = cursor.offset;
+        let mut has_witness = false;
+        let mut output_count = None;
+        if input_count == 0 {
+            match cursor.read_bytes(1, "segwit flag")?[0] {
+                0x00 => output_count = Some(0),
+                0x01 => {
+                    has_witness = true;
+                    input_count = cursor.read_count("input count", MIN_INPUT_SIZE)?;
+                    inputs_start = cursor.offset;
+                }
+                _ => {
+                    return Err(BitcoinError::InvalidField {
+                        field: "segwit flag",
+                        offset: cursor.offset - 1,
+                        reason: "unknown serialization flag".to_string(),
+                    })
+                }
+            }
+        }
+        for _ in 0..input_count {
+            InputView::parse(cursor)?;
+        }
+        let output_count = match output_count {
+            Some(output_count) => output_count,
+            None => cursor.read_count("output count", MIN_OUTPUT_SIZE)?,
+        };
+        let outputs_start = cursor.offset;
+        for _ in 0..output_count {
+            OutputView::parse(cursor)?;
+        }
+        let mut witness_start = None;
+        if has_witness {
+            witness_start = Some(cursor.offset);
+            let mut empty = true;
+            for _ in 0..input_count {
+                empty &= WitnessView::parse(cursor)?.is_empty();
+            }
+            if empty {
+                return Err(BitcoinError::InvalidField {
+                    field: "witness",
+                    offset: cursor.offset,
+                    reason: "superfluous witness record".to_string(),
+                });
+            }
+        }
+        let locktime = cursor.read_u32("locktime")?;
+        let payload = &cursor.payload[start..cursor.offset];
+        Ok(Self {
+            payload,
+            version,
+            locktime,
+            input_count,
+            output_count,
+            inputs_start: inputs_start - start,
+            outputs_start: outputs_start - start,
+            witness_start: witness_start.map(|offset| offset - start),
+        })
+    }
+
+    pub fn as_bytes(&self) -> &'a [u8] {
+        self.payload
+    }
+
+    pub fn version(&self) -> u32 {
+        self.version
+    }
+
+    pub fn locktime(&self) -> u32 {
+        self.locktime
+    }
+
+    pub fn has_witness(&self) -> bool {
+        self.witness_start.is_some()
+    }
+
+    pub fn input_count(&self) -> usize {
+        self.input_count
+    }
+
+    pub fn output_count(&self) -> usize {
+        self.output_count
+    }
+
+    pub fn inputs(&self) -> Inputs<'a> {
+        Inputs {
+            cursor: Cursor::new(self.payload, self.inputs_start),
+            witness: self
+                .witness_start
+                .map(|offset| Cursor::new(self.payload, offset)),
+            remaining: self.input_count,
+        }
+    }
+
+    pub fn outputs(&self) -> Outputs<'a> {
+        Outputs {
+            cursor: Cursor::new(self.payload, self.outputs_start),
+            remaining: self.output_count,
+        }
+    }
+
+    pub fn txid(&self) -> Txid {
+        let mut hasher = Sha256::new();
+        match self.witness_start {
+            Some(witness_start) => {
+                // Version, then everything from the input count up to the witnesses, then locktime
+                hasher.update(&self.payload[..4]);
+                hasher.update(&self.payload[6..witness_start]);
+                hasher.update(&self.payload[self.payload.len() - 4..]);
+            }
+            None => hasher.update(self.payload),
+        }
+        Txid::from_byte_array(Sha256::digest(hasher.finalize()).into())
+    }
+
+    pub fn wtxid(&self) -> Wtxid {
+        Wtxid::from_byte_array(Sha256::digest(Sha256::digest(self.payload)).into())
+    }
+
+    pub fn to_owned(&self) -> Transaction {
+        Transaction {
+            version: self.version,
+            inputs: self.inputs().map(|input| input.to_owned()).collect(),
+            outputs: self.outputs().map(|output| output.to_owned()).collect(),
+            locktime: self.locktime,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct InputView<'a> {
+    pub previous_output_hash: &'a [u8; 32],
+    pub previous_output_index: u32,
+    pub script_sig: &'a [u8],
+    pub sequence: u32,
+    pub witness: WitnessView<'a>,
+}
+
+impl<'a> InputView<'a> {
+    fn parse(cursor: &mut Cursor<'a>) -> Result<Self> {
+        let previous_output_hash = cursor.read_bytes(32, "outpoint hash")?.try_into().unwrap();
+        let previous_output_index = cursor.read_u32("outpoint index")?;
+        let script_sig = cursor.read_var_bytes("script_sig")?;
+        let sequence = cursor.read_u32("sequence")?;
+        Ok(Self {
+            previous_output_hash,
+            previous_output_index,
+            script_sig,
+            sequence,
+            witness: WitnessView::default(),
+        })
+    }
+
+    pub fn to_owned(&self) -> TransactionInput {
+        TransactionInput {
+            previous_output: OutPoint {
+                hash: Txid::from_byte_array(*self.previous_output_hash),
+                index: self.previous_output_index,
+            },
+            script_sig: self.script_sig.to_vec(),
+            sequence: self.sequence,
+            witness: self.witness.iter().map(|item| item.to_vec()).collect(),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct OutputView<'a> {
+    pub value: Amount,
+    pub script_pub_key: &'a [u8],
+}
+
+impl<'a> OutputView<'a> {
+    fn parse(cursor: &mut Cursor<'a>) -> Result<Self> {
+        let value = Amount::from_sat(cursor.read_u64("output value")?);
+        let script_pub_key = cursor.read_var_bytes("script_pub_key")?;
+        Ok(Self {
+            value,
+            script_pub_key,
+        })
+    }
+
+    pub fn to_owned(&self) -> TransactionOutput {
+        TransactionOutput {
+            value: self.value,
+            script_pub_key: self.script_pub_key.to_vec(),
+        }
+    }
+}
+
+/// Witness stack of one input: the serialized items without the leading count.
+#[derive(Debug, Clone, Default)]
+pub struct WitnessView<'a> {
+    items: &'a [u8],
+    len: usize,
+}
+
+impl<'a> WitnessView<'a> {
+    fn parse(cursor: &mut Cursor<'a>) -> Result<Self> {
+        let len = cursor.read_count("witness item count", 1)?;
+        let start = cursor.offset;
+        for _ in 0..len {
+            cursor.read_var_bytes("witness item")?;
+        }
+        Ok(Self {
+            items: &cursor.payload[start..cursor.offset],
+            len,
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &'a [u8]> {
+        let mut cursor = Cursor::new(self.items, 0);
+        (0..self.len).map_while(move |_| cursor.read_var_bytes("witness item").ok())
+    }
+}
+
+// The iterators below walk framing that was validated when the view was built,
+// so the reads cannot fail.
+#[derive(Debug, Clone)]
+pub struct Inputs<'a> {
+    cursor: Cursor<'a>,
+    witness: Option<Cursor<'a>>,
+    remaining: usize,
+}
+
+impl<'a> Iterator for Inputs<'a> {
+    type Item = InputView<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.remaining == 0 {
+            return None;
+        }
+        self.remaining -= 1;
+        let mut input = InputView::parse(&mut self.cursor).ok()?;
+        if let Some(witness) = self.witness.as_mut() {
+            input.witness = WitnessView::parse(witness).ok()?;
+        }
+        Some(input)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl ExactSizeIterator for Inputs<'_> {}
+
+#[derive(Debug, Clone)]
+pub struct Outputs<'a> {
+    cursor: Cursor<'a>,
+    remaining: usize,
+}
+
+impl<'a> Iterator for Outputs<'a> {
+    type Item = OutputView<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.remaining == 0 {
+            return None;
+        }
+        self.remaining -= 1;
+        OutputView::parse(&mut self.cursor).ok()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl ExactSizeIterator for Outputs<'_> {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BLOCK_1: &str = "010000006fe28c0ab6f1b372c1a6a246ae63f74f931e8365e15a089c68d6190000000000982051fd1e4ba744bbbe680e1fee14677ba1a3c3540bf7b1cdb606e857233e0e61bc6649ffff001d01e362990101000000010000000000000000000000000000000000000000000000000000000000000000ffffffff0704ffff001d0104ffffffff0100f2052a0100000043410496b538e853519c726a2c91e61ec11600ae1390813a627c66fb8be7947be63c52da7589379515d4e0a604f8141781e62294721166bf621e73a82cbf2342c858eeac00000000";
+    const SEGWIT_TRANSACTION: &str = "02000000000101595895ea20179de87052b4046dfe6fd515860505d6511a9004cf12a1f93cac7c0100000000ffffffff01deb807000000000017a9140f3444e271620c736808aa7b33e370bd87cb5a078702483045022100fb60dad8df4af2841adc0346638c16d0b8035f5e3f3753b88db122e70c79f9370220756e6633b17fd2710e626347d28d60b0a2d6cbb41de51740644b9fb3ba7751040121028fa937ca8cba2197a37c007176ed8941055d3bcb8627d085e94553e62f057dcc00000000";
+
+    fn points_into(slice: &[u8], payload: &[u8]) -> bool {
+        payload.as_ptr_range().contains(&slice.as_ptr())
+    }
+
+    #[test]
+    fn test_block_view() {
+        let payload = hex::decode(BLOCK_1).unwrap();
+        let view = BlockView::new(&payload).unwrap();
+        let block = Block::deserialize(&payload).unwrap();
+
+        assert_eq!(view.as_bytes(), payload);
+        assert_eq!(view.header().nonce, block.block_header.nonce);
+        assert_eq!(view.transaction_count(), 1);
+
+        let transaction = view.transactions().next().unwrap();
+        assert_eq!(transaction.txid(), block.transactions[0].txid());
+        assert_eq!(transaction.as_bytes(), &payload[81..]);
+
+        let output = transaction.outputs().next().unwrap();
+        assert_eq!(output.value, Amount::from_int_btc(50));
+        assert_eq!(
+            output.script_pub_key,
+            block.transactions[0].outputs[0].script_pub_key
+        );
+        assert!(points_into(output.script_pub_key, &payload));
+
+        let input = transaction.inputs().next().unwrap();
+        assert_eq!(input.script_sig, hex::decode("04ffff001d0104").unwrap());
+        assert!(points_into(input.script_sig, &payload));
+        assert!(input.witness.is_empty());
+
+        assert_eq!(view.to_owned().serialize(), payload);
+    }
+
+    #[test]
+    fn test_segwit_transaction_view() {
+        let payload = hex::decode(SEGWIT_TRANSACTION).unwrap();
+        let view = TransactionView::new(&payload).unwrap();
+        let transaction = Transaction::deserialize(&payload).unwrap();
+
+        assert!(view.has_witness());
+        assert_eq!(view.version(), 2);
+        assert_eq!(view.input_count(), 1);
+        assert_eq!(view.output_count(), 1);
+        assert_eq!(view.txid(), transaction.txid());
+        assert_eq!(view.wtxid(), transaction.wtxid());
+
+        let input = view.inputs().next().unwrap();
+        let witness: Vec<&[u8]> = input.witness.iter().collect();
+        assert_eq!(witness.len(), 2);
+        assert_eq!(witness, transaction.inputs[0].witness);
+        assert!(points_into(witness[1], &payload));
+
+        assert_eq!(view.to_owned().serialize(), payload);
+    }
+
+    #[test]
+    fn test_view_rejects_truncated_payloads() {
+        let payload = hex::decode(BLOCK_1).unwrap();
+        for len in 0..payload.len() {
+            assert!(BlockView::new(&payload[..len]).is_err());
+        }
+        let payload = hex::decode(SEGWIT_TRANSACTION).unwrap();
+        for len in 0..payload.len() {
+            assert!(TransactionView::new(&payload[..len]).is_err());
+        }
+        match TransactionView::new(&payload[..payload.len() - 1]) {
+            Err(BitcoinError::UnexpectedEof { field, offset }) => {
+                assert_eq!(field, "locktime");
+                assert_eq!(offset, payload.len() - 4);
+            }
+            other => panic!("unexpected result {:?}", other),
+        }
+    }
+}